@@ -0,0 +1,89 @@
+use {
+  criterion::{criterion_group, criterion_main, BenchmarkId, Criterion},
+  levenshtein::levenshtein,
+  std::hint::black_box,
+  zenless_disk_ocr::source::vocabulary::Vocabulary
+};
+
+/// Generates `count` pseudo-random words of 2 to 8 characters drawn from the
+/// characters of the bundled vocabulary, so that distances look like real
+/// OCR input.
+fn generate_words(count: usize) -> Vec<String> {
+  let bundled = Vocabulary::bundled();
+  let mut alphabet: Vec<char> = bundled.words().flat_map(str::chars).collect();
+  alphabet.sort();
+  alphabet.dedup();
+
+  let mut state: u64 = 0x2545f4914f6cdd1d;
+  let mut next = move || {
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    state as usize
+  };
+
+  let mut words: Vec<String> = bundled.words().map(str::to_string).collect();
+  while words.len() < count {
+    let length = 2 + next() % 7;
+    words.push((0 .. length).map(|_| alphabet[next() % alphabet.len()]).collect());
+  }
+  words.truncate(count);
+  words
+}
+
+fn linear_best<'a>(words: &'a [String], word: &str) -> Option<(&'a str, usize)> {
+  words.iter().map(|w| (w.as_str(), levenshtein(word, w))).min_by_key(|&(_, d)| d)
+}
+
+fn linear_within<'a>(words: &'a [String], word: &str, k: usize) -> Vec<(&'a str, usize)> {
+  words
+    .iter()
+    .map(|w| (w.as_str(), levenshtein(word, w)))
+    .filter(|&(_, d)| d <= k)
+    .collect()
+}
+
+fn fuzzy_lookup(c: &mut Criterion) {
+  let queries = ["冰属性伤害加戒", "暴击伤吉", "主属佳", "混沌重金禹", "能量自动回夏"];
+
+  for size in [34, 1_000, 10_000] {
+    let words = generate_words(size);
+    let vocabulary = Vocabulary::new(words.iter().map(String::as_str));
+
+    let mut group = c.benchmark_group(format!("fuzzy_lookup/{}", size));
+
+    group.bench_function(BenchmarkId::new("best", "linear"), |b| {
+      b.iter(|| {
+        for query in queries {
+          black_box(linear_best(&words, black_box(query)));
+        }
+      })
+    });
+    group.bench_function(BenchmarkId::new("best", "bk_tree"), |b| {
+      b.iter(|| {
+        for query in queries {
+          black_box(vocabulary.find_best(black_box(query), 1));
+        }
+      })
+    });
+    group.bench_function(BenchmarkId::new("within_1", "linear"), |b| {
+      b.iter(|| {
+        for query in queries {
+          black_box(linear_within(&words, black_box(query), 1));
+        }
+      })
+    });
+    group.bench_function(BenchmarkId::new("within_1", "bk_tree"), |b| {
+      b.iter(|| {
+        for query in queries {
+          black_box(vocabulary.find_within(black_box(query), 1));
+        }
+      })
+    });
+
+    group.finish();
+  }
+}
+
+criterion_group!(benches, fuzzy_lookup);
+criterion_main!(benches);
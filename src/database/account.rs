@@ -0,0 +1,102 @@
+use {
+  crate::database::database::{execute, next_row, now, with_connection},
+  sqlite::Value
+};
+
+/// The account disks are saved to when no other is chosen. It always exists,
+/// and holds the disks saved before accounts were introduced.
+pub const DEFAULT_ACCOUNT: &str = "default";
+
+/// A game account with its own disk inventory, identified by its UID.
+#[derive(Clone, Debug)]
+pub struct Account {
+  pub uid: String,
+  pub name: Option<String>,
+  pub created_at: i64,
+  /// Number of disks in the account's inventory.
+  pub disks: i64
+}
+
+/// Adds an account, or renames it if it exists.
+pub async fn insert_account(uid: &str, name: Option<&str>) -> Result<(), String> {
+  if uid.is_empty() || uid.chars().any(char::is_whitespace) {
+    return Err(format!("Invalid account UID: {:?}", uid));
+  }
+
+  let parameters = vec![
+    (":uid", Value::String(uid.to_string())),
+    (":name", name.map_or(Value::Null, |name| Value::String(name.to_string()))),
+    (":created_at", Value::Integer(now())),
+  ];
+
+  with_connection(move |connection| {
+    execute(
+      connection,
+      r#"
+      INSERT INTO accounts (uid, name, created_at)
+      VALUES (:uid, :name, :created_at)
+      ON CONFLICT (uid) DO UPDATE SET name = excluded.name;
+      "#,
+      &parameters
+    )
+  })
+  .await
+}
+
+pub async fn read_accounts() -> Result<Vec<Account>, String> {
+  with_connection(|connection| {
+    let mut statement = match connection.prepare(
+      r#"
+      SELECT accounts.uid, accounts.name, accounts.created_at, COUNT(disks.id) AS disks
+      FROM accounts
+      LEFT JOIN disks ON disks.account = accounts.uid AND disks.deleted_at IS NULL
+      GROUP BY accounts.uid
+      ORDER BY accounts.uid
+      "#
+    ) {
+      Ok(statement) => statement,
+      Err(error) => return Err(format!("Failed to prepare statement: {}", error))
+    };
+
+    let mut result = vec![];
+
+    while next_row(&mut statement)? {
+      if let (Ok(uid), Ok(name), Ok(created_at), Ok(disks)) = (
+        statement.read::<String, _>("uid"),
+        statement.read::<Option<String>, _>("name"),
+        statement.read::<i64, _>("created_at"),
+        statement.read::<i64, _>("disks")
+      ) {
+        result.push(Account { uid, name, created_at, disks });
+      } else {
+        return Err("Failed to read account".to_string());
+      }
+    }
+
+    Ok(result)
+  })
+  .await
+}
+
+pub async fn read_account(uid: &str) -> Result<Option<Account>, String> {
+  Ok(read_accounts().await?.into_iter().find(|account| account.uid == uid))
+}
+
+/// Deletes an account together with its inventory. The default account can't
+/// be deleted.
+pub async fn delete_account(uid: &str) -> Result<bool, String> {
+  if uid == DEFAULT_ACCOUNT {
+    return Err("The default account cannot be removed".to_string());
+  }
+
+  let uid = uid.to_string();
+  with_connection(move |connection| {
+    execute(
+      connection,
+      "DELETE FROM accounts WHERE uid = :uid",
+      &[(":uid", Value::String(uid))]
+    )?;
+    Ok(connection.change_count() > 0)
+  })
+  .await
+}
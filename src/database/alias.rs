@@ -0,0 +1,128 @@
+use {
+  crate::{
+    database::database::{execute, next_row, now, prepare, transaction, with_connection},
+    source::alias::{Alias, AliasTable, WordScope}
+  },
+  sqlite::{Connection, Value}
+};
+
+/// Records that `raw` read from a region of `scope` by the client of `locale`
+/// should be `canonical`, replacing any earlier correction for the same text.
+pub async fn insert_alias(
+  locale: &str,
+  scope: WordScope,
+  raw: &str,
+  canonical: &str
+) -> Result<(), String> {
+  let parameters = vec![
+    (":locale", Value::String(locale.to_string())),
+    (":scope", Value::String(scope.to_string())),
+    (":raw", Value::String(raw.to_string())),
+    (":canonical", Value::String(canonical.to_string())),
+    (":updated_at", Value::Integer(now())),
+  ];
+
+  with_connection(move |connection| {
+    execute(
+      connection,
+      r#"
+      INSERT INTO aliases (locale, scope, raw, canonical, updated_at)
+      VALUES (:locale, :scope, :raw, :canonical, :updated_at)
+      ON CONFLICT (locale, scope, raw) DO UPDATE SET
+        canonical = excluded.canonical,
+        updated_at = excluded.updated_at;
+      "#,
+      &parameters
+    )
+  })
+  .await
+}
+
+fn read_alias_rows(connection: &Connection, locale: &str) -> Result<AliasTable, String> {
+  let mut statement = prepare(
+    connection,
+    "SELECT scope, raw, canonical, updated_at FROM aliases WHERE locale = ? ORDER BY \
+     scope, raw",
+    &[Value::String(locale.to_string())]
+  )?;
+
+  let mut result = AliasTable::new();
+
+  while next_row(&mut statement)? {
+    let scope = match statement.read::<String, _>("scope") {
+      Ok(scope) => WordScope::try_from(&scope as &str)?,
+      Err(error) => return Err(format!("Failed to read scope: {}", error))
+    };
+
+    if let (Ok(raw), Ok(canonical), Ok(updated_at)) = (
+      statement.read::<String, _>("raw"),
+      statement.read::<String, _>("canonical"),
+      statement.read::<i64, _>("updated_at")
+    ) {
+      let locale = locale.to_string();
+      result.insert(Alias { locale, scope, raw, canonical, updated_at });
+    } else {
+      return Err("Failed to read alias".to_string());
+    }
+  }
+
+  Ok(result)
+}
+
+/// Reads the aliases learned for the client of `locale`.
+pub async fn read_aliases(locale: &str) -> Result<AliasTable, String> {
+  let locale = locale.to_string();
+  with_connection(move |connection| read_alias_rows(connection, &locale)).await
+}
+
+fn remove_alias(
+  connection: &Connection,
+  locale: &str,
+  scope: WordScope,
+  raw: &str
+) -> Result<bool, String> {
+  execute(
+    connection,
+    "DELETE FROM aliases WHERE locale = :locale AND scope = :scope AND raw = :raw",
+    &[
+      (":locale", Value::String(locale.to_string())),
+      (":scope", Value::String(scope.to_string())),
+      (":raw", Value::String(raw.to_string()))
+    ]
+  )?;
+  Ok(connection.change_count() > 0)
+}
+
+pub async fn delete_alias(
+  locale: &str,
+  scope: WordScope,
+  raw: &str
+) -> Result<bool, String> {
+  let locale = locale.to_string();
+  let raw = raw.to_string();
+  with_connection(move |connection| remove_alias(connection, &locale, scope, &raw)).await
+}
+
+/// Deletes every alias of `locale` for which `predicate` holds, all of them
+/// or none, and returns the deleted aliases. Aliases of other locales are
+/// left alone, as their words are checked against another vocabulary.
+pub async fn prune_aliases<F>(locale: &str, predicate: F) -> Result<Vec<Alias>, String>
+where F: Fn(&Alias) -> bool + Send + 'static {
+  let locale = locale.to_string();
+  with_connection(move |connection| {
+    transaction(connection, || {
+      let aliases = read_alias_rows(connection, &locale)?;
+
+      let mut pruned = vec![];
+      for alias in aliases.aliases() {
+        if predicate(alias) {
+          remove_alias(connection, &locale, alias.scope, &alias.raw)?;
+          pruned.push(alias.clone());
+        }
+      }
+
+      Ok(pruned)
+    })
+  })
+  .await
+}
@@ -1,225 +1,205 @@
 use {
-  crate::disk::disk::{
-    Affix,
-    Disk
+  crate::{
+    database::{
+      journal::{record, DiskSnapshot, Operation},
+      migration::migrate,
+      quarantine::quarantine_disks
+    },
+    disk::disk::{Affix, Disk, EquippedBy, Rarity},
+    source::game_data::GameData
   },
-  sqlite::{Connection, ConnectionThreadSafe, State, Statement, Value},
-  std::collections::HashMap,
-  tokio::sync::OnceCell
+  sqlite::{Connection, State, Statement, Value},
+  std::{
+    collections::{HashMap, HashSet},
+    env,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+      mpsc::{self, Sender},
+      OnceLock
+    },
+    thread,
+    time::{SystemTime, UNIX_EPOCH}
+  },
+  tokio::sync::{oneshot, OnceCell}
 };
 
-static CONNECTION: OnceCell<Result<ConnectionThreadSafe, String>> = OnceCell::const_new();
+pub(crate) fn now() -> i64 {
+  SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
+
+static DATABASE_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Work for the database thread, run with its connection.
+type Job = Box<dyn FnOnce(&Connection) + Send>;
+
+static DATABASE: OnceCell<Result<Sender<Job>, String>> = OnceCell::const_new();
+
+/// Where the database is kept when no other path is set: `zenlessDiskOCR` in
+/// the user's data directory, `$XDG_DATA_HOME`, `%APPDATA%` or
+/// `~/.local/share`, or the working directory when none of them is known.
+pub fn default_database_path() -> PathBuf {
+  let data_dir = env::var_os("XDG_DATA_HOME")
+    .filter(|dir| !dir.is_empty())
+    .map(PathBuf::from)
+    .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+    .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")));
+
+  match data_dir {
+    Some(dir) => dir.join("zenlessDiskOCR").join("database.db"),
+    None => PathBuf::from("database.db")
+  }
+}
 
-async fn get_connection() -> &'static Result<ConnectionThreadSafe, String> {
-  CONNECTION
-    .get_or_init(|| {
-      async {
-        let connection = Connection::open_thread_safe("database.db");
-        match connection {
-          Ok(connection) => Ok(connection),
-          Err(error) => Err(format!("Failed to open database: {}", error))
+/// Where earlier versions kept the database: the working directory.
+pub const LEGACY_DATABASE_PATH: &str = "database.db";
+
+/// The database an earlier version left in the working directory, when there
+/// is one and none at the default path yet, so that it can be used rather
+/// than starting an empty inventory.
+pub fn legacy_database_path() -> Option<PathBuf> {
+  let legacy = PathBuf::from(LEGACY_DATABASE_PATH);
+  let default = default_database_path();
+  (default != legacy && legacy.is_file() && !default.exists()).then_some(legacy)
+}
+
+/// Makes the database at `path` the one opened instead of the default one.
+/// Has to be called before the database is first used.
+pub fn set_database_path(path: impl Into<PathBuf>) -> Result<(), String> {
+  if DATABASE.initialized() {
+    return Err("The database is already open".to_string());
+  }
+  DATABASE_PATH
+    .set(path.into())
+    .map_err(|_| "The database path is already set".to_string())
+}
+
+/// The database opened by [`with_connection`].
+pub fn database_path() -> PathBuf {
+  DATABASE_PATH.get().cloned().unwrap_or_else(default_database_path)
+}
+
+pub(crate) fn open(path: &Path) -> Result<Connection, String> {
+  if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+    fs::create_dir_all(parent).map_err(|error| {
+      format!("Failed to create directory {}: {}", parent.display(), error)
+    })?;
+  }
+
+  let connection = match Connection::open(path) {
+    Ok(connection) => connection,
+    Err(error) => {
+      return Err(format!("Failed to open database {}: {}", path.display(), error))
+    },
+  };
+  migrate(&connection)?;
+  // Only after migrating, as rebuilding a table drops rows others refer to
+  if let Err(error) = connection.execute("PRAGMA foreign_keys = ON") {
+    return Err(format!("Failed to enable foreign keys: {}", error));
+  }
+  Ok(connection)
+}
+
+/// Starts the thread that owns the connection and runs every database job in
+/// turn, so that SQLite never blocks the async runtime's workers.
+async fn start_database() -> Result<Sender<Job>, String> {
+  let path = database_path();
+  let (sender, jobs) = mpsc::channel::<Job>();
+  let (opened, result) = oneshot::channel();
+
+  thread::Builder::new()
+    .name("database".to_string())
+    .spawn(move || {
+      let connection = match open(&path) {
+        Ok(connection) => {
+          let _ = opened.send(Ok(()));
+          connection
+        },
+        Err(error) => {
+          let _ = opened.send(Err(error));
+          return;
         }
+      };
+
+      for job in jobs {
+        job(&connection);
       }
     })
-    .await
+    .map_err(|error| format!("Failed to start the database thread: {}", error))?;
+
+  match result.await {
+    Ok(Ok(())) => Ok(sender),
+    Ok(Err(error)) => Err(error),
+    Err(_) => Err("The database thread stopped".to_string())
+  }
 }
 
-async fn init_database() -> Result<(), String> {
-  let connection = match get_connection().await {
-    Ok(connection) => connection,
+/// Runs `job` with the connection on the database thread, opening the
+/// database first if needed, and waits for its result without blocking.
+pub(crate) async fn with_connection<T, F>(job: F) -> Result<T, String>
+where
+  T: Send + 'static,
+  F: FnOnce(&Connection) -> Result<T, String> + Send + 'static
+{
+  let sender = match DATABASE.get_or_init(start_database).await {
+    Ok(sender) => sender,
     Err(error) => return Err(error.clone())
   };
 
-  let table_stat = connection
-    .prepare("SELECT name FROM sqlite_master WHERE type='table' AND name='disks'")
-    .map_err(|error| format!("Failed to prepare statement: {}", error))?
-    .next();
-
-  if let Ok(State::Done) = table_stat {
-    match connection.execute(
-      r#"
-        CREATE TABLE disks (
-          id INTEGER AUTOINCREMENT PRIMARY KEY,
-          set TEXT NOT NULL,
-          slot INTEGER NOT NULL,
-          primary_affix_name TEXT NOT NULL,
-          primary_affix_value REAL NOT NULL,
-          primary_affix_value_type TEXT NOT NULL,
-          secondary_affix_1_name TEXT,
-          secondary_affix_1_value REAL,
-          secondary_affix_1_value_type TEXT,
-          secondary_affix_2_name TEXT,
-          secondary_affix_2_value REAL,
-          secondary_affix_2_value_type TEXT,
-          secondary_affix_3_name TEXT,
-          secondary_affix_3_value REAL,
-          secondary_affix_3_value_type TEXT,
-          secondary_affix_4_name TEXT,
-          secondary_affix_4_value REAL,
-          secondary_affix_4_value_type TEXT
-        );
-        "#
-    ) {
-      Ok(_) => Ok(()),
-      Err(error) => Err(format!("Failed to create table: {}", error))
+  let (reply, result) = oneshot::channel();
+  sender
+    .send(Box::new(move |connection| {
+      let _ = reply.send(job(connection));
+    }))
+    .map_err(|_| "The database thread stopped".to_string())?;
+
+  result.await.map_err(|_| "The database thread stopped".to_string())?
+}
+
+/// Opens the database, bringing its schema up to date.
+pub async fn init_database() -> Result<(), String> {
+  with_connection(|_| Ok(())).await
+}
+
+/// Runs `body` in a transaction, committing when it succeeds and rolling
+/// back when it fails.
+pub(crate) fn transaction<T>(
+  connection: &Connection,
+  body: impl FnOnce() -> Result<T, String>
+) -> Result<T, String> {
+  connection
+    .execute("BEGIN IMMEDIATE")
+    .map_err(|error| format!("Failed to begin transaction: {}", error))?;
+
+  match body() {
+    Ok(result) => {
+      connection
+        .execute("COMMIT")
+        .map_err(|error| format!("Failed to commit transaction: {}", error))?;
+      Ok(result)
+    },
+    Err(error) => {
+      let _ = connection.execute("ROLLBACK");
+      Err(error)
     }
-  } else {
-    Ok(())
   }
 }
 
-async fn insert_disk(disk: &Disk) -> Result<(), String> {
-  let connection = match get_connection().await {
-    Ok(connection) => connection,
-    Err(error) => return Err(error.clone())
-  };
-
-  let mut statement = match connection.prepare(
-    r#"
-    INSERT INTO disks (
-      set,
-      slot,
-      primary_affix_name,
-      primary_affix_value,
-      primary_affix_value_type,
-      secondary_affix_1_name,
-      secondary_affix_1_value,
-      secondary_affix_1_value_type,
-      secondary_affix_2_name,
-      secondary_affix_2_value,
-      secondary_affix_2_value_type,
-      secondary_affix_3_name,
-      secondary_affix_3_value,
-      secondary_affix_3_value_type,
-      secondary_affix_4_name,
-      secondary_affix_4_value,
-      secondary_affix_4_value_type
-    )
-    VALUES (
-      set = :set,
-      slot = :slot,
-      primary_affix_name = :primary_affix_name,
-      primary_affix_value = :primary_affix_value,
-      primary_affix_value_type = :primary_affix_value_type,
-      secondary_affix_1_name = :secondary_affix_1_name,
-      secondary_affix_1_value = :secondary_affix_1_value,
-      secondary_affix_1_value_type = :secondary_affix_1_value_type,
-      secondary_affix_2_name = :secondary_affix_2_name,
-      secondary_affix_2_value = :secondary_affix_2_value,
-      secondary_affix_2_value_type = :secondary_affix_2_value_type,
-      secondary_affix_3_name = :secondary_affix_3_name,
-      secondary_affix_3_value = :secondary_affix_3_value,
-      secondary_affix_3_value_type = :secondary_affix_3_value_type,
-      secondary_affix_4_name = :secondary_affix_4_name,
-      secondary_affix_4_value = :secondary_affix_4_value,
-      secondary_affix_4_value_type = :secondary_affix_4_value_type
-    );
-    "#
-  ) {
+/// Runs a statement that returns no rows with the given parameters.
+pub(crate) fn execute(
+  connection: &Connection,
+  sql: &str,
+  parameters: &[(&str, Value)]
+) -> Result<(), String> {
+  let mut statement = match connection.prepare(sql) {
     Ok(statement) => statement,
     Err(error) => return Err(format!("Failed to prepare statement: {}", error))
   };
 
-  let affix_1 = disk.secondary_affixes[0].as_ref();
-  let affix_2 = disk.secondary_affixes[1].as_ref();
-  let affix_3 = disk.secondary_affixes[2].as_ref();
-  let affix_4 = disk.secondary_affixes[3].as_ref();
-
-  match statement.bind::<&[(_, Value)]>(
-    &[
-      (":set", Value::String(disk.set.to_string())),
-      (":slot", Value::Integer(i64::from(disk.slot))),
-      (":primary_affix_name", Value::String(disk.primary_affix.name.to_string())),
-      (":primary_affix_value", Value::Float(disk.primary_affix.value)),
-      (
-        ":primary_affix_value_type",
-        Value::String(disk.primary_affix.value_type.to_string())
-      ),
-      (
-        ":secondary_affix_1_name",
-        if let Some(affix) = affix_1 {
-          Value::String(affix.name.to_string())
-        } else {
-          Value::Null
-        }
-      ),
-      (
-        ":secondary_affix_1_value",
-        if let Some(affix) = affix_1 { Value::Float(affix.value) } else { Value::Null }
-      ),
-      (
-        ":secondary_affix_1_value_type",
-        if let Some(affix) = affix_1 {
-          Value::String(affix.value_type.to_string())
-        } else {
-          Value::Null
-        }
-      ),
-      (
-        ":secondary_affix_2_name",
-        if let Some(affix) = affix_2 {
-          Value::String(affix.name.to_string())
-        } else {
-          Value::Null
-        }
-      ),
-      (
-        ":secondary_affix_2_value",
-        if let Some(affix) = affix_2 { Value::Float(affix.value) } else { Value::Null }
-      ),
-      (
-        ":secondary_affix_2_value_type",
-        if let Some(affix) = affix_2 {
-          Value::String(affix.value_type.to_string())
-        } else {
-          Value::Null
-        }
-      ),
-      (
-        ":secondary_affix_3_name",
-        if let Some(affix) = affix_3 {
-          Value::String(affix.name.to_string())
-        } else {
-          Value::Null
-        }
-      ),
-      (
-        ":secondary_affix_3_value",
-        if let Some(affix) = affix_3 { Value::Float(affix.value) } else { Value::Null }
-      ),
-      (
-        ":secondary_affix_3_value_type",
-        if let Some(affix) = affix_3 {
-          Value::String(affix.value_type.to_string())
-        } else {
-          Value::Null
-        }
-      ),
-      (
-        ":secondary_affix_4_name",
-        if let Some(affix) = affix_4 {
-          Value::String(affix.name.to_string())
-        } else {
-          Value::Null
-        }
-      ),
-      (
-        ":secondary_affix_4_value",
-        if let Some(affix) = affix_4 { Value::Float(affix.value) } else { Value::Null }
-      ),
-      (
-        ":secondary_affix_4_value_type",
-        if let Some(affix) = affix_4 {
-          Value::String(affix.value_type.to_string())
-        } else {
-          Value::Null
-        }
-      )
-    ][..]
-  ) {
-    Ok(_) => (),
-    Err(error) => return Err(format!("Failed to bind set: {}", error))
-  }
+  statement
+    .bind::<&[(_, Value)]>(parameters)
+    .map_err(|error| format!("Failed to bind parameters: {}", error))?;
 
   match statement.next() {
     Ok(State::Done) => Ok(()),
@@ -228,123 +208,669 @@ async fn insert_disk(disk: &Disk) -> Result<(), String> {
   }
 }
 
-async fn read_disk() -> Result<HashMap<i64, Disk>, String> {
-  let connection = match get_connection().await {
-    Ok(connection) => connection,
-    Err(error) => return Err(error.clone())
+/// The disks seen during one sweep of the inventory. A disk scanned twice in
+/// the same sweep is two disks with the same stats, while one scanned again in
+/// a later sweep is the disk saved before.
+#[derive(Clone, Debug, Default)]
+pub struct ScanSession {
+  /// ID of the stored session the scans are recorded in, if any.
+  pub(crate) id: Option<i64>,
+  pub(crate) seen: HashSet<i64>
+}
+
+impl ScanSession {
+  /// A session whose scans aren't recorded.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn id(&self) -> Option<i64> {
+    self.id
+  }
+
+  /// IDs of the stored disks seen so far.
+  pub fn seen(&self) -> &HashSet<i64> {
+    &self.seen
+  }
+}
+
+/// What saving a scanned disk did.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Saved {
+  /// The disk wasn't stored yet.
+  Inserted(i64),
+  /// The disk was stored, and was enhanced or its equipped agent, rarity or
+  /// marks changed.
+  Updated(i64),
+  /// The disk was stored as it is.
+  Skipped(i64)
+}
+
+impl Saved {
+  /// ID of the stored disk.
+  pub fn id(&self) -> i64 {
+    match self {
+      Self::Inserted(id) | Self::Updated(id) | Self::Skipped(id) => *id
+    }
+  }
+}
+
+impl Display for Saved {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let str = match self {
+      Self::Inserted(_) => "inserted",
+      Self::Updated(_) => "updated",
+      Self::Skipped(_) => "skipped"
+    };
+    write!(f, "{}", str)
+  }
+}
+
+/// Saves a scanned disk to the inventory of `account`. A stored disk that
+/// wasn't seen yet in `session` and that the scanned one is, or may be after
+/// enhancing, is updated instead of saving the disk again; its rarity and
+/// level are kept when they weren't read.
+pub async fn insert_disk(
+  game_data: &GameData,
+  account: &str,
+  disk: &Disk,
+  session: &mut ScanSession
+) -> Result<Saved, String> {
+  let game_data = game_data.clone();
+  let account = account.to_string();
+  let disk = disk.clone();
+  let seen = session.seen.clone();
+
+  let saved = with_connection(move |connection| {
+    transaction(connection, || save_disk(connection, &game_data, &account, &disk, &seen))
+  })
+  .await?;
+
+  session.seen.insert(saved.id());
+  Ok(saved)
+}
+
+/// Saves a scanned disk as `insert_disk` does, within a transaction. A stored
+/// disk with the same affixes is preferred over one the scanned disk may be
+/// enhanced from, and a disk with the same affixes as one already seen is
+/// another copy of it, saved on its own.
+pub(crate) fn save_disk(
+  connection: &Connection,
+  game_data: &GameData,
+  account: &str,
+  disk: &Disk,
+  seen: &HashSet<i64>
+) -> Result<Saved, String> {
+  let parameters = vec![
+    Value::String(account.to_string()),
+    Value::String(disk.set.clone()),
+    Value::Integer(disk.slot.into()),
+  ];
+  let filter = "account = ? AND set_id = ? AND slot = ? AND deleted_at IS NULL AND id \
+                NOT IN (SELECT disk_id FROM quarantine)";
+  let mut statement =
+    prepare(connection, &format!("SELECT * FROM disks WHERE {}", filter), &parameters)?;
+  let rows = read_disk_rows(&mut statement)?;
+  let affixes = read_affix_rows(
+    connection,
+    &format!("disk_id IN (SELECT id FROM disks WHERE {})", filter),
+    &parameters
+  )?;
+  // Stored disks that no longer fit the game data are never matched
+  let mut stored: Vec<_> =
+    LoadedDisks::parse(game_data, rows, affixes).disks.into_iter().collect();
+  stored.sort_by_key(|(id, _)| *id);
+
+  let unseen = || stored.iter().filter(|(id, _)| !seen.contains(id));
+  let same = unseen().find(|(_, stored)| disk.same_affixes(stored));
+  let duplicate =
+    || stored.iter().any(|(id, stored)| seen.contains(id) && disk.same_affixes(stored));
+  let matched = match same {
+    Some((id, _)) => Some((*id, true)),
+    None if duplicate() => None,
+    None => {
+      unseen().find(|(_, stored)| disk.is_upgrade_of(stored)).map(|(id, _)| (*id, false))
+    },
+  };
+
+  let mut snapshot = DiskSnapshot::new(game_data, disk);
+  let Some((id, same)) = matched else {
+    let id = snapshot.insert(connection, account)?;
+    record(connection, account, id, Operation::Insert, None)?;
+    return Ok(Saved::Inserted(id));
   };
 
-  let table_stat = connection
-    .prepare("SELECT name FROM sqlite_master WHERE type='table' AND name='disks'")
-    .map_err(|error| format!("Failed to prepare statement: {}", error))?
-    .next();
+  let stored = DiskSnapshot::read(connection, id)?;
+  snapshot.fill_unread(&stored);
+  if same && stored.same_details(&snapshot) {
+    return Ok(Saved::Skipped(id));
+  }
+  snapshot.write(connection, id)?;
+  record(connection, account, id, Operation::Update, Some((&stored, &snapshot)))?;
+  Ok(Saved::Updated(id))
+}
+
+/// Replaces the stored state of the disk `id` of `account` with `disk`.
+pub async fn update_disk(
+  game_data: &GameData,
+  account: &str,
+  id: i64,
+  disk: &Disk
+) -> Result<(), String> {
+  let account = account.to_string();
+  let after = DiskSnapshot::new(game_data, disk);
+
+  with_connection(move |connection| {
+    transaction(connection, || {
+      if !is_stored(connection, &account, id)? {
+        return Err(format!("No disk {}", id));
+      }
+      let before = DiskSnapshot::read(connection, id)?;
+      if before == after {
+        return Ok(());
+      }
+      after.write(connection, id)?;
+      record(connection, &account, id, Operation::Update, Some((&before, &after)))
+    })
+  })
+  .await
+}
+
+/// Removes the disk `id` from the inventory of `account`, keeping it with a
+/// deletion date so the removal can be undone. Returns whether the disk was
+/// in the inventory.
+pub async fn delete_disk(account: &str, id: i64) -> Result<bool, String> {
+  let account = account.to_string();
+  with_connection(move |connection| {
+    transaction(connection, || {
+      if !is_stored(connection, &account, id)? {
+        return Ok(false);
+      }
+      execute(
+        connection,
+        "UPDATE disks SET deleted_at = :deleted_at WHERE id = :id",
+        &[(":id", Value::Integer(id)), (":deleted_at", Value::Integer(now()))]
+      )?;
+      record(connection, &account, id, Operation::Delete, None)?;
+      Ok(true)
+    })
+  })
+  .await
+}
+
+/// Whether the disk `id` is in the inventory of `account` and not deleted.
+fn is_stored(connection: &Connection, account: &str, id: i64) -> Result<bool, String> {
+  let mut statement = prepare(
+    connection,
+    "SELECT id FROM disks WHERE id = ? AND account = ? AND deleted_at IS NULL",
+    &[Value::Integer(id), Value::String(account.to_string())]
+  )?;
+  match statement.next() {
+    Ok(State::Row) => Ok(true),
+    Ok(State::Done) => Ok(false),
+    Err(error) => Err(format!("Failed to execute statement: {}", error))
+  }
+}
+
+/// Reads the disk `id` of the inventory of `account`.
+pub async fn get_disk(
+  game_data: &GameData,
+  account: &str,
+  id: i64
+) -> Result<Option<Disk>, String> {
+  let parameters = vec![Value::Integer(id), Value::String(account.to_string())];
+  let (disks, affixes) = with_connection(move |connection| {
+    let mut statement = prepare(
+      connection,
+      "SELECT * FROM disks WHERE id = ? AND account = ? AND deleted_at IS NULL",
+      &parameters
+    )?;
+    let disks = read_disk_rows(&mut statement)?;
+    let affixes = read_affix_rows(connection, "disk_id = ?", &parameters[.. 1])?;
+    Ok((disks, affixes))
+  })
+  .await?;
+
+  let mut affixes = parse_affixes(game_data, affixes);
+  match disks.into_iter().next() {
+    Some(row) => {
+      let (_, disk) =
+        parse_disk(game_data, row, &mut affixes).map_err(|row| row.error)?;
+      Ok(Some(disk))
+    },
+    None => Ok(None)
+  }
+}
+
+pub(crate) fn last_insert_id(connection: &Connection) -> Result<i64, String> {
+  let mut statement = connection
+    .prepare("SELECT last_insert_rowid() AS id")
+    .map_err(|error| format!("Failed to prepare statement: {}", error))?;
+  match statement.next() {
+    Ok(State::Row) => {
+      statement
+        .read::<i64, _>("id")
+        .map_err(|error| format!("Failed to read id: {}", error))
+    },
+    Ok(State::Done) => Err("Failed to read id: no row".to_string()),
+    Err(error) => Err(format!("Failed to execute statement: {}", error))
+  }
+}
+
+/// A disk as stored, before it is checked against the game data.
+pub(crate) struct DiskRow {
+  pub id: i64,
+  pub set: String,
+  pub slot: i64,
+  pub equipped_by: Option<EquippedBy>,
+  pub rarity: Option<String>,
+  pub level: Option<i64>,
+  pub locked: bool,
+  pub trashed: bool,
+  pub scanned_at: Option<i64>
+}
+
+/// An affix as stored, before it is checked against the game data.
+pub(crate) struct AffixRow {
+  pub disk_id: i64,
+  pub position: i64,
+  pub kind: String,
+  pub stat: String,
+  pub value: f64,
+  pub value_type: String
+}
+
+/// A stored disk that couldn't be read, and why.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RowError {
+  pub id: i64,
+  pub error: String
+}
 
-  if let Ok(State::Done) = table_stat {
-    return Err("Table does not exist".to_string());
+impl Display for RowError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "Disk {}: {}", self.id, self.error)
   }
+}
 
-  let mut statement = match connection.prepare("SELECT * FROM disks") {
+/// The disks read from an inventory, by ID, and the stored disks that
+/// couldn't be read.
+#[derive(Debug, Default)]
+pub struct LoadedDisks {
+  pub disks: HashMap<i64, Disk>,
+  pub errors: Vec<RowError>
+}
+
+impl LoadedDisks {
+  /// Builds the disks of the rows with their affixes, keeping the rows that
+  /// don't fit the game data as errors.
+  pub(crate) fn parse(
+    game_data: &GameData,
+    rows: Vec<Result<DiskRow, RowError>>,
+    affixes: Vec<Result<AffixRow, RowError>>
+  ) -> Self {
+    let mut affixes = parse_affixes(game_data, affixes);
+    let mut result = Self::default();
+    for row in rows {
+      match parse_disk(game_data, row, &mut affixes) {
+        Ok((id, disk)) => {
+          result.disks.insert(id, disk);
+        },
+        Err(error) => result.errors.push(error)
+      }
+    }
+    result
+  }
+}
+
+/// Reads the inventory of `account`. A stored disk that doesn't fit the game
+/// data, such as one of an unknown set, is reported in the errors instead of
+/// failing the whole read; with `quarantine`, it is also moved to the
+/// quarantine, where reads skip it until it is released.
+pub async fn read_disk(
+  game_data: &GameData,
+  account: &str,
+  quarantine: bool
+) -> Result<LoadedDisks, String> {
+  let parameters = vec![Value::String(account.to_string())];
+  let (disks, affixes) = with_connection(move |connection| {
+    let mut statement = prepare(
+      connection,
+      "SELECT * FROM disks WHERE account = ? AND deleted_at IS NULL AND id NOT IN \
+       (SELECT disk_id FROM quarantine)",
+      &parameters
+    )?;
+    let disks = read_disk_rows(&mut statement)?;
+
+    let affixes = read_affix_rows(
+      connection,
+      "disk_id IN (SELECT id FROM disks WHERE account = ? AND deleted_at IS NULL)",
+      &parameters
+    )?;
+    Ok((disks, affixes))
+  })
+  .await?;
+
+  let result = LoadedDisks::parse(game_data, disks, affixes);
+
+  if quarantine && !result.errors.is_empty() {
+    quarantine_disks(result.errors.clone()).await?;
+  }
+  Ok(result)
+}
+
+/// Prepares a statement with positional parameters.
+pub(crate) fn prepare<'a>(
+  connection: &'a Connection,
+  sql: &str,
+  parameters: &[Value]
+) -> Result<Statement<'a>, String> {
+  let mut statement = match connection.prepare(sql) {
     Ok(statement) => statement,
     Err(error) => return Err(format!("Failed to prepare statement: {}", error))
   };
+  statement
+    .bind(parameters)
+    .map_err(|error| format!("Failed to bind parameters: {}", error))?;
+  Ok(statement)
+}
 
-  let mut result: HashMap<i64, Disk> = HashMap::new();
+/// Steps `statement` to its next row, returning whether there was one. A
+/// failed step is an error rather than the end of the rows, so a read is
+/// never cut short silently.
+pub(crate) fn next_row(statement: &mut Statement) -> Result<bool, String> {
+  match statement.next() {
+    Ok(State::Row) => Ok(true),
+    Ok(State::Done) => Ok(false),
+    Err(error) => Err(format!("Failed to execute statement: {}", error))
+  }
+}
 
-  while let Ok(State::Row) = statement.next() {
-    let id: i64 = match statement.read::<i64, _>("id") {
-      Ok(id) => id,
-      Err(error) => return Err(format!("Failed to read id: {}", error))
-    };
+/// Reads every row of a statement selecting the columns of the disks table,
+/// keeping the rows that can't be read as errors.
+pub(crate) fn read_disk_rows(
+  statement: &mut Statement
+) -> Result<Vec<Result<DiskRow, RowError>>, String> {
+  let mut result = vec![];
+  while next_row(statement)? {
+    result.push(read_disk_row(statement)?);
+  }
+  Ok(result)
+}
 
-    let set: &'static str = match statement.read::<String, _>("set") {
-      Ok(set) => {
-        match Disk::get_static_set_name(&set) {
-          Some(set) => set,
-          None => return Err(format!("Invalid disk set: {}", set))
-        }
+/// Reads the disk at the current row of a statement selecting the columns of
+/// the disks table. Only a missing ID fails the read; other unreadable
+/// columns make the row an error.
+pub(crate) fn read_disk_row(
+  statement: &Statement
+) -> Result<Result<DiskRow, RowError>, String> {
+  let id: i64 = match statement.read::<i64, _>("id") {
+    Ok(id) => id,
+    Err(error) => return Err(format!("Failed to read id: {}", error))
+  };
+  let row_error = |error: String| Ok(Err(RowError { id, error }));
+
+  let set = match statement.read::<String, _>("set_id") {
+    Ok(set) => set,
+    Err(error) => return row_error(format!("Failed to read set: {}", error))
+  };
+
+  let slot = match statement.read::<i64, _>("slot") {
+    Ok(slot) => slot,
+    Err(error) => return row_error(format!("Failed to read slot: {}", error))
+  };
+
+  let equipped_by = match (
+    statement.read::<Option<String>, _>("equipped_by"),
+    statement.read::<Option<String>, _>("equipped_by_raw")
+  ) {
+    (Ok(Some(id)), _) => Some(EquippedBy::Agent(id)),
+    (Ok(None), Ok(Some(raw))) => Some(EquippedBy::Unknown(raw)),
+    (Ok(None), Ok(None)) => None,
+    _ => return row_error("Failed to read equipped agent".to_string())
+  };
+
+  if let (Ok(rarity), Ok(level), Ok(locked), Ok(trashed), Ok(scanned_at)) = (
+    statement.read::<Option<String>, _>("rarity"),
+    statement.read::<Option<i64>, _>("level"),
+    statement.read::<i64, _>("locked"),
+    statement.read::<i64, _>("trashed"),
+    statement.read::<Option<i64>, _>("scanned_at")
+  ) {
+    Ok(Ok(DiskRow {
+      id,
+      set,
+      slot,
+      equipped_by,
+      rarity,
+      level,
+      locked: locked != 0,
+      trashed: trashed != 0,
+      scanned_at
+    }))
+  } else {
+    row_error("Failed to read details".to_string())
+  }
+}
+
+/// Reads the affixes matching `filter`, a condition on the columns of the
+/// affixes table with positional parameters. Affixes that can't be read are
+/// errors of their disk.
+pub(crate) fn read_affix_rows(
+  connection: &Connection,
+  filter: &str,
+  parameters: &[Value]
+) -> Result<Vec<Result<AffixRow, RowError>>, String> {
+  let mut statement = prepare(
+    connection,
+    &format!(
+      "SELECT disk_id, position, kind, stat, value, value_type FROM affixes WHERE {}",
+      filter
+    ),
+    parameters
+  )?;
+
+  let mut result = vec![];
+
+  while next_row(&mut statement)? {
+    let disk_id = statement
+      .read::<i64, _>("disk_id")
+      .map_err(|error| format!("Failed to read affix: {}", error))?;
+
+    if let (Ok(position), Ok(kind), Ok(stat), Ok(value), Ok(value_type)) = (
+      statement.read::<i64, _>("position"),
+      statement.read::<String, _>("kind"),
+      statement.read::<String, _>("stat"),
+      statement.read::<f64, _>("value"),
+      statement.read::<String, _>("value_type")
+    ) {
+      result.push(Ok(AffixRow { disk_id, position, kind, stat, value, value_type }));
+    } else {
+      result
+        .push(Err(RowError { id: disk_id, error: "Failed to read affix".to_string() }));
+    }
+  }
+
+  Ok(result)
+}
+
+/// The affixes of a disk, the primary affix first and then the secondary
+/// affixes by position, or the first error among them.
+pub(crate) type DiskAffixes = Result<[Option<Affix>; 5], String>;
+
+/// Groups the affixes by disk ID, checking them against the game data.
+pub(crate) fn parse_affixes(
+  game_data: &GameData,
+  rows: Vec<Result<AffixRow, RowError>>
+) -> HashMap<i64, DiskAffixes> {
+  let mut result: HashMap<i64, DiskAffixes> = HashMap::new();
+
+  for row in rows {
+    let (disk_id, affix) = match row {
+      Ok(row) => {
+        (row.disk_id, parse_affix(game_data, &row).map(|affix| (row.position, affix)))
       },
-      Err(error) => return Err(format!("Failed to read set: {}", error))
+      Err(error) => (error.id, Err(error.error))
     };
+    let affixes = result.entry(disk_id).or_insert_with(|| Ok(Default::default()));
+    match (affixes, affix) {
+      (Ok(affixes), Ok((position, affix))) => affixes[position as usize] = Some(affix),
+      (affixes @ Ok(_), Err(error)) => *affixes = Err(error),
+      (Err(_), _) => ()
+    }
+  }
 
-    let slot: u8 = match statement.read::<i64, _>("slot") {
-      Ok(slot) => {
-        if slot > 0 && slot < 7 {
-          slot as u8
-        } else {
-          return Err(format!("Invalid slot: {}", slot));
-        }
-      },
-      Err(error) => return Err(format!("Failed to read slot: {}", error))
+  result
+}
+
+fn parse_affix(game_data: &GameData, row: &AffixRow) -> Result<Affix, String> {
+  match (row.kind.as_str(), row.position) {
+    ("primary", 0) | ("secondary", 1 ..= 4) => (),
+    _ => return Err(format!("Invalid affix position: {} {}", row.kind, row.position))
+  }
+  Affix::parse(game_data, &row.stat, row.value, &row.value_type)
+}
+
+/// Builds the disk of a row with its affixes, taken out of `affixes`.
+pub(crate) fn parse_disk(
+  game_data: &GameData,
+  row: Result<DiskRow, RowError>,
+  affixes: &mut HashMap<i64, DiskAffixes>
+) -> Result<(i64, Disk), RowError> {
+  let row = row?;
+  let id = row.id;
+  let affixes = affixes.remove(&id).unwrap_or_else(|| Ok(Default::default()));
+  build_disk(game_data, row, affixes)
+    .map(|disk| (id, disk))
+    .map_err(|error| RowError { id, error })
+}
+
+fn build_disk(
+  game_data: &GameData,
+  row: DiskRow,
+  affixes: DiskAffixes
+) -> Result<Disk, String> {
+  let slot: u8 = if row.slot > 0 && row.slot < 7 {
+    row.slot as u8
+  } else {
+    return Err(format!("Invalid slot: {}", row.slot));
+  };
+
+  let [primary_affix, affix_1, affix_2, affix_3, affix_4] = affixes?;
+  let primary_affix = match primary_affix {
+    Some(affix) => affix,
+    None => return Err("No primary affix".to_string())
+  };
+
+  let rarity = row.rarity.as_deref().map(Rarity::try_from).transpose()?;
+  let level = match row.level {
+    Some(level) => {
+      Some(u8::try_from(level).map_err(|_| format!("Invalid level: {}", level))?)
+    },
+    None => None
+  };
+
+  Disk::new(game_data, &row.set, slot, primary_affix, affix_1, affix_2, affix_3, affix_4)?
+    .with_equipped_by(row.equipped_by)
+    .with_rarity(rarity)
+    .with_level(level)
+    .map(|disk| disk.with_locked(row.locked).with_trashed(row.trashed))
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    crate::disk::disk::{Affix, EquippedBy},
+    std::slice
+  };
+
+  fn disk(game_data: &GameData, crit_rate: f64, atk: f64, level: u8) -> Disk {
+    let affix = |stat: &str, value: f64, value_type: &str| {
+      Affix::parse(game_data, stat, value, value_type).unwrap()
     };
+    Disk::new(
+      game_data,
+      "chaos_jazz",
+      1,
+      affix("hp", 550.0, "flat"),
+      Some(affix("crit_rate", crit_rate, "percentage")),
+      Some(affix("atk", atk, "flat")),
+      None,
+      None
+    )
+    .unwrap()
+    .with_level(Some(level))
+    .unwrap()
+  }
 
-    let primary_affix = if let (Ok(name), Ok(value), Ok(value_type)) = (
-      statement.read::<String, _>("primary_affix_name"),
-      statement.read::<f64, _>("primary_affix_value"),
-      statement.read::<String, _>("primary_affix_value_type")
-    ) {
-      Affix::try_from((&name as &str, value, &value_type as &str))?
-    } else {
-      return Err("Failed to read primary affix".to_string());
+  fn sweep(connection: &Connection, game_data: &GameData, disks: &[Disk]) -> Vec<Saved> {
+    let mut seen = HashSet::new();
+    disks
+      .iter()
+      .map(|disk| {
+        let saved = save_disk(connection, game_data, "default", disk, &seen).unwrap();
+        seen.insert(saved.id());
+        saved
+      })
+      .collect()
+  }
+
+  #[test]
+  fn rescans_match_the_stored_disks() {
+    let game_data = GameData::bundled();
+    let connection = open(Path::new(":memory:")).unwrap();
+    let before = disk(&game_data, 2.4, 19.0, 0);
+    let [Saved::Inserted(id)] =
+      sweep(&connection, &game_data, slice::from_ref(&before))[..]
+    else {
+      panic!("the disk wasn't inserted");
     };
 
-    let mut secondary_affixes: [Option<Affix>; 4] = [None, None, None, None];
+    assert_eq!(
+      sweep(&connection, &game_data, slice::from_ref(&before)),
+      [Saved::Skipped(id)]
+    );
 
-    fn read_secondary_affixes_and_save_if_fail(
-      statement: &mut Statement,
-      secondary_affixes: &mut [Option<Affix>; 4],
-      index: usize,
-      env: (i64, &str, u8, &Affix, &mut HashMap<i64, Disk>)
-    ) -> Result<(), String> {
-      if index > 3 {
-        return Err("Invalid index".to_string());
-      }
+    let equipped =
+      before.clone().with_equipped_by(Some(EquippedBy::Agent("anby".to_string())));
+    assert_eq!(sweep(&connection, &game_data, &[equipped]), [Saved::Updated(id)]);
 
-      let name_key = format!("secondary_affix_{}_name", index + 1);
-      let value_key = format!("secondary_affix_{}_value", index + 1);
-      let value_type_key = format!("secondary_affix_{}_value_type", index + 1);
-
-      if let (Ok(Some(name)), Ok(Some(value)), Ok(Some(value_type))) = (
-        statement.read::<Option<String>, _>(&name_key as &str),
-        statement.read::<Option<f64>, _>(&value_key as &str),
-        statement.read::<Option<String>, _>(&value_type_key as &str)
-      ) {
-        let affix = Affix::try_from((&name as &str, value, &value_type as &str))?;
-
-        secondary_affixes[index] = Some(affix);
-
-        Ok(())
-      } else {
-        let disk = Disk::new(
-          env.1,
-          env.2,
-          env.3.clone(),
-          secondary_affixes[0].take(),
-          secondary_affixes[1].take(),
-          secondary_affixes[2].take(),
-          secondary_affixes[3].take()
-        )?;
-
-        env.4.insert(env.0, disk);
-
-        return Err("no more secondary affixes".to_string());
-      }
-    }
+    let enhanced = disk(&game_data, 4.8, 19.0, 3);
+    assert_eq!(
+      sweep(&connection, &game_data, slice::from_ref(&enhanced)),
+      [Saved::Updated(id)]
+    );
+    let mut statement =
+      prepare(&connection, "SELECT level FROM disks WHERE id = ?", &[Value::Integer(id)])
+        .unwrap();
+    assert!(next_row(&mut statement).unwrap());
+    assert_eq!(statement.read::<Option<i64>, _>("level").unwrap(), Some(3));
+    assert_eq!(sweep(&connection, &game_data, &[enhanced]), [Saved::Skipped(id)]);
 
-    for i in 0 .. 4 {
-      if let Err(error) = read_secondary_affixes_and_save_if_fail(
-        &mut statement,
-        &mut secondary_affixes,
-        i,
-        (id, set, slot, &primary_affix, &mut result)
-      ) {
-        if error == "no more secondary affixes" {
-          break;
-        } else {
-          return Err(error);
-        }
-      }
-    }
+    // A lower value can't come from enhancing the stored disk
+    let other = disk(&game_data, 2.4, 19.0, 6);
+    assert!(matches!(sweep(&connection, &game_data, &[other])[..], [Saved::Inserted(_)]));
   }
 
-  Ok(result)
+  #[test]
+  fn duplicates_within_a_session_are_saved_apart() {
+    let game_data = GameData::bundled();
+    let connection = open(Path::new(":memory:")).unwrap();
+    let copy = disk(&game_data, 2.4, 19.0, 0);
+
+    let saved = sweep(&connection, &game_data, &[copy.clone(), copy.clone()]);
+    let [Saved::Inserted(first), Saved::Inserted(second)] = saved[..] else {
+      panic!("the copies weren't both inserted: {:?}", saved);
+    };
+    assert_ne!(first, second);
+
+    let saved = sweep(&connection, &game_data, &[copy.clone(), copy.clone()]);
+    assert_eq!(saved, [Saved::Skipped(first), Saved::Skipped(second)]);
+
+    // The enhanced copy is matched to the second one rather than saved again
+    let enhanced = disk(&game_data, 2.4, 38.0, 3);
+    let saved = sweep(&connection, &game_data, &[copy, enhanced]);
+    assert_eq!(saved, [Saved::Skipped(first), Saved::Updated(second)]);
+  }
 }
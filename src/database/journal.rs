@@ -0,0 +1,663 @@
+use {
+  crate::{
+    database::database::{
+      execute,
+      last_insert_id,
+      next_row,
+      now,
+      prepare,
+      transaction,
+      with_connection,
+      AffixRow,
+      DiskRow
+    },
+    disk::disk::{Disk, EquippedBy},
+    source::game_data::GameData
+  },
+  serde::{Deserialize, Serialize},
+  sqlite::{Connection, Value},
+  std::fmt::Display
+};
+
+/// A change made to a stored disk.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Operation {
+  Insert,
+  Update,
+  Delete
+}
+
+impl TryFrom<&str> for Operation {
+  type Error = String;
+
+  fn try_from(value: &str) -> Result<Self, Self::Error> {
+    match value {
+      "insert" => Ok(Self::Insert),
+      "update" => Ok(Self::Update),
+      "delete" => Ok(Self::Delete),
+      _ => Err(format!("Invalid operation: {}", value))
+    }
+  }
+}
+
+impl Display for Operation {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let str = match self {
+      Self::Insert => "insert",
+      Self::Update => "update",
+      Self::Delete => "delete"
+    };
+    write!(f, "{}", str)
+  }
+}
+
+/// One change in the journal of an account.
+#[derive(Clone, Debug)]
+pub struct JournalEntry {
+  pub id: i64,
+  pub disk_id: i64,
+  pub operation: Operation,
+  pub created_at: i64,
+  /// Set while the change is undone, until it is redone.
+  pub undone_at: Option<i64>
+}
+
+/// The stored state of a disk, kept in the journal as JSON so that updates
+/// can be undone and redone without the game data.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct DiskSnapshot {
+  set: String,
+  slot: u8,
+  equipped_by: Option<String>,
+  equipped_by_raw: Option<String>,
+  rarity: Option<String>,
+  level: Option<u8>,
+  locked: bool,
+  trashed: bool,
+  affixes: Vec<AffixSnapshot>
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+struct AffixSnapshot {
+  position: i64,
+  kind: String,
+  stat: String,
+  value: f64,
+  value_type: String,
+  rolls: Option<u32>
+}
+
+impl DiskSnapshot {
+  pub fn new(game_data: &GameData, disk: &Disk) -> Self {
+    let (equipped_by, equipped_by_raw) = match disk.equipped_by.as_ref() {
+      Some(EquippedBy::Agent(id)) => (Some(id.clone()), None),
+      Some(EquippedBy::Unknown(raw)) => (None, Some(raw.clone())),
+      None => (None, None)
+    };
+
+    let primary = Some((0, "primary", &disk.primary_affix, None));
+    let secondary =
+      disk.secondary_affixes.iter().enumerate().filter_map(|(index, affix)| {
+        affix
+          .as_ref()
+          .map(|affix| (index as i64 + 1, "secondary", affix, affix.rolls(game_data)))
+      });
+    let affixes = primary
+      .into_iter()
+      .chain(secondary)
+      .map(|(position, kind, affix, rolls)| {
+        AffixSnapshot {
+          position,
+          kind: kind.to_string(),
+          stat: affix.stat.clone(),
+          value: affix.value,
+          value_type: affix.value_type.to_string(),
+          rolls
+        }
+      })
+      .collect();
+
+    Self {
+      set: disk.set.clone(),
+      slot: disk.slot,
+      equipped_by,
+      equipped_by_raw,
+      rarity: disk.rarity.map(|rarity| rarity.to_string()),
+      level: disk.level,
+      locked: disk.locked,
+      trashed: disk.trashed,
+      affixes
+    }
+  }
+
+  /// The rows the snapshot would be stored as for the disk `id`, so that it
+  /// is built into a disk the same way as the stored ones.
+  pub fn to_rows(&self, id: i64) -> (DiskRow, Vec<AffixRow>) {
+    let equipped_by = match (self.equipped_by.as_ref(), self.equipped_by_raw.as_ref()) {
+      (Some(agent), _) => Some(EquippedBy::Agent(agent.clone())),
+      (None, Some(raw)) => Some(EquippedBy::Unknown(raw.clone())),
+      (None, None) => None
+    };
+    let row = DiskRow {
+      id,
+      set: self.set.clone(),
+      slot: self.slot.into(),
+      equipped_by,
+      rarity: self.rarity.clone(),
+      level: self.level.map(i64::from),
+      locked: self.locked,
+      trashed: self.trashed,
+      scanned_at: None
+    };
+
+    let affixes = self
+      .affixes
+      .iter()
+      .map(|affix| {
+        AffixRow {
+          disk_id: id,
+          position: affix.position,
+          kind: affix.kind.clone(),
+          stat: affix.stat.clone(),
+          value: affix.value,
+          value_type: affix.value_type.clone()
+        }
+      })
+      .collect();
+
+    (row, affixes)
+  }
+
+  /// Reads the state of the disk with ID `id`.
+  pub fn read(connection: &Connection, id: i64) -> Result<Self, String> {
+    let mut statement = prepare(
+      connection,
+      "SELECT set_id, slot, equipped_by, equipped_by_raw, rarity, level, locked, \
+       trashed FROM disks WHERE id = ?",
+      &[Value::Integer(id)]
+    )?;
+    if !next_row(&mut statement)? {
+      return Err(format!("No disk {}", id));
+    }
+
+    let (
+      Ok(set),
+      Ok(slot),
+      Ok(equipped_by),
+      Ok(equipped_by_raw),
+      Ok(rarity),
+      Ok(level),
+      Ok(locked),
+      Ok(trashed)
+    ) = (
+      statement.read::<String, _>("set_id"),
+      statement.read::<i64, _>("slot"),
+      statement.read::<Option<String>, _>("equipped_by"),
+      statement.read::<Option<String>, _>("equipped_by_raw"),
+      statement.read::<Option<String>, _>("rarity"),
+      statement.read::<Option<i64>, _>("level"),
+      statement.read::<i64, _>("locked"),
+      statement.read::<i64, _>("trashed")
+    )
+    else {
+      return Err(format!("Failed to read disk {}", id));
+    };
+
+    let mut statement = prepare(
+      connection,
+      "SELECT position, kind, stat, value, value_type, rolls FROM affixes WHERE disk_id \
+       = ? ORDER BY position",
+      &[Value::Integer(id)]
+    )?;
+    let mut affixes = vec![];
+    while next_row(&mut statement)? {
+      if let (Ok(position), Ok(kind), Ok(stat), Ok(value), Ok(value_type), Ok(rolls)) = (
+        statement.read::<i64, _>("position"),
+        statement.read::<String, _>("kind"),
+        statement.read::<String, _>("stat"),
+        statement.read::<f64, _>("value"),
+        statement.read::<String, _>("value_type"),
+        statement.read::<Option<i64>, _>("rolls")
+      ) {
+        let rolls = rolls.map(|rolls| rolls as u32);
+        affixes.push(AffixSnapshot { position, kind, stat, value, value_type, rolls });
+      } else {
+        return Err(format!("Failed to read the affixes of disk {}", id));
+      }
+    }
+
+    Ok(Self {
+      set,
+      slot: slot as u8,
+      equipped_by,
+      equipped_by_raw,
+      rarity,
+      level: level.map(|level| level as u8),
+      locked: locked != 0,
+      trashed: trashed != 0,
+      affixes
+    })
+  }
+
+  /// Saves the disk to the inventory of `account` and returns its ID.
+  pub fn insert(&self, connection: &Connection, account: &str) -> Result<i64, String> {
+    execute(
+      connection,
+      r#"
+      INSERT INTO disks (account, set_id, slot, scanned_at)
+      VALUES (:account, :set_id, :slot, :scanned_at);
+      "#,
+      &[
+        (":account", Value::String(account.to_string())),
+        (":set_id", Value::String(self.set.clone())),
+        (":slot", Value::Integer(self.slot.into())),
+        (":scanned_at", Value::Integer(now()))
+      ]
+    )?;
+    let id = last_insert_id(connection)?;
+    self.write(connection, id)?;
+    Ok(id)
+  }
+
+  /// Makes the disk with ID `id` match the snapshot, replacing its affixes.
+  pub fn write(&self, connection: &Connection, id: i64) -> Result<(), String> {
+    let optional_string = |value: &Option<String>| {
+      value.as_ref().map_or(Value::Null, |value| Value::String(value.clone()))
+    };
+
+    execute(
+      connection,
+      r#"
+      UPDATE disks SET
+        set_id = :set_id,
+        slot = :slot,
+        equipped_by = :equipped_by,
+        equipped_by_raw = :equipped_by_raw,
+        rarity = :rarity,
+        level = :level,
+        locked = :locked,
+        trashed = :trashed
+      WHERE id = :id;
+      "#,
+      &[
+        (":id", Value::Integer(id)),
+        (":set_id", Value::String(self.set.clone())),
+        (":slot", Value::Integer(self.slot.into())),
+        (":equipped_by", optional_string(&self.equipped_by)),
+        (":equipped_by_raw", optional_string(&self.equipped_by_raw)),
+        (":rarity", optional_string(&self.rarity)),
+        (":level", self.level.map_or(Value::Null, |level| Value::Integer(level.into()))),
+        (":locked", Value::Integer(self.locked.into())),
+        (":trashed", Value::Integer(self.trashed.into()))
+      ]
+    )?;
+
+    execute(
+      connection,
+      "DELETE FROM affixes WHERE disk_id = :disk_id",
+      &[(":disk_id", Value::Integer(id))]
+    )?;
+    for affix in self.affixes.iter() {
+      execute(
+        connection,
+        r#"
+        INSERT INTO affixes (disk_id, position, kind, stat, value, value_type, rolls)
+        VALUES (:disk_id, :position, :kind, :stat, :value, :value_type, :rolls);
+        "#,
+        &[
+          (":disk_id", Value::Integer(id)),
+          (":position", Value::Integer(affix.position)),
+          (":kind", Value::String(affix.kind.clone())),
+          (":stat", Value::String(affix.stat.clone())),
+          (":value", Value::Float(affix.value)),
+          (":value_type", Value::String(affix.value_type.clone())),
+          (
+            ":rolls",
+            affix.rolls.map_or(Value::Null, |rolls| Value::Integer(rolls.into()))
+          )
+        ]
+      )?;
+    }
+
+    Ok(())
+  }
+
+  /// Whether both snapshots have the same details besides the affixes.
+  pub fn same_details(&self, other: &Self) -> bool {
+    self.equipped_by == other.equipped_by
+      && self.equipped_by_raw == other.equipped_by_raw
+      && self.rarity == other.rarity
+      && self.level == other.level
+      && self.locked == other.locked
+      && self.trashed == other.trashed
+  }
+
+  /// Keeps the rarity and level of `stored` where they weren't read.
+  pub fn fill_unread(&mut self, stored: &Self) {
+    self.rarity = self.rarity.take().or(stored.rarity.clone());
+    self.level = self.level.or(stored.level);
+  }
+
+  pub fn to_json(&self) -> Result<String, String> {
+    serde_json::to_string(self)
+      .map_err(|error| format!("Failed to serialize disk snapshot: {}", error))
+  }
+
+  pub fn from_json(json: &str) -> Result<Self, String> {
+    serde_json::from_str(json)
+      .map_err(|error| format!("Invalid disk snapshot: {}", error))
+  }
+}
+
+/// Journals a change to a disk, with its state before and after for updates.
+/// Changes undone before are forgotten, as they can't be redone any more.
+pub(crate) fn record(
+  connection: &Connection,
+  account: &str,
+  disk_id: i64,
+  operation: Operation,
+  snapshots: Option<(&DiskSnapshot, &DiskSnapshot)>
+) -> Result<(), String> {
+  execute(
+    connection,
+    "DELETE FROM journal WHERE account = :account AND undone_at IS NOT NULL",
+    &[(":account", Value::String(account.to_string()))]
+  )?;
+
+  let (before, after) = match snapshots {
+    Some((before, after)) => {
+      (Value::String(before.to_json()?), Value::String(after.to_json()?))
+    },
+    None => (Value::Null, Value::Null)
+  };
+  execute(
+    connection,
+    r#"
+    INSERT INTO journal (account, disk_id, operation, before, after, created_at)
+    VALUES (:account, :disk_id, :operation, :before, :after, :created_at);
+    "#,
+    &[
+      (":account", Value::String(account.to_string())),
+      (":disk_id", Value::Integer(disk_id)),
+      (":operation", Value::String(operation.to_string())),
+      (":before", before),
+      (":after", after),
+      (":created_at", Value::Integer(now()))
+    ]
+  )
+}
+
+/// A journal entry with the snapshots needed to undo or redo it.
+struct StoredEntry {
+  entry: JournalEntry,
+  before: Option<String>,
+  after: Option<String>
+}
+
+fn read_entries(
+  connection: &Connection,
+  filter: &str,
+  parameters: &[Value]
+) -> Result<Vec<StoredEntry>, String> {
+  let mut statement = prepare(
+    connection,
+    &format!(
+      "SELECT id, disk_id, operation, before, after, created_at, undone_at FROM journal \
+       WHERE {}",
+      filter
+    ),
+    parameters
+  )?;
+
+  let mut result = vec![];
+
+  while next_row(&mut statement)? {
+    if let (
+      Ok(id),
+      Ok(disk_id),
+      Ok(operation),
+      Ok(before),
+      Ok(after),
+      Ok(created_at),
+      Ok(undone_at)
+    ) = (
+      statement.read::<i64, _>("id"),
+      statement.read::<i64, _>("disk_id"),
+      statement.read::<String, _>("operation"),
+      statement.read::<Option<String>, _>("before"),
+      statement.read::<Option<String>, _>("after"),
+      statement.read::<i64, _>("created_at"),
+      statement.read::<Option<i64>, _>("undone_at")
+    ) {
+      let operation = Operation::try_from(&operation as &str)?;
+      let entry = JournalEntry { id, disk_id, operation, created_at, undone_at };
+      result.push(StoredEntry { entry, before, after });
+    } else {
+      return Err("Failed to read journal entry".to_string());
+    }
+  }
+
+  Ok(result)
+}
+
+fn set_deleted(
+  connection: &Connection,
+  disk_id: i64,
+  deleted: bool
+) -> Result<(), String> {
+  execute(
+    connection,
+    "UPDATE disks SET deleted_at = :deleted_at WHERE id = :id",
+    &[
+      (":id", Value::Integer(disk_id)),
+      (":deleted_at", if deleted { Value::Integer(now()) } else { Value::Null })
+    ]
+  )
+}
+
+fn set_undone(
+  connection: &Connection,
+  entry: &mut JournalEntry,
+  undone: bool
+) -> Result<(), String> {
+  entry.undone_at = undone.then(now);
+  execute(
+    connection,
+    "UPDATE journal SET undone_at = :undone_at WHERE id = :id",
+    &[
+      (":id", Value::Integer(entry.id)),
+      (":undone_at", entry.undone_at.map_or(Value::Null, Value::Integer))
+    ]
+  )
+}
+
+fn snapshot(json: Option<&String>, entry: &JournalEntry) -> Result<DiskSnapshot, String> {
+  match json {
+    Some(json) => DiskSnapshot::from_json(json),
+    None => Err(format!("Journal entry {} has no snapshot", entry.id))
+  }
+}
+
+/// Undoes the last `count` changes to the disks of `account` that are not
+/// undone yet, newest first, and returns them.
+pub async fn undo(account: &str, count: u32) -> Result<Vec<JournalEntry>, String> {
+  let account = account.to_string();
+  with_connection(move |connection| {
+    transaction(connection, || undo_changes(connection, &account, count))
+  })
+  .await
+}
+
+fn undo_changes(
+  connection: &Connection,
+  account: &str,
+  count: u32
+) -> Result<Vec<JournalEntry>, String> {
+  let mut entries = read_entries(
+    connection,
+    "account = ? AND undone_at IS NULL ORDER BY id DESC LIMIT ?",
+    &[Value::String(account.to_string()), Value::Integer(count.into())]
+  )?;
+
+  for stored in entries.iter_mut() {
+    let entry = &mut stored.entry;
+    match entry.operation {
+      Operation::Insert => set_deleted(connection, entry.disk_id, true)?,
+      Operation::Update => {
+        snapshot(stored.before.as_ref(), entry)?.write(connection, entry.disk_id)?
+      },
+      Operation::Delete => set_deleted(connection, entry.disk_id, false)?
+    }
+    set_undone(connection, entry, true)?;
+  }
+
+  Ok(entries.into_iter().map(|stored| stored.entry).collect())
+}
+
+/// Redoes the last `count` undone changes to the disks of `account`, in the
+/// order they were first made, and returns them.
+pub async fn redo(account: &str, count: u32) -> Result<Vec<JournalEntry>, String> {
+  let account = account.to_string();
+  with_connection(move |connection| {
+    transaction(connection, || redo_changes(connection, &account, count))
+  })
+  .await
+}
+
+fn redo_changes(
+  connection: &Connection,
+  account: &str,
+  count: u32
+) -> Result<Vec<JournalEntry>, String> {
+  let mut entries = read_entries(
+    connection,
+    "account = ? AND undone_at IS NOT NULL ORDER BY id ASC LIMIT ?",
+    &[Value::String(account.to_string()), Value::Integer(count.into())]
+  )?;
+
+  for stored in entries.iter_mut() {
+    let entry = &mut stored.entry;
+    match entry.operation {
+      Operation::Insert => set_deleted(connection, entry.disk_id, false)?,
+      Operation::Update => {
+        snapshot(stored.after.as_ref(), entry)?.write(connection, entry.disk_id)?
+      },
+      Operation::Delete => set_deleted(connection, entry.disk_id, true)?
+    }
+    set_undone(connection, entry, false)?;
+  }
+
+  Ok(entries.into_iter().map(|stored| stored.entry).collect())
+}
+
+/// Reads the last `limit` changes to the disks of `account`, newest first.
+pub async fn read_journal(
+  account: &str,
+  limit: u32
+) -> Result<Vec<JournalEntry>, String> {
+  let account = account.to_string();
+  with_connection(move |connection| {
+    let entries = read_entries(
+      connection,
+      "account = ? ORDER BY id DESC LIMIT ?",
+      &[Value::String(account), Value::Integer(limit.into())]
+    )?;
+    Ok(entries.into_iter().map(|stored| stored.entry).collect())
+  })
+  .await
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    crate::{
+      database::database::{open, save_disk},
+      disk::disk::Affix
+    },
+    std::{collections::HashSet, path::Path}
+  };
+
+  fn disk(game_data: &GameData, set: &str, crit_rate: f64, level: u8) -> Disk {
+    let affix = |stat: &str, value: f64, value_type: &str| {
+      Affix::parse(game_data, stat, value, value_type).unwrap()
+    };
+    Disk::new(
+      game_data,
+      set,
+      1,
+      affix("hp", 550.0, "flat"),
+      Some(affix("crit_rate", crit_rate, "percentage")),
+      Some(affix("atk", 19.0, "flat")),
+      None,
+      None
+    )
+    .unwrap()
+    .with_level(Some(level))
+    .unwrap()
+  }
+
+  fn deleted(connection: &Connection, id: i64) -> bool {
+    let mut statement = prepare(
+      connection,
+      "SELECT deleted_at FROM disks WHERE id = ?",
+      &[Value::Integer(id)]
+    )
+    .unwrap();
+    assert!(next_row(&mut statement).unwrap());
+    statement.read::<Option<i64>, _>("deleted_at").unwrap().is_some()
+  }
+
+  fn ids(entries: &[JournalEntry]) -> Vec<(i64, Operation)> {
+    entries.iter().map(|entry| (entry.disk_id, entry.operation)).collect()
+  }
+
+  #[test]
+  fn undoes_newest_first_and_redoes_oldest_first() {
+    let game_data = GameData::bundled();
+    let connection = open(Path::new(":memory:")).unwrap();
+    let save = |disk: &Disk| {
+      save_disk(&connection, &game_data, "default", disk, &HashSet::new()).unwrap().id()
+    };
+
+    let first = save(&disk(&game_data, "chaos_jazz", 2.4, 0));
+    let second = save(&disk(&game_data, "woodpecker_electro", 2.4, 0));
+    assert_eq!(save(&disk(&game_data, "chaos_jazz", 4.8, 3)), first);
+
+    let undone = undo_changes(&connection, "default", 2).unwrap();
+    assert_eq!(ids(&undone), [(first, Operation::Update), (second, Operation::Insert)]);
+    assert!(undone.iter().all(|entry| entry.undone_at.is_some()));
+    assert_eq!(DiskSnapshot::read(&connection, first).unwrap().level, Some(0));
+    assert!(deleted(&connection, second));
+
+    let redone = redo_changes(&connection, "default", 1).unwrap();
+    assert_eq!(ids(&redone), [(second, Operation::Insert)]);
+    assert!(!deleted(&connection, second));
+    assert_eq!(DiskSnapshot::read(&connection, first).unwrap().level, Some(0));
+
+    let redone = redo_changes(&connection, "default", 5).unwrap();
+    assert_eq!(ids(&redone), [(first, Operation::Update)]);
+    assert_eq!(DiskSnapshot::read(&connection, first).unwrap().level, Some(3));
+    assert!(redo_changes(&connection, "default", 1).unwrap().is_empty());
+  }
+
+  #[test]
+  fn new_changes_forget_undone_ones() {
+    let game_data = GameData::bundled();
+    let connection = open(Path::new(":memory:")).unwrap();
+    let save = |disk: &Disk| {
+      save_disk(&connection, &game_data, "default", disk, &HashSet::new()).unwrap().id()
+    };
+
+    let first = save(&disk(&game_data, "chaos_jazz", 2.4, 0));
+    undo_changes(&connection, "default", 1).unwrap();
+    assert!(deleted(&connection, first));
+
+    let second = save(&disk(&game_data, "woodpecker_electro", 2.4, 0));
+    assert!(redo_changes(&connection, "default", 1).unwrap().is_empty());
+    assert!(deleted(&connection, first));
+
+    let undone = undo_changes(&connection, "default", 5).unwrap();
+    assert_eq!(ids(&undone), [(second, Operation::Insert)]);
+  }
+}
@@ -0,0 +1,563 @@
+use {
+  crate::database::database::{next_row, now, transaction},
+  sqlite::{Connection, State, Value}
+};
+
+/// One step of the schema, applied once to every database in order of
+/// `version`.
+pub struct Migration {
+  pub version: i64,
+  pub name: &'static str,
+  pub sql: &'static str
+}
+
+/// Every schema change so far. New migrations are appended with the next
+/// version and never edited once released, as databases record which
+/// versions they already have.
+pub const MIGRATIONS: &[Migration] = &[
+  Migration {
+    version: 1,
+    name: "initial",
+    sql: r#"
+      CREATE TABLE disks (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        set_id TEXT NOT NULL,
+        slot INTEGER NOT NULL,
+        primary_affix_name TEXT NOT NULL,
+        primary_affix_value REAL NOT NULL,
+        primary_affix_value_type TEXT NOT NULL,
+        secondary_affix_1_name TEXT,
+        secondary_affix_1_value REAL,
+        secondary_affix_1_value_type TEXT,
+        secondary_affix_2_name TEXT,
+        secondary_affix_2_value REAL,
+        secondary_affix_2_value_type TEXT,
+        secondary_affix_3_name TEXT,
+        secondary_affix_3_value REAL,
+        secondary_affix_3_value_type TEXT,
+        secondary_affix_4_name TEXT,
+        secondary_affix_4_value REAL,
+        secondary_affix_4_value_type TEXT,
+        equipped_by TEXT,
+        equipped_by_raw TEXT
+      );
+    "#
+  },
+  Migration {
+    version: 2,
+    name: "normalized_affixes",
+    // Affixes move to their own table, the primary affix at position 0 and
+    // the secondary affixes at 1 to 4. Roll counts are left unknown for
+    // existing affixes, as they depend on the game data.
+    sql: r#"
+      CREATE TABLE affixes (
+        disk_id INTEGER NOT NULL REFERENCES disks (id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        kind TEXT NOT NULL CHECK (kind IN ('primary', 'secondary')),
+        stat TEXT NOT NULL,
+        value REAL NOT NULL,
+        value_type TEXT NOT NULL,
+        rolls INTEGER,
+        PRIMARY KEY (disk_id, position)
+      );
+
+      CREATE INDEX affixes_stat ON affixes (stat, value);
+
+      INSERT INTO affixes (disk_id, position, kind, stat, value, value_type)
+      SELECT
+        id,
+        0,
+        'primary',
+        primary_affix_name,
+        primary_affix_value,
+        primary_affix_value_type
+      FROM disks;
+
+      INSERT INTO affixes (disk_id, position, kind, stat, value, value_type)
+      SELECT
+        id,
+        1,
+        'secondary',
+        secondary_affix_1_name,
+        secondary_affix_1_value,
+        secondary_affix_1_value_type
+      FROM disks
+      WHERE secondary_affix_1_name IS NOT NULL;
+
+      INSERT INTO affixes (disk_id, position, kind, stat, value, value_type)
+      SELECT
+        id,
+        2,
+        'secondary',
+        secondary_affix_2_name,
+        secondary_affix_2_value,
+        secondary_affix_2_value_type
+      FROM disks
+      WHERE secondary_affix_2_name IS NOT NULL;
+
+      INSERT INTO affixes (disk_id, position, kind, stat, value, value_type)
+      SELECT
+        id,
+        3,
+        'secondary',
+        secondary_affix_3_name,
+        secondary_affix_3_value,
+        secondary_affix_3_value_type
+      FROM disks
+      WHERE secondary_affix_3_name IS NOT NULL;
+
+      INSERT INTO affixes (disk_id, position, kind, stat, value, value_type)
+      SELECT
+        id,
+        4,
+        'secondary',
+        secondary_affix_4_name,
+        secondary_affix_4_value,
+        secondary_affix_4_value_type
+      FROM disks
+      WHERE secondary_affix_4_name IS NOT NULL;
+
+      CREATE TABLE disks_narrow (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        set_id TEXT NOT NULL,
+        slot INTEGER NOT NULL,
+        equipped_by TEXT,
+        equipped_by_raw TEXT
+      );
+
+      INSERT INTO disks_narrow (id, set_id, slot, equipped_by, equipped_by_raw)
+      SELECT id, set_id, slot, equipped_by, equipped_by_raw FROM disks;
+
+      DROP TABLE disks;
+
+      ALTER TABLE disks_narrow RENAME TO disks;
+    "#
+  },
+  Migration {
+    version: 3,
+    name: "accounts",
+    // Disks saved before accounts existed go to the default account.
+    sql: r#"
+      CREATE TABLE accounts (
+        uid TEXT PRIMARY KEY,
+        name TEXT,
+        created_at INTEGER NOT NULL
+      );
+
+      INSERT INTO accounts (uid, name, created_at)
+      VALUES ('default', NULL, CAST(strftime('%s', 'now') AS INTEGER));
+
+      ALTER TABLE disks ADD COLUMN account TEXT NOT NULL DEFAULT 'default'
+        REFERENCES accounts (uid) ON DELETE CASCADE;
+
+      CREATE INDEX disks_account ON disks (account);
+    "#
+  },
+  Migration {
+    version: 4,
+    name: "disk_details",
+    // Scan dates are unknown for disks saved before they were recorded.
+    sql: r#"
+      ALTER TABLE disks ADD COLUMN rarity TEXT CHECK (rarity IN ('S', 'A', 'B'));
+      ALTER TABLE disks ADD COLUMN level INTEGER CHECK (level BETWEEN 0 AND 15);
+      ALTER TABLE disks ADD COLUMN locked INTEGER NOT NULL DEFAULT 0;
+      ALTER TABLE disks ADD COLUMN trashed INTEGER NOT NULL DEFAULT 0;
+      ALTER TABLE disks ADD COLUMN scanned_at INTEGER;
+
+      CREATE INDEX disks_set_slot ON disks (account, set_id, slot);
+    "#
+  },
+  Migration {
+    version: 5,
+    name: "journal",
+    // Deleted disks stay with their deletion date, so that deletions can be
+    // undone. Updates keep the state of the disk before and after as JSON.
+    sql: r#"
+      ALTER TABLE disks ADD COLUMN deleted_at INTEGER;
+
+      CREATE TABLE journal (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        account TEXT NOT NULL REFERENCES accounts (uid) ON DELETE CASCADE,
+        disk_id INTEGER NOT NULL REFERENCES disks (id) ON DELETE CASCADE,
+        operation TEXT NOT NULL CHECK (operation IN ('insert', 'update', 'delete')),
+        before TEXT,
+        after TEXT,
+        created_at INTEGER NOT NULL,
+        undone_at INTEGER
+      );
+
+      CREATE INDEX journal_account ON journal (account, id);
+    "#
+  },
+  Migration {
+    version: 6,
+    name: "provenance",
+    // Records outlive the disks they link to, which are only soft deleted
+    // anyway. Crops are PNG files, left out unless asked for.
+    sql: r#"
+      CREATE TABLE scan_sessions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        account TEXT NOT NULL REFERENCES accounts (uid) ON DELETE CASCADE,
+        started_at INTEGER NOT NULL,
+        language TEXT,
+        font TEXT,
+        layout TEXT,
+        input TEXT
+      );
+
+      CREATE TABLE scan_records (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        session_id INTEGER NOT NULL REFERENCES scan_sessions (id) ON DELETE CASCADE,
+        disk_id INTEGER REFERENCES disks (id) ON DELETE SET NULL,
+        outcome TEXT NOT NULL CHECK (outcome IN ('inserted', 'updated', 'skipped')),
+        scanned_at INTEGER NOT NULL,
+        source_path TEXT,
+        source_hash TEXT,
+        layout TEXT
+      );
+
+      CREATE INDEX scan_records_disk ON scan_records (disk_id);
+
+      CREATE TABLE scan_regions (
+        record_id INTEGER NOT NULL REFERENCES scan_records (id) ON DELETE CASCADE,
+        region TEXT NOT NULL,
+        raw TEXT NOT NULL,
+        matched TEXT,
+        confidence REAL,
+        correction TEXT,
+        crop BLOB,
+        PRIMARY KEY (record_id, region)
+      );
+    "#
+  },
+  Migration {
+    version: 7,
+    name: "quarantine",
+    // Disks that don't fit the game data stay where they are, listed here so
+    // reads skip them until they are repaired.
+    sql: r#"
+      CREATE TABLE quarantine (
+        disk_id INTEGER PRIMARY KEY REFERENCES disks (id) ON DELETE CASCADE,
+        error TEXT NOT NULL,
+        quarantined_at INTEGER NOT NULL
+      );
+    "#
+  },
+  Migration {
+    version: 8,
+    name: "snapshots",
+    // Snapshots keep the state of each disk as JSON, so they stay as they
+    // were taken when disks are changed or deleted later.
+    sql: r#"
+      CREATE TABLE snapshots (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        account TEXT NOT NULL REFERENCES accounts (uid) ON DELETE CASCADE,
+        name TEXT,
+        session_id INTEGER REFERENCES scan_sessions (id) ON DELETE SET NULL,
+        taken_at INTEGER NOT NULL
+      );
+
+      CREATE INDEX snapshots_account ON snapshots (account, id);
+
+      CREATE TABLE snapshot_disks (
+        snapshot_id INTEGER NOT NULL REFERENCES snapshots (id) ON DELETE CASCADE,
+        disk_id INTEGER NOT NULL,
+        state TEXT NOT NULL,
+        PRIMARY KEY (snapshot_id, disk_id)
+      );
+    "#
+  },
+  Migration {
+    version: 9,
+    name: "aliases",
+    // Aliases correct text to words of one client language, so the locale is
+    // part of their key.
+    sql: r#"
+      CREATE TABLE aliases (
+        locale TEXT NOT NULL,
+        scope TEXT NOT NULL,
+        raw TEXT NOT NULL,
+        canonical TEXT NOT NULL,
+        updated_at INTEGER NOT NULL,
+        PRIMARY KEY (locale, scope, raw)
+      );
+    "#
+  }
+];
+
+/// Records the migrations applied to a database.
+const SCHEMA_VERSION_TABLE: &str = r#"
+  CREATE TABLE IF NOT EXISTS schema_version (
+    version INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    applied_at INTEGER NOT NULL
+  );
+"#;
+
+/// The version a database is at once every migration was applied.
+pub fn latest_version() -> i64 {
+  MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+/// Returns the version of the schema of a database, 0 for one that was
+/// never migrated.
+pub fn schema_version(connection: &Connection) -> Result<i64, String> {
+  if !table_exists(connection, "schema_version")? {
+    return Ok(0);
+  }
+
+  let mut statement = connection
+    .prepare("SELECT COALESCE(MAX(version), 0) AS version FROM schema_version")
+    .map_err(|error| format!("Failed to prepare statement: {}", error))?;
+  match statement.next() {
+    Ok(State::Row) => {
+      statement
+        .read::<i64, _>("version")
+        .map_err(|error| format!("Failed to read schema version: {}", error))
+    },
+    Ok(State::Done) => Ok(0),
+    Err(error) => Err(format!("Failed to read schema version: {}", error))
+  }
+}
+
+/// Brings the schema of a database up to date, applying the migrations it
+/// doesn't have yet in one transaction, so a failed migration leaves the
+/// database as it was. Returns the migrations that were applied.
+pub fn migrate(connection: &Connection) -> Result<Vec<&'static Migration>, String> {
+  transaction(connection, || apply_pending(connection))
+}
+
+fn apply_pending(connection: &Connection) -> Result<Vec<&'static Migration>, String> {
+  connection
+    .execute(SCHEMA_VERSION_TABLE)
+    .map_err(|error| format!("Failed to create table: {}", error))?;
+
+  let current = schema_version(connection)?;
+  if current > latest_version() {
+    return Err(format!(
+      "Database schema version {} is newer than the latest supported version {}",
+      current,
+      latest_version()
+    ));
+  }
+
+  // Versions before migrations kept learned aliases in a table of their own,
+  // which is set aside until the migrations create the current one
+  let legacy_aliases = current == 0 && table_exists(connection, "aliases")?;
+  if legacy_aliases {
+    connection
+      .execute("ALTER TABLE aliases RENAME TO legacy_aliases")
+      .map_err(|error| format!("Failed to set aside legacy aliases: {}", error))?;
+  }
+
+  let mut applied = vec![];
+  for migration in MIGRATIONS.iter().filter(|migration| migration.version > current) {
+    connection.execute(migration.sql).map_err(|error| {
+      format!(
+        "Failed to apply migration {} ({}): {}",
+        migration.version, migration.name, error
+      )
+    })?;
+
+    let mut statement = connection
+      .prepare(
+        "INSERT INTO schema_version (version, name, applied_at) VALUES (:version, \
+         :name, :applied_at)"
+      )
+      .map_err(|error| format!("Failed to prepare statement: {}", error))?;
+    statement
+      .bind::<&[(_, Value)]>(
+        &[
+          (":version", Value::Integer(migration.version)),
+          (":name", Value::String(migration.name.to_string())),
+          (":applied_at", Value::Integer(now()))
+        ][..]
+      )
+      .map_err(|error| format!("Failed to bind schema version: {}", error))?;
+    match statement.next() {
+      Ok(State::Done) => (),
+      Ok(State::Row) => return Err("Unexpected row".to_string()),
+      Err(error) => return Err(format!("Failed to execute statement: {}", error))
+    }
+
+    applied.push(migration);
+  }
+
+  // Those aliases were learned for Simplified Chinese, the only language
+  // supported then
+  if legacy_aliases {
+    connection
+      .execute(
+        r#"
+        INSERT INTO aliases (locale, scope, raw, canonical, updated_at)
+        SELECT 'zh-CN', scope, raw, canonical, updated_at FROM legacy_aliases;
+
+        DROP TABLE legacy_aliases;
+        "#
+      )
+      .map_err(|error| format!("Failed to migrate legacy aliases: {}", error))?;
+  }
+
+  Ok(applied)
+}
+
+fn table_exists(connection: &Connection, name: &str) -> Result<bool, String> {
+  let mut statement = connection
+    .prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name = ?")
+    .map_err(|error| format!("Failed to prepare statement: {}", error))?;
+  statement
+    .bind((1, name))
+    .map_err(|error| format!("Failed to bind table name: {}", error))?;
+  next_row(&mut statement)
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    crate::{
+      database::database::{prepare, read_affix_rows, read_disk_rows, LoadedDisks},
+      source::game_data::GameData
+    }
+  };
+
+  fn count(connection: &Connection, sql: &str) -> i64 {
+    let mut statement = connection.prepare(sql).unwrap();
+    assert!(next_row(&mut statement).unwrap());
+    statement.read::<i64, _>(0).unwrap()
+  }
+
+  /// Every table and index with the SQL that defines it, as SQLite keeps it
+  /// after the changes made to it.
+  fn schema(connection: &Connection) -> Vec<(String, String, String)> {
+    let mut statement = connection
+      .prepare(
+        "SELECT type, name, sql FROM sqlite_master WHERE sql IS NOT NULL AND name NOT \
+         LIKE 'sqlite_%' ORDER BY type, name"
+      )
+      .unwrap();
+    let mut schema = vec![];
+    while next_row(&mut statement).unwrap() {
+      schema.push((
+        statement.read::<String, _>("type").unwrap(),
+        statement.read::<String, _>("name").unwrap(),
+        statement.read::<String, _>("sql").unwrap()
+      ));
+    }
+    schema
+  }
+
+  fn migrated_empty_database() -> Connection {
+    let connection = Connection::open(":memory:").unwrap();
+    migrate(&connection).unwrap();
+    connection
+  }
+
+  #[test]
+  fn migrates_an_empty_database() {
+    let connection = Connection::open(":memory:").unwrap();
+    assert_eq!(schema_version(&connection).unwrap(), 0);
+
+    let applied = migrate(&connection).unwrap();
+    assert_eq!(applied.len(), MIGRATIONS.len());
+    assert_eq!(schema_version(&connection).unwrap(), latest_version());
+    assert_eq!(
+      count(&connection, "SELECT COUNT(*) FROM accounts WHERE uid = 'default'"),
+      1
+    );
+
+    assert!(migrate(&connection).unwrap().is_empty());
+    assert_eq!(schema_version(&connection).unwrap(), latest_version());
+  }
+
+  #[test]
+  fn migrates_a_version_1_database() {
+    let connection = Connection::open(":memory:").unwrap();
+    transaction(&connection, || {
+      connection.execute(SCHEMA_VERSION_TABLE).unwrap();
+      connection.execute(MIGRATIONS[0].sql).unwrap();
+      connection.execute("INSERT INTO schema_version VALUES (1, 'initial', 0)").unwrap();
+      connection
+        .execute(
+          r#"
+          INSERT INTO disks (
+            set_id, slot, primary_affix_name, primary_affix_value,
+            primary_affix_value_type, secondary_affix_1_name, secondary_affix_1_value,
+            secondary_affix_1_value_type, secondary_affix_3_name, secondary_affix_3_value,
+            secondary_affix_3_value_type, equipped_by
+          )
+          VALUES (
+            'chaos_jazz', 1, 'hp', 550, 'flat', 'crit_rate', 2.4, 'percentage', 'atk',
+            19, 'flat', 'anby'
+          );
+          "#
+        )
+        .unwrap();
+      Ok(())
+    })
+    .unwrap();
+    assert_eq!(schema_version(&connection).unwrap(), 1);
+
+    let applied = migrate(&connection).unwrap();
+    assert_eq!(applied.first().map(|migration| migration.version), Some(2));
+    assert_eq!(schema_version(&connection).unwrap(), latest_version());
+
+    let parameters = [Value::String("default".to_string())];
+    let mut statement =
+      prepare(&connection, "SELECT * FROM disks WHERE account = ?", &parameters).unwrap();
+    let rows = read_disk_rows(&mut statement).unwrap();
+    let affixes = read_affix_rows(&connection, "1 = 1", &[]).unwrap();
+    let loaded = LoadedDisks::parse(&GameData::bundled(), rows, affixes);
+    assert!(loaded.errors.is_empty());
+
+    let disk = &loaded.disks[&1];
+    assert_eq!((disk.set.as_str(), disk.slot), ("chaos_jazz", 1));
+    assert_eq!(
+      (disk.primary_affix.stat.as_str(), disk.primary_affix.value),
+      ("hp", 550.0)
+    );
+    let secondary: Vec<_> = disk
+      .secondary_affixes
+      .iter()
+      .map(|affix| affix.as_ref().map(|affix| affix.stat.as_str()))
+      .collect();
+    assert_eq!(secondary, [Some("crit_rate"), None, Some("atk"), None]);
+    assert_eq!((disk.level, disk.locked), (None, false));
+
+    assert_eq!(schema(&connection), schema(&migrated_empty_database()));
+  }
+
+  #[test]
+  fn migrates_the_aliases_of_a_database_without_versions() {
+    let connection = Connection::open(":memory:").unwrap();
+    connection
+      .execute(
+        r#"
+        CREATE TABLE aliases (
+          scope TEXT NOT NULL,
+          raw TEXT NOT NULL,
+          canonical TEXT NOT NULL,
+          updated_at INTEGER NOT NULL,
+          PRIMARY KEY (scope, raw)
+        );
+
+        INSERT INTO aliases VALUES ('set', '混屯爵士', '混沌爵士', 7);
+        "#
+      )
+      .unwrap();
+    assert_eq!(schema_version(&connection).unwrap(), 0);
+
+    assert_eq!(migrate(&connection).unwrap().len(), MIGRATIONS.len());
+    assert_eq!(schema(&connection), schema(&migrated_empty_database()));
+
+    let mut statement = connection.prepare("SELECT * FROM aliases").unwrap();
+    assert!(next_row(&mut statement).unwrap());
+    let row: Vec<String> = ["locale", "scope", "raw", "canonical"]
+      .iter()
+      .map(|column| statement.read::<String, _>(*column).unwrap())
+      .collect();
+    assert_eq!(row, ["zh-CN", "set", "混屯爵士", "混沌爵士"]);
+    assert_eq!(statement.read::<i64, _>("updated_at").unwrap(), 7);
+    assert!(!next_row(&mut statement).unwrap());
+  }
+}
@@ -1 +1,10 @@
-pub mod database;
\ No newline at end of file
+pub mod account;
+pub mod alias;
+#[allow(clippy::module_inception)]
+pub mod database;
+pub mod journal;
+pub mod migration;
+pub mod provenance;
+pub mod quarantine;
+pub mod query;
+pub mod snapshot;
@@ -0,0 +1,595 @@
+use {
+  crate::{
+    database::database::{
+      execute,
+      last_insert_id,
+      next_row,
+      now,
+      prepare,
+      save_disk,
+      transaction,
+      with_connection,
+      Saved,
+      ScanSession
+    },
+    disk::disk::{Disk, EquippedBy},
+    ocr::numeric::RecognizedValue,
+    scan::{
+      layout::Region,
+      profile::ScanProfile,
+      recognize::{RecognizedEquipped, RecognizedWord}
+    },
+    source::game_data::GameData
+  },
+  image::{GrayImage, ImageFormat},
+  sha2::{Digest, Sha256},
+  sqlite::{Connection, Value},
+  std::{
+    collections::HashSet,
+    fs,
+    io::Cursor,
+    path::{Path, PathBuf}
+  }
+};
+
+/// What was read from one region of a screenshot.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegionReading {
+  pub region: Region,
+  /// The text as OCR read it.
+  pub raw: String,
+  /// The word or value the text was taken for, if any.
+  pub matched: Option<String>,
+  /// Confidence of the OCR engine, between 0 and 1.
+  pub confidence: f32,
+  /// How the text was corrected to get the match, if it was.
+  pub correction: Option<String>,
+  /// The region cropped out of the screenshot, as PNG.
+  pub crop: Option<Vec<u8>>
+}
+
+impl RegionReading {
+  pub fn new(region: Region, raw: &str, confidence: f32) -> Self {
+    Self {
+      region,
+      raw: raw.to_string(),
+      matched: None,
+      confidence,
+      correction: None,
+      crop: None
+    }
+  }
+
+  /// The reading of a word region, noting when the word had to be matched
+  /// fuzzily or through an alias.
+  pub fn from_word(region: Region, word: &RecognizedWord) -> Self {
+    let mut reading = Self::new(region, &word.raw, word.confidence);
+    if let Some(matched) = word.word {
+      reading.matched = Some(matched.to_string());
+      if matched != word.raw {
+        reading.correction = Some(format!("{} → {}", word.raw, matched));
+      }
+    }
+    reading
+  }
+
+  /// The reading of a value region, matched to its best candidate and noting
+  /// the corrections that candidate needed.
+  pub fn from_value(region: Region, value: &RecognizedValue) -> Self {
+    let mut reading = Self::new(region, &value.raw, value.confidence);
+    if let Some(candidate) = value.candidates.first() {
+      reading.matched = Some(format!("{} ({})", candidate.value, candidate.value_type));
+      if candidate.penalty > 0 {
+        reading.correction = Some(format!("{} corrections", candidate.penalty));
+      }
+    }
+    reading
+  }
+
+  /// The reading of the equipped line, matched to the ID of the agent it
+  /// names, if known.
+  pub fn from_equipped(region: Region, equipped: &RecognizedEquipped) -> Self {
+    let mut reading = Self::new(region, &equipped.raw, equipped.confidence);
+    if let Some(EquippedBy::Agent(id)) = equipped.equipped_by.as_ref() {
+      reading.matched = Some(id.clone());
+    }
+    reading
+  }
+
+  pub fn with_matched(mut self, matched: &str) -> Self {
+    self.matched = Some(matched.to_string());
+    self
+  }
+
+  pub fn with_correction(mut self, correction: &str) -> Self {
+    self.correction = Some(correction.to_string());
+    self
+  }
+
+  /// Keeps the cropped region with the reading.
+  pub fn with_crop(mut self, crop: &GrayImage) -> Result<Self, String> {
+    let mut png = Cursor::new(vec![]);
+    crop
+      .write_to(&mut png, ImageFormat::Png)
+      .map_err(|error| format!("Failed to encode crop of {}: {}", self.region, error))?;
+    self.crop = Some(png.into_inner());
+    Ok(self)
+  }
+}
+
+/// Where a scanned disk came from and how it was read.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScanRecord {
+  pub source_path: Option<PathBuf>,
+  /// SHA-256 of the source file, in hex.
+  pub source_hash: Option<String>,
+  /// ID of the layout profile the screenshot was read with.
+  pub layout: Option<String>,
+  pub regions: Vec<RegionReading>
+}
+
+impl ScanRecord {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// A record of a screenshot read from `path`, with the hash of the file.
+  pub fn from_file(path: impl AsRef<Path>) -> Result<Self, String> {
+    let path = path.as_ref();
+    let content = fs::read(path)
+      .map_err(|error| format!("Failed to read {}: {}", path.display(), error))?;
+    let hash = Sha256::digest(&content)
+      .iter()
+      .map(|byte| format!("{:02x}", byte))
+      .collect::<String>();
+
+    Ok(Self {
+      source_path: Some(path.to_path_buf()),
+      source_hash: Some(hash),
+      ..Self::default()
+    })
+  }
+
+  pub fn with_layout(mut self, layout: &str) -> Self {
+    self.layout = Some(layout.to_string());
+    self
+  }
+
+  pub fn with_region(mut self, reading: RegionReading) -> Self {
+    self.regions.push(reading);
+    self
+  }
+}
+
+/// A recorded scan of a disk, with the session it was part of.
+#[derive(Clone, Debug)]
+pub struct StoredRecord {
+  pub id: i64,
+  pub session_id: i64,
+  /// The disk the scan created or matched; `None` once it is gone.
+  pub disk_id: Option<i64>,
+  /// `inserted`, `updated` or `skipped`.
+  pub outcome: String,
+  pub scanned_at: i64,
+  pub session_started_at: i64,
+  pub language: Option<String>,
+  pub font: Option<String>,
+  pub input: Option<String>,
+  pub record: ScanRecord
+}
+
+/// Starts a sweep of the inventory of `account` with the settings of
+/// `profile`, recording the scans saved with it.
+pub async fn start_scan_session(
+  account: &str,
+  profile: &ScanProfile
+) -> Result<ScanSession, String> {
+  let account = account.to_string();
+  let profile = profile.clone();
+
+  let id =
+    with_connection(move |connection| insert_session(connection, &account, &profile))
+      .await?;
+
+  Ok(ScanSession { id: Some(id), ..ScanSession::default() })
+}
+
+fn insert_session(
+  connection: &Connection,
+  account: &str,
+  profile: &ScanProfile
+) -> Result<i64, String> {
+  execute(
+    connection,
+    r#"
+    INSERT INTO scan_sessions (account, started_at, language, font, layout, input)
+    VALUES (:account, :started_at, :language, :font, :layout, :input);
+    "#,
+    &[
+      (":account", Value::String(account.to_string())),
+      (":started_at", Value::Integer(now())),
+      (":language", Value::String(profile.language.clone())),
+      (":font", optional_string(profile.font.as_deref())),
+      (":layout", optional_string(profile.layout.as_deref())),
+      (":input", optional_string(profile.input.as_deref()))
+    ]
+  )?;
+  last_insert_id(connection)
+}
+
+fn optional_string(value: Option<&str>) -> Value {
+  value.map_or(Value::Null, |value| Value::String(value.to_string()))
+}
+
+/// Saves a scanned disk as `insert_disk` does, and records how it was read in
+/// `session`, which has to be started with `start_scan_session`.
+pub async fn insert_scanned_disk(
+  game_data: &GameData,
+  account: &str,
+  disk: &Disk,
+  record: &ScanRecord,
+  session: &mut ScanSession
+) -> Result<Saved, String> {
+  let Some(session_id) = session.id else {
+    return Err("The scan session isn't recorded".to_string());
+  };
+  let game_data = game_data.clone();
+  let account = account.to_string();
+  let disk = disk.clone();
+  let seen = session.seen.clone();
+  let record = record.clone();
+
+  let saved = with_connection(move |connection| {
+    transaction(connection, || {
+      save_scanned_disk(
+        connection,
+        &game_data,
+        &account,
+        &disk,
+        &record,
+        session_id,
+        &seen
+      )
+    })
+  })
+  .await?;
+
+  session.seen.insert(saved.id());
+  Ok(saved)
+}
+
+/// Saves a scanned disk as `save_disk` does and records how it was read in
+/// the session `session_id`.
+fn save_scanned_disk(
+  connection: &Connection,
+  game_data: &GameData,
+  account: &str,
+  disk: &Disk,
+  record: &ScanRecord,
+  session_id: i64,
+  seen: &HashSet<i64>
+) -> Result<Saved, String> {
+  let saved = save_disk(connection, game_data, account, disk, seen)?;
+  insert_record(connection, session_id, saved, record)?;
+  Ok(saved)
+}
+
+fn insert_record(
+  connection: &Connection,
+  session_id: i64,
+  saved: Saved,
+  record: &ScanRecord
+) -> Result<(), String> {
+  execute(
+    connection,
+    r#"
+    INSERT INTO scan_records (
+      session_id,
+      disk_id,
+      outcome,
+      scanned_at,
+      source_path,
+      source_hash,
+      layout
+    )
+    VALUES (
+      :session_id,
+      :disk_id,
+      :outcome,
+      :scanned_at,
+      :source_path,
+      :source_hash,
+      :layout
+    );
+    "#,
+    &[
+      (":session_id", Value::Integer(session_id)),
+      (":disk_id", Value::Integer(saved.id())),
+      (":outcome", Value::String(saved.to_string())),
+      (":scanned_at", Value::Integer(now())),
+      (
+        ":source_path",
+        optional_string(
+          record.source_path.as_ref().map(|path| path.to_string_lossy()).as_deref()
+        )
+      ),
+      (":source_hash", optional_string(record.source_hash.as_deref())),
+      (":layout", optional_string(record.layout.as_deref()))
+    ]
+  )?;
+  let record_id = last_insert_id(connection)?;
+
+  for reading in record.regions.iter() {
+    execute(
+      connection,
+      r#"
+      INSERT INTO scan_regions (record_id, region, raw, matched, confidence, correction, crop)
+      VALUES (:record_id, :region, :raw, :matched, :confidence, :correction, :crop);
+      "#,
+      &[
+        (":record_id", Value::Integer(record_id)),
+        (":region", Value::String(reading.region.to_string())),
+        (":raw", Value::String(reading.raw.clone())),
+        (":matched", optional_string(reading.matched.as_deref())),
+        (":confidence", Value::Float(reading.confidence.into())),
+        (":correction", optional_string(reading.correction.as_deref())),
+        (":crop", reading.crop.clone().map_or(Value::Null, Value::Binary))
+      ]
+    )?;
+  }
+
+  Ok(())
+}
+
+/// Reads the recorded scans of the disk `disk_id` of `account`, oldest first.
+pub async fn read_scan_records(
+  account: &str,
+  disk_id: i64
+) -> Result<Vec<StoredRecord>, String> {
+  let account = account.to_string();
+  with_connection(move |connection| read_records(connection, &account, disk_id)).await
+}
+
+fn read_records(
+  connection: &Connection,
+  account: &str,
+  disk_id: i64
+) -> Result<Vec<StoredRecord>, String> {
+  let parameters = vec![Value::Integer(disk_id), Value::String(account.to_string())];
+  let mut statement = prepare(
+    connection,
+    r#"
+    SELECT
+      scan_records.id,
+      scan_records.session_id,
+      scan_records.disk_id,
+      scan_records.outcome,
+      scan_records.scanned_at,
+      scan_records.source_path,
+      scan_records.source_hash,
+      scan_records.layout,
+      scan_sessions.started_at,
+      scan_sessions.language,
+      scan_sessions.font,
+      scan_sessions.input
+    FROM scan_records
+    JOIN scan_sessions ON scan_sessions.id = scan_records.session_id
+    WHERE scan_records.disk_id = ? AND scan_sessions.account = ?
+    ORDER BY scan_records.id
+    "#,
+    &parameters
+  )?;
+
+  let mut result = vec![];
+  while next_row(&mut statement)? {
+    if let (
+      Ok(id),
+      Ok(session_id),
+      Ok(disk_id),
+      Ok(outcome),
+      Ok(scanned_at),
+      Ok(source_path),
+      Ok(source_hash),
+      Ok(layout),
+      Ok(session_started_at),
+      Ok(language),
+      Ok(font),
+      Ok(input)
+    ) = (
+      statement.read::<i64, _>("id"),
+      statement.read::<i64, _>("session_id"),
+      statement.read::<Option<i64>, _>("disk_id"),
+      statement.read::<String, _>("outcome"),
+      statement.read::<i64, _>("scanned_at"),
+      statement.read::<Option<String>, _>("source_path"),
+      statement.read::<Option<String>, _>("source_hash"),
+      statement.read::<Option<String>, _>("layout"),
+      statement.read::<i64, _>("started_at"),
+      statement.read::<Option<String>, _>("language"),
+      statement.read::<Option<String>, _>("font"),
+      statement.read::<Option<String>, _>("input")
+    ) {
+      let record = ScanRecord {
+        source_path: source_path.map(PathBuf::from),
+        source_hash,
+        layout,
+        regions: vec![]
+      };
+      result.push(StoredRecord {
+        id,
+        session_id,
+        disk_id,
+        outcome,
+        scanned_at,
+        session_started_at,
+        language,
+        font,
+        input,
+        record
+      });
+    } else {
+      return Err("Failed to read scan record".to_string());
+    }
+  }
+
+  for stored in result.iter_mut() {
+    stored.record.regions = read_region_readings(connection, stored.id)?;
+  }
+
+  Ok(result)
+}
+
+fn read_region_readings(
+  connection: &Connection,
+  record_id: i64
+) -> Result<Vec<RegionReading>, String> {
+  let mut statement = prepare(
+    connection,
+    "SELECT region, raw, matched, confidence, correction, crop FROM scan_regions WHERE \
+     record_id = ?",
+    &[Value::Integer(record_id)]
+  )?;
+
+  let mut result = vec![];
+  while next_row(&mut statement)? {
+    if let (Ok(region), Ok(raw), Ok(matched), Ok(confidence), Ok(correction), Ok(crop)) = (
+      statement.read::<String, _>("region"),
+      statement.read::<String, _>("raw"),
+      statement.read::<Option<String>, _>("matched"),
+      statement.read::<Option<f64>, _>("confidence"),
+      statement.read::<Option<String>, _>("correction"),
+      statement.read::<Option<Vec<u8>>, _>("crop")
+    ) {
+      result.push(RegionReading {
+        region: Region::try_from(region.as_str())?,
+        raw,
+        matched,
+        confidence: confidence.unwrap_or(0.0) as f32,
+        correction,
+        crop
+      });
+    } else {
+      return Err(format!("Failed to read the regions of scan record {}", record_id));
+    }
+  }
+
+  // Regions in the order of the panel rather than of their names
+  result.sort_by_key(|reading| reading.region);
+  Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    crate::{
+      database::database::open,
+      disk::disk::{Affix, AffixValueType},
+      ocr::numeric::ValueCandidate
+    },
+    image::Luma
+  };
+
+  #[test]
+  fn reads_back_how_a_disk_was_scanned() {
+    let game_data = GameData::bundled();
+    let connection = open(Path::new(":memory:")).unwrap();
+    let profile = ScanProfile {
+      font: Some("global_light".to_string()),
+      input: Some("keyboard_mouse".to_string()),
+      ..ScanProfile::new("zh-CN")
+    };
+    let first = insert_session(&connection, "default", &profile).unwrap();
+    let second =
+      insert_session(&connection, "default", &ScanProfile::new("zh-CN")).unwrap();
+
+    let disk = Disk::new(
+      &game_data,
+      "chaos_jazz",
+      1,
+      Affix::new(&game_data, "hp", 550.0, AffixValueType::Flat).unwrap(),
+      Some(Affix::new(&game_data, "crit_rate", 4.8, AffixValueType::Percentage).unwrap()),
+      None,
+      None,
+      None
+    )
+    .unwrap();
+    let set = RecognizedWord {
+      raw: "混沌爵土".to_string(),
+      word: Some("混沌爵士"),
+      confidence: 0.75
+    };
+    let value = RecognizedValue {
+      raw: "4.896".to_string(),
+      candidates: vec![ValueCandidate {
+        value: 4.8,
+        value_type: AffixValueType::Percentage,
+        penalty: 1
+      }],
+      confidence: 0.5
+    };
+    let crop = GrayImage::from_pixel(4, 2, Luma([200]));
+    let record = ScanRecord {
+      source_path: Some(PathBuf::from("screenshots/1.png")),
+      source_hash: Some("0123abcd".to_string()),
+      ..ScanRecord::new()
+    }
+    .with_layout("16_9")
+    .with_region(
+      RegionReading::from_word(Region::SetName, &set).with_crop(&crop).unwrap()
+    )
+    .with_region(RegionReading::from_value(Region::SecondaryValue(1), &value));
+
+    let saved = save_scanned_disk(
+      &connection,
+      &game_data,
+      "default",
+      &disk,
+      &record,
+      first,
+      &HashSet::new()
+    )
+    .unwrap();
+    let Saved::Inserted(id) = saved else { panic!("{:?}", saved) };
+    let again = ScanRecord::new().with_layout("custom");
+    let saved = save_scanned_disk(
+      &connection,
+      &game_data,
+      "default",
+      &disk,
+      &again,
+      second,
+      &HashSet::new()
+    )
+    .unwrap();
+    assert_eq!(saved, Saved::Skipped(id));
+
+    let records = read_records(&connection, "default", id).unwrap();
+    assert_eq!(records.len(), 2);
+
+    let stored = &records[0];
+    assert_eq!(
+      (stored.session_id, stored.disk_id, stored.outcome.as_str()),
+      (first, Some(id), "inserted")
+    );
+    assert_eq!(stored.language.as_deref(), Some("zh-CN"));
+    assert_eq!(stored.font.as_deref(), Some("global_light"));
+    assert_eq!(stored.input.as_deref(), Some("keyboard_mouse"));
+    assert_eq!(stored.record, record);
+    let regions = &stored.record.regions;
+    assert_eq!(regions[0].correction.as_deref(), Some("混沌爵土 → 混沌爵士"));
+    assert_eq!(regions[1].matched.as_deref(), Some("4.8 (percentage)"));
+    let decoded = image::load_from_memory(regions[0].crop.as_ref().unwrap()).unwrap();
+    assert_eq!(decoded.to_luma8(), crop);
+
+    let stored = &records[1];
+    assert_eq!(
+      (stored.session_id, stored.disk_id, stored.outcome.as_str()),
+      (second, Some(id), "skipped")
+    );
+    assert_eq!((stored.font.as_deref(), stored.input.as_deref()), (None, None));
+    assert_eq!(stored.record, again);
+
+    assert!(read_records(&connection, "other", id).unwrap().is_empty());
+  }
+}
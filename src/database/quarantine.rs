@@ -0,0 +1,132 @@
+use {
+  crate::{
+    database::database::{
+      execute,
+      next_row,
+      now,
+      prepare,
+      read_affix_rows,
+      read_disk_rows,
+      transaction,
+      with_connection,
+      LoadedDisks,
+      RowError
+    },
+    source::game_data::GameData
+  },
+  sqlite::{Connection, Value}
+};
+
+/// A stored disk kept out of reads until it is repaired.
+#[derive(Clone, Debug)]
+pub struct QuarantinedDisk {
+  pub id: i64,
+  /// Why the disk couldn't be read when it was last checked.
+  pub error: String,
+  pub quarantined_at: i64
+}
+
+/// Moves the disks that couldn't be read to the quarantine.
+pub(crate) async fn quarantine_disks(errors: Vec<RowError>) -> Result<(), String> {
+  with_connection(move |connection| {
+    transaction(connection, || write_quarantine(connection, &errors))
+  })
+  .await
+}
+
+fn write_quarantine(connection: &Connection, errors: &[RowError]) -> Result<(), String> {
+  for error in errors.iter() {
+    execute(
+      connection,
+      r#"
+      INSERT INTO quarantine (disk_id, error, quarantined_at)
+      VALUES (:disk_id, :error, :quarantined_at)
+      ON CONFLICT (disk_id) DO UPDATE SET error = excluded.error;
+      "#,
+      &[
+        (":disk_id", Value::Integer(error.id)),
+        (":error", Value::String(error.error.clone())),
+        (":quarantined_at", Value::Integer(now()))
+      ]
+    )?;
+  }
+  Ok(())
+}
+
+/// Reads the quarantined disks of `account`, ordered by ID.
+pub async fn read_quarantine(account: &str) -> Result<Vec<QuarantinedDisk>, String> {
+  let account = account.to_string();
+  with_connection(move |connection| {
+    let mut statement = prepare(
+      connection,
+      "SELECT quarantine.disk_id, quarantine.error, quarantine.quarantined_at FROM \
+       quarantine JOIN disks ON disks.id = quarantine.disk_id WHERE disks.account = ? \
+       ORDER BY quarantine.disk_id",
+      &[Value::String(account)]
+    )?;
+
+    let mut result = vec![];
+    while next_row(&mut statement)? {
+      if let (Ok(id), Ok(error), Ok(quarantined_at)) = (
+        statement.read::<i64, _>("disk_id"),
+        statement.read::<String, _>("error"),
+        statement.read::<i64, _>("quarantined_at")
+      ) {
+        result.push(QuarantinedDisk { id, error, quarantined_at });
+      } else {
+        return Err("Failed to read quarantined disk".to_string());
+      }
+    }
+
+    Ok(result)
+  })
+  .await
+}
+
+/// Checks the quarantined disks of `account` again, those in `ids` or all of
+/// them when it is empty. Disks that can be read now are released back to
+/// the inventory and returned with the errors of those that still can't.
+pub async fn release_quarantine(
+  game_data: &GameData,
+  account: &str,
+  ids: &[i64]
+) -> Result<LoadedDisks, String> {
+  let mut filter = "id IN (SELECT disk_id FROM quarantine) AND account = ?".to_string();
+  let mut parameters = vec![Value::String(account.to_string())];
+  if !ids.is_empty() {
+    filter.push_str(&format!(" AND id IN ({})", vec!["?"; ids.len()].join(", ")));
+    parameters.extend(ids.iter().map(|id| Value::Integer(*id)));
+  }
+
+  // The disks are checked in the transaction that releases them, so that none
+  // changed in between
+  let game_data = game_data.clone();
+  with_connection(move |connection| {
+    transaction(connection, || {
+      let mut statement = prepare(
+        connection,
+        &format!("SELECT * FROM disks WHERE {}", filter),
+        &parameters
+      )?;
+      let disks = read_disk_rows(&mut statement)?;
+      let affixes = read_affix_rows(
+        connection,
+        &format!("disk_id IN (SELECT id FROM disks WHERE {})", filter),
+        &parameters
+      )?;
+      let result = LoadedDisks::parse(&game_data, disks, affixes);
+
+      for id in result.disks.keys() {
+        execute(
+          connection,
+          "DELETE FROM quarantine WHERE disk_id = :disk_id",
+          &[(":disk_id", Value::Integer(*id))]
+        )?;
+      }
+      write_quarantine(connection, &result.errors)?;
+
+      Ok(result)
+    })
+  })
+  .await
+}
@@ -0,0 +1,954 @@
+use {
+  crate::{
+    database::database::{
+      next_row,
+      parse_affixes,
+      parse_disk,
+      prepare,
+      read_affix_rows,
+      read_disk_row,
+      with_connection,
+      RowError
+    },
+    disk::disk::{AffixValueType, Disk, Rarity},
+    source::game_data::GameData
+  },
+  sqlite::{Connection, Value},
+  std::{collections::BTreeMap, fmt::Display}
+};
+
+/// Which agent, if any, matching disks are equipped on.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EquippedFilter {
+  /// Equipped on the agent with this ID.
+  Agent(String),
+  /// Equipped on any agent, known or not.
+  Any,
+  Unequipped
+}
+
+impl TryFrom<&str> for EquippedFilter {
+  type Error = String;
+
+  fn try_from(value: &str) -> Result<Self, Self::Error> {
+    match value {
+      "any" => Ok(Self::Any),
+      "none" => Ok(Self::Unequipped),
+      "" => Err("Invalid equipped filter: empty".to_string()),
+      _ => Ok(Self::Agent(value.to_string()))
+    }
+  }
+}
+
+impl Display for EquippedFilter {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Agent(id) => write!(f, "{}", id),
+      Self::Any => write!(f, "any"),
+      Self::Unequipped => write!(f, "none")
+    }
+  }
+}
+
+/// A stat with an optional value type, written `stat` or `stat:value_type`,
+/// e.g. `hp:percentage`. Without a value type, either matches.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StatFilter {
+  pub stat: String,
+  pub value_type: Option<AffixValueType>
+}
+
+impl TryFrom<&str> for StatFilter {
+  type Error = String;
+
+  fn try_from(value: &str) -> Result<Self, Self::Error> {
+    let (stat, value_type) = match value.split_once(':') {
+      Some((stat, value_type)) => (stat, Some(AffixValueType::try_from(value_type)?)),
+      None => (value, None)
+    };
+    if stat.is_empty() {
+      return Err(format!("Invalid stat: {}", value));
+    }
+    Ok(Self { stat: stat.to_string(), value_type })
+  }
+}
+
+impl Display for StatFilter {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self.value_type {
+      Some(value_type) => write!(f, "{}:{}", self.stat, value_type),
+      None => write!(f, "{}", self.stat)
+    }
+  }
+}
+
+/// A secondary affix matching disks must have, at least `min_value` when set.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SecondaryFilter {
+  pub stat: StatFilter,
+  pub min_value: Option<f64>
+}
+
+/// What disks are ordered by.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DiskOrder {
+  Id,
+  Set,
+  Slot,
+  Rarity,
+  Level,
+  PrimaryValue,
+  /// Value of a secondary affix; disks without it come last when descending.
+  Secondary(StatFilter),
+  ScannedAt,
+  /// The weighted number of secondary affix rolls.
+  Score
+}
+
+impl TryFrom<&str> for DiskOrder {
+  type Error = String;
+
+  /// Parses a field name, or a secondary stat such as `crit_rate`.
+  fn try_from(value: &str) -> Result<Self, Self::Error> {
+    match value {
+      "id" => Ok(Self::Id),
+      "set" => Ok(Self::Set),
+      "slot" => Ok(Self::Slot),
+      "rarity" => Ok(Self::Rarity),
+      "level" => Ok(Self::Level),
+      "primary_value" => Ok(Self::PrimaryValue),
+      "scanned_at" => Ok(Self::ScannedAt),
+      "score" => Ok(Self::Score),
+      _ => Ok(Self::Secondary(StatFilter::try_from(value)?))
+    }
+  }
+}
+
+impl Display for DiskOrder {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Id => write!(f, "id"),
+      Self::Set => write!(f, "set"),
+      Self::Slot => write!(f, "slot"),
+      Self::Rarity => write!(f, "rarity"),
+      Self::Level => write!(f, "level"),
+      Self::PrimaryValue => write!(f, "primary_value"),
+      Self::Secondary(stat) => write!(f, "{}", stat),
+      Self::ScannedAt => write!(f, "scanned_at"),
+      Self::Score => write!(f, "score")
+    }
+  }
+}
+
+/// Selects disks of an account. Filters of the same kind match any of their
+/// values, and filters of different kinds must all match.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiskQuery {
+  pub account: String,
+  /// Set IDs.
+  pub sets: Vec<String>,
+  pub slots: Vec<u8>,
+  pub rarities: Vec<Rarity>,
+  pub min_level: Option<u8>,
+  pub max_level: Option<u8>,
+  pub primary_stats: Vec<StatFilter>,
+  /// Every one of these must be among the secondary affixes.
+  pub secondary: Vec<SecondaryFilter>,
+  pub locked: Option<bool>,
+  pub trashed: Option<bool>,
+  pub equipped: Option<EquippedFilter>,
+  /// Unix time at or after which matching disks were scanned.
+  pub scanned_after: Option<i64>,
+  /// Unix time before which matching disks were scanned.
+  pub scanned_before: Option<i64>,
+  /// Orderings applied in turn, each descending when its flag is set. Ties
+  /// are ordered by ID.
+  pub order: Vec<(DiskOrder, bool)>,
+  /// Weight of each secondary stat in the score, by stat ID. When empty,
+  /// every stat weighs 1 and the score is the number of rolls.
+  pub weights: BTreeMap<String, f64>,
+  /// Number of disks per page; every disk is returned when not set.
+  pub limit: Option<u32>,
+  pub offset: u32
+}
+
+impl DiskQuery {
+  /// A query for every disk of `account`, by ID.
+  pub fn new(account: &str) -> Self {
+    Self {
+      account: account.to_string(),
+      sets: vec![],
+      slots: vec![],
+      rarities: vec![],
+      min_level: None,
+      max_level: None,
+      primary_stats: vec![],
+      secondary: vec![],
+      locked: None,
+      trashed: None,
+      equipped: None,
+      scanned_after: None,
+      scanned_before: None,
+      order: vec![],
+      weights: BTreeMap::new(),
+      limit: None,
+      offset: 0
+    }
+  }
+
+  pub fn set(mut self, set: &str) -> Self {
+    self.sets.push(set.to_string());
+    self
+  }
+
+  pub fn slot(mut self, slot: u8) -> Self {
+    self.slots.push(slot);
+    self
+  }
+
+  pub fn rarity(mut self, rarity: Rarity) -> Self {
+    self.rarities.push(rarity);
+    self
+  }
+
+  pub fn level_range(mut self, min_level: Option<u8>, max_level: Option<u8>) -> Self {
+    self.min_level = min_level;
+    self.max_level = max_level;
+    self
+  }
+
+  pub fn primary_stat(mut self, stat: StatFilter) -> Self {
+    self.primary_stats.push(stat);
+    self
+  }
+
+  pub fn secondary_stat(mut self, stat: StatFilter, min_value: Option<f64>) -> Self {
+    self.secondary.push(SecondaryFilter { stat, min_value });
+    self
+  }
+
+  pub fn locked(mut self, locked: bool) -> Self {
+    self.locked = Some(locked);
+    self
+  }
+
+  pub fn trashed(mut self, trashed: bool) -> Self {
+    self.trashed = Some(trashed);
+    self
+  }
+
+  pub fn equipped(mut self, equipped: EquippedFilter) -> Self {
+    self.equipped = Some(equipped);
+    self
+  }
+
+  pub fn scanned_between(mut self, after: Option<i64>, before: Option<i64>) -> Self {
+    self.scanned_after = after;
+    self.scanned_before = before;
+    self
+  }
+
+  pub fn order_by(mut self, order: DiskOrder, descending: bool) -> Self {
+    self.order.push((order, descending));
+    self
+  }
+
+  pub fn weight(mut self, stat: &str, weight: f64) -> Self {
+    self.weights.insert(stat.to_string(), weight);
+    self
+  }
+
+  /// Returns the `page`th page, counting from 0, of `size` disks.
+  pub fn page(mut self, page: u32, size: u32) -> Self {
+    self.limit = Some(size);
+    self.offset = page.saturating_mul(size);
+    self
+  }
+
+  /// Checks that every set, stat and agent of the query is in the game data.
+  pub fn validate(&self, game_data: &GameData) -> Result<(), String> {
+    let mut errors = vec![];
+
+    for set in self.sets.iter() {
+      if game_data.set(set).is_none() {
+        errors.push(format!("unknown set {}", set));
+      }
+    }
+    for slot in self.slots.iter() {
+      if game_data.slot(*slot).is_none() {
+        errors.push(format!("unknown slot {}", slot));
+      }
+    }
+    let stats = self
+      .primary_stats
+      .iter()
+      .chain(self.secondary.iter().map(|filter| &filter.stat))
+      .chain(self.order.iter().filter_map(|(order, _)| {
+        match order {
+          DiskOrder::Secondary(stat) => Some(stat),
+          _ => None
+        }
+      }));
+    for filter in stats {
+      if game_data.stat(&filter.stat).is_none() {
+        errors.push(format!("unknown stat {}", filter.stat));
+      }
+    }
+    for stat in self.weights.keys() {
+      if game_data.stat(stat).is_none() {
+        errors.push(format!("unknown stat {} in the weights", stat));
+      }
+    }
+    if let Some(EquippedFilter::Agent(agent)) = self.equipped.as_ref() {
+      if game_data.agent(agent).is_none() {
+        errors.push(format!("unknown agent {}", agent));
+      }
+    }
+    if let (Some(min_level), Some(max_level)) = (self.min_level, self.max_level) {
+      if min_level > max_level {
+        errors.push(format!("level range {} to {} is empty", min_level, max_level));
+      }
+    }
+
+    if !errors.is_empty() {
+      let list: String = errors.iter().map(|error| format!("\n  {}", error)).collect();
+      return Err(format!("{} errors:{}", errors.len(), list));
+    }
+
+    Ok(())
+  }
+}
+
+/// SQL text with the values of its positional parameters, in order.
+#[derive(Default)]
+struct Sql {
+  text: String,
+  parameters: Vec<Value>
+}
+
+impl Sql {
+  fn push(&mut self, text: &str) -> &mut Self {
+    self.text.push_str(text);
+    self
+  }
+
+  fn bind(&mut self, value: Value) -> &mut Self {
+    self.text.push('?');
+    self.parameters.push(value);
+    self
+  }
+
+  /// Appends a parenthesized list of parameters for `IN`.
+  fn bind_list(&mut self, values: impl IntoIterator<Item = Value>) -> &mut Self {
+    self.push("(");
+    for (index, value) in values.into_iter().enumerate() {
+      if index > 0 {
+        self.push(", ");
+      }
+      self.bind(value);
+    }
+    self.push(")")
+  }
+
+  fn append(&mut self, other: Sql) -> &mut Self {
+    self.text.push_str(&other.text);
+    self.parameters.extend(other.parameters);
+    self
+  }
+
+  /// Appends a condition on the affix of `alias` having a stat.
+  fn stat_condition(&mut self, alias: &str, filter: &StatFilter) -> &mut Self {
+    self.push(&format!("{}.stat = ", alias)).bind(Value::String(filter.stat.clone()));
+    if let Some(value_type) = filter.value_type {
+      self
+        .push(&format!(" AND {}.value_type = ", alias))
+        .bind(Value::String(value_type.to_string()));
+    }
+    self
+  }
+}
+
+/// Builds the expression for the score of a disk: the value of each of its
+/// secondary affixes divided by the value of one roll, times the weight of
+/// its stat.
+fn score_sql(query: &DiskQuery, game_data: &GameData) -> Sql {
+  let mut cases = Sql::default();
+  for stat in game_data.stats.iter() {
+    let weight = match query.weights.is_empty() {
+      true => 1.0,
+      false => query.weights.get(&stat.id).copied().unwrap_or(0.0)
+    };
+    for (value_type, roll) in stat.substat_rolls.iter() {
+      if weight == 0.0 || *roll <= 0.0 {
+        continue;
+      }
+      cases
+        .push(" WHEN score_affixes.stat = ")
+        .bind(Value::String(stat.id.clone()))
+        .push(" AND score_affixes.value_type = ")
+        .bind(Value::String(value_type.to_string()))
+        .push(" THEN score_affixes.value * ")
+        .bind(Value::Float(weight / roll));
+    }
+  }
+
+  let mut sql = Sql::default();
+  if cases.parameters.is_empty() {
+    sql.push("0.0");
+  } else {
+    sql.push("(SELECT COALESCE(SUM(CASE").append(cases).push(
+      " END), 0.0) FROM affixes AS score_affixes WHERE score_affixes.disk_id = disks.id \
+       AND score_affixes.kind = 'secondary')"
+    );
+  }
+  sql
+}
+
+fn where_sql(query: &DiskQuery) -> Sql {
+  let mut sql = Sql::default();
+  sql.push("disks.account = ").bind(Value::String(query.account.clone()));
+  sql.push(
+    " AND disks.deleted_at IS NULL AND disks.id NOT IN (SELECT disk_id FROM quarantine)"
+  );
+
+  if !query.sets.is_empty() {
+    sql
+      .push(" AND disks.set_id IN ")
+      .bind_list(query.sets.iter().map(|set| Value::String(set.clone())));
+  }
+  if !query.slots.is_empty() {
+    sql
+      .push(" AND disks.slot IN ")
+      .bind_list(query.slots.iter().map(|slot| Value::Integer((*slot).into())));
+  }
+  if !query.rarities.is_empty() {
+    sql
+      .push(" AND disks.rarity IN ")
+      .bind_list(query.rarities.iter().map(|rarity| Value::String(rarity.to_string())));
+  }
+  if let Some(min_level) = query.min_level {
+    sql.push(" AND disks.level >= ").bind(Value::Integer(min_level.into()));
+  }
+  if let Some(max_level) = query.max_level {
+    sql.push(" AND disks.level <= ").bind(Value::Integer(max_level.into()));
+  }
+
+  if !query.primary_stats.is_empty() {
+    sql.push(
+      " AND EXISTS (SELECT 1 FROM affixes AS primary_affixes WHERE \
+       primary_affixes.disk_id = disks.id AND primary_affixes.position = 0 AND ("
+    );
+    for (index, filter) in query.primary_stats.iter().enumerate() {
+      if index > 0 {
+        sql.push(" OR ");
+      }
+      sql.push("(").stat_condition("primary_affixes", filter).push(")");
+    }
+    sql.push("))");
+  }
+  for filter in query.secondary.iter() {
+    sql.push(
+      " AND EXISTS (SELECT 1 FROM affixes AS secondary_affixes WHERE \
+       secondary_affixes.disk_id = disks.id AND secondary_affixes.kind = 'secondary' \
+       AND "
+    );
+    sql.stat_condition("secondary_affixes", &filter.stat);
+    if let Some(min_value) = filter.min_value {
+      sql.push(" AND secondary_affixes.value >= ").bind(Value::Float(min_value));
+    }
+    sql.push(")");
+  }
+
+  if let Some(locked) = query.locked {
+    sql.push(" AND disks.locked = ").bind(Value::Integer(locked.into()));
+  }
+  if let Some(trashed) = query.trashed {
+    sql.push(" AND disks.trashed = ").bind(Value::Integer(trashed.into()));
+  }
+  match query.equipped.as_ref() {
+    Some(EquippedFilter::Agent(agent)) => {
+      sql.push(" AND disks.equipped_by = ").bind(Value::String(agent.clone()));
+    },
+    Some(EquippedFilter::Any) => {
+      sql.push(
+        " AND (disks.equipped_by IS NOT NULL OR disks.equipped_by_raw IS NOT NULL)"
+      );
+    },
+    Some(EquippedFilter::Unequipped) => {
+      sql.push(" AND disks.equipped_by IS NULL AND disks.equipped_by_raw IS NULL");
+    },
+    None => ()
+  }
+  if let Some(after) = query.scanned_after {
+    sql.push(" AND disks.scanned_at >= ").bind(Value::Integer(after));
+  }
+  if let Some(before) = query.scanned_before {
+    sql.push(" AND disks.scanned_at < ").bind(Value::Integer(before));
+  }
+
+  sql
+}
+
+fn order_sql(query: &DiskQuery) -> Sql {
+  let mut sql = Sql::default();
+  for (order, descending) in query.order.iter() {
+    match order {
+      DiskOrder::Id => sql.push("disks.id"),
+      DiskOrder::Set => sql.push("disks.set_id"),
+      DiskOrder::Slot => sql.push("disks.slot"),
+      DiskOrder::Rarity => {
+        sql.push("CASE disks.rarity WHEN 'S' THEN 3 WHEN 'A' THEN 2 WHEN 'B' THEN 1 END")
+      },
+      DiskOrder::Level => sql.push("disks.level"),
+      DiskOrder::PrimaryValue => {
+        sql.push(
+          "(SELECT order_affixes.value FROM affixes AS order_affixes WHERE \
+           order_affixes.disk_id = disks.id AND order_affixes.position = 0)"
+        )
+      },
+      DiskOrder::Secondary(filter) => {
+        sql
+          .push(
+            "(SELECT MAX(order_affixes.value) FROM affixes AS order_affixes WHERE \
+             order_affixes.disk_id = disks.id AND order_affixes.kind = 'secondary' AND "
+          )
+          .stat_condition("order_affixes", filter)
+          .push(")")
+      },
+      DiskOrder::ScannedAt => sql.push("disks.scanned_at"),
+      DiskOrder::Score => sql.push("score")
+    };
+    sql.push(if *descending { " DESC, " } else { " ASC, " });
+  }
+  sql.push("disks.id ASC");
+  sql
+}
+
+/// A disk found by a query.
+#[derive(Debug)]
+pub struct QueriedDisk {
+  pub id: i64,
+  pub disk: Disk,
+  /// Unix time the disk was scanned at, unknown for disks saved before scan
+  /// dates were recorded.
+  pub scanned_at: Option<i64>,
+  pub score: f64
+}
+
+/// One page of the disks matching a query.
+#[derive(Debug)]
+pub struct DiskPage {
+  pub disks: Vec<QueriedDisk>,
+  /// Number of disks matching the query, over every page.
+  pub total: u64,
+  /// Disks of the page that couldn't be read.
+  pub errors: Vec<RowError>
+}
+
+/// Runs a query over the stored disks.
+pub async fn query_disks(
+  game_data: &GameData,
+  query: &DiskQuery
+) -> Result<DiskPage, String> {
+  query.validate(game_data)?;
+
+  let game_data = game_data.clone();
+  let query = query.clone();
+  with_connection(move |connection| read_disk_page(connection, &game_data, &query)).await
+}
+
+/// Runs a query as `query_disks` does, on `connection`.
+pub(crate) fn read_disk_page(
+  connection: &Connection,
+  game_data: &GameData,
+  query: &DiskQuery
+) -> Result<DiskPage, String> {
+  let filter = where_sql(query);
+  let count_sql = format!("SELECT COUNT(*) AS total FROM disks WHERE {}", filter.text);
+
+  let mut statement = prepare(connection, &count_sql, &filter.parameters)?;
+  let total = if next_row(&mut statement)? {
+    statement
+      .read::<i64, _>("total")
+      .map_err(|error| format!("Failed to read disk count: {}", error))?
+  } else {
+    0
+  };
+
+  let mut page = Sql::default();
+  page
+    .push("SELECT disks.*, ")
+    .append(score_sql(query, game_data))
+    .push(" AS score FROM disks WHERE ")
+    .append(filter)
+    .push(" ORDER BY ")
+    .append(order_sql(query))
+    .push(" LIMIT ")
+    .bind(Value::Integer(query.limit.map_or(-1, i64::from)))
+    .push(" OFFSET ")
+    .bind(Value::Integer(query.offset.into()));
+
+  let mut statement = prepare(connection, &page.text, &page.parameters)?;
+  let mut disks = vec![];
+  while next_row(&mut statement)? {
+    let score = statement
+      .read::<f64, _>("score")
+      .map_err(|error| format!("Failed to read score: {}", error))?;
+    disks.push((read_disk_row(&statement)?, score));
+  }
+
+  let affix_filter = format!("disk_id IN (SELECT id FROM ({}))", page.text);
+  let affixes = read_affix_rows(connection, &affix_filter, &page.parameters)?;
+
+  let mut affixes = parse_affixes(game_data, affixes);
+  let mut result = DiskPage { disks: vec![], total: total as u64, errors: vec![] };
+  for (row, score) in disks {
+    let scanned_at = row.as_ref().ok().and_then(|row| row.scanned_at);
+    match parse_disk(game_data, row, &mut affixes) {
+      Ok((id, disk)) => result.disks.push(QueriedDisk { id, disk, scanned_at, score }),
+      Err(error) => result.errors.push(error)
+    }
+  }
+
+  Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    crate::{
+      database::database::{execute, open, save_disk},
+      disk::disk::{Affix, EquippedBy}
+    },
+    std::{collections::HashSet, path::Path}
+  };
+
+  fn stat(value: &str) -> StatFilter {
+    StatFilter::try_from(value).unwrap()
+  }
+
+  fn text(value: &str) -> Value {
+    Value::String(value.to_string())
+  }
+
+  /// Saves four disks of the default account and one of another account,
+  /// returning the IDs of the former in order:
+  ///
+  /// 1. chaos_jazz, slot 1, S, level 15, locked, on anby, scanned at 1000,
+  ///    crit_rate 4.8% and atk 19, scoring 3
+  /// 2. chaos_jazz, slot 2, S, level 9, scanned at 2000, crit_rate 2.4% and
+  ///    crit_dmg 14.4%, scoring 4
+  /// 3. woodpecker_electro, slot 4, A, level 3, on an unknown agent, scanned at
+  ///    3000, atk 19 and hp 112, scoring 2
+  /// 4. woodpecker_electro, slot 1, S, level 0, trashed, never scanned,
+  ///    crit_dmg 9.6%, def 15, pen 9 and anomaly_proficiency 9, scoring 5
+  fn save_disks(connection: &Connection, game_data: &GameData) -> Vec<i64> {
+    let affix = |stat: &str, value: f64, value_type: &str| {
+      Some(Affix::parse(game_data, stat, value, value_type).unwrap())
+    };
+    let disk =
+      |set: &str, slot: u8, primary: Option<Affix>, secondary: [Option<Affix>; 4]| {
+        let [s1, s2, s3, s4] = secondary;
+        Disk::new(game_data, set, slot, primary.unwrap(), s1, s2, s3, s4).unwrap()
+      };
+
+    let disks = [
+      disk(
+        "chaos_jazz",
+        1,
+        affix("hp", 550.0, "flat"),
+        [affix("crit_rate", 4.8, "percentage"), affix("atk", 19.0, "flat"), None, None]
+      )
+      .with_rarity(Some(Rarity::S))
+      .with_level(Some(15))
+      .unwrap()
+      .with_locked(true)
+      .with_equipped_by(Some(EquippedBy::Agent("anby".to_string()))),
+      disk(
+        "chaos_jazz",
+        2,
+        affix("atk", 79.0, "flat"),
+        [
+          affix("crit_rate", 2.4, "percentage"),
+          affix("crit_dmg", 14.4, "percentage"),
+          None,
+          None
+        ]
+      )
+      .with_rarity(Some(Rarity::S))
+      .with_level(Some(9))
+      .unwrap(),
+      disk(
+        "woodpecker_electro",
+        4,
+        affix("crit_rate", 24.0, "percentage"),
+        [affix("atk", 19.0, "flat"), affix("hp", 112.0, "flat"), None, None]
+      )
+      .with_rarity(Some(Rarity::A))
+      .with_level(Some(3))
+      .unwrap()
+      .with_equipped_by(Some(EquippedBy::Unknown("某人".to_string()))),
+      disk(
+        "woodpecker_electro",
+        1,
+        affix("hp", 550.0, "flat"),
+        [
+          affix("crit_dmg", 9.6, "percentage"),
+          affix("def", 15.0, "flat"),
+          affix("pen", 9.0, "flat"),
+          affix("anomaly_proficiency", 9.0, "flat")
+        ]
+      )
+      .with_rarity(Some(Rarity::S))
+      .with_level(Some(0))
+      .unwrap()
+      .with_trashed(true)
+    ];
+
+    let mut ids = vec![];
+    for (disk, scanned_at) in disks.iter().zip([Some(1000), Some(2000), Some(3000), None])
+    {
+      let id =
+        save_disk(connection, game_data, "default", disk, &HashSet::new()).unwrap().id();
+      let scanned_at = scanned_at.map_or(Value::Null, Value::Integer);
+      execute(
+        connection,
+        "UPDATE disks SET scanned_at = :scanned_at WHERE id = :id",
+        &[(":id", Value::Integer(id)), (":scanned_at", scanned_at)]
+      )
+      .unwrap();
+      ids.push(id);
+    }
+
+    execute(
+      connection,
+      "INSERT INTO accounts (uid, name, created_at) VALUES ('other', NULL, 0)",
+      &[]
+    )
+    .unwrap();
+    save_disk(connection, game_data, "other", &disks[0], &HashSet::new()).unwrap();
+
+    ids
+  }
+
+  /// Runs `query` on the disks of `save_disks`, returning the positions, from
+  /// 1, of the disks found, or 0 for the disk of the other account, and the
+  /// total count.
+  fn find(query: DiskQuery) -> (Vec<usize>, u64) {
+    let game_data = GameData::bundled();
+    let connection = open(Path::new(":memory:")).unwrap();
+    let ids = save_disks(&connection, &game_data);
+
+    query.validate(&game_data).unwrap();
+    let page = read_disk_page(&connection, &game_data, &query).unwrap();
+    assert!(page.errors.is_empty());
+    let positions = page
+      .disks
+      .iter()
+      .map(|disk| ids.iter().position(|id| *id == disk.id).map_or(0, |i| i + 1))
+      .collect();
+    (positions, page.total)
+  }
+
+  fn found(query: DiskQuery) -> Vec<usize> {
+    find(query).0
+  }
+
+  fn default() -> DiskQuery {
+    DiskQuery::new("default")
+  }
+
+  #[test]
+  fn finds_every_disk_of_the_account() {
+    assert_eq!(find(default()), (vec![1, 2, 3, 4], 4));
+    assert_eq!(find(DiskQuery::new("other")), (vec![0], 1));
+    assert_eq!(find(DiskQuery::new("nobody")), (vec![], 0));
+  }
+
+  #[test]
+  fn filters_by_set() {
+    assert_eq!(found(default().set("chaos_jazz")), [1, 2]);
+    assert_eq!(
+      found(default().set("woodpecker_electro").set("chaos_jazz")),
+      [1, 2, 3, 4]
+    );
+    assert_eq!(found(default().set("puffer_electro")), [] as [usize; 0]);
+  }
+
+  #[test]
+  fn filters_by_slot() {
+    assert_eq!(found(default().slot(1)), [1, 4]);
+    assert_eq!(found(default().slot(4).slot(2)), [2, 3]);
+    assert_eq!(found(default().set("chaos_jazz").slot(1)), [1]);
+  }
+
+  #[test]
+  fn filters_by_level_range() {
+    assert_eq!(found(default().level_range(Some(3), Some(9))), [2, 3]);
+    assert_eq!(found(default().level_range(Some(10), None)), [1]);
+    assert_eq!(found(default().level_range(None, Some(0))), [4]);
+    assert_eq!(found(default().level_range(Some(9), Some(9))), [2]);
+  }
+
+  #[test]
+  fn filters_by_primary_stat() {
+    assert_eq!(found(default().primary_stat(stat("hp"))), [1, 4]);
+    assert_eq!(
+      found(default().primary_stat(stat("crit_rate")).primary_stat(stat("atk"))),
+      [2, 3]
+    );
+    assert_eq!(found(default().primary_stat(stat("hp:percentage"))), [] as [usize; 0]);
+  }
+
+  #[test]
+  fn filters_by_secondary_stat_and_value() {
+    assert_eq!(found(default().secondary_stat(stat("crit_rate"), None)), [1, 2]);
+    assert_eq!(found(default().secondary_stat(stat("crit_rate"), Some(4.8))), [1]);
+    assert_eq!(
+      found(default().secondary_stat(stat("crit_rate"), Some(4.9))),
+      [] as [usize; 0]
+    );
+    assert_eq!(found(default().secondary_stat(stat("atk:flat"), Some(19.0))), [1, 3]);
+    assert_eq!(
+      found(default().secondary_stat(stat("atk:percentage"), None)),
+      [] as [usize; 0]
+    );
+    // Every secondary filter has to match
+    assert_eq!(
+      found(
+        default()
+          .secondary_stat(stat("crit_rate"), None)
+          .secondary_stat(stat("crit_dmg"), Some(10.0))
+      ),
+      [2]
+    );
+    // Primary affixes don't count
+    assert_eq!(found(default().secondary_stat(stat("hp"), None)), [3]);
+  }
+
+  #[test]
+  fn filters_by_equipped_agent() {
+    assert_eq!(found(default().equipped(EquippedFilter::Agent("anby".to_string()))), [1]);
+    assert_eq!(
+      found(default().equipped(EquippedFilter::Agent("billy".to_string()))),
+      [] as [usize; 0]
+    );
+    assert_eq!(found(default().equipped(EquippedFilter::Any)), [1, 3]);
+    assert_eq!(found(default().equipped(EquippedFilter::Unequipped)), [2, 4]);
+  }
+
+  #[test]
+  fn filters_by_scan_date() {
+    assert_eq!(found(default().scanned_between(Some(2000), None)), [2, 3]);
+    assert_eq!(found(default().scanned_between(None, Some(2000))), [1]);
+    assert_eq!(found(default().scanned_between(Some(1000), Some(3000))), [1, 2]);
+    assert_eq!(found(default().scanned_between(Some(3001), None)), [] as [usize; 0]);
+  }
+
+  #[test]
+  fn filters_by_rarity_and_flags() {
+    assert_eq!(found(default().rarity(Rarity::A)), [3]);
+    assert_eq!(found(default().locked(true)), [1]);
+    assert_eq!(found(default().locked(false).trashed(false)), [2, 3]);
+    assert_eq!(found(default().trashed(true)), [4]);
+  }
+
+  #[test]
+  fn orders_by_score() {
+    let game_data = GameData::bundled();
+    let connection = open(Path::new(":memory:")).unwrap();
+    let ids = save_disks(&connection, &game_data);
+    let scores = |query: DiskQuery| {
+      read_disk_page(&connection, &game_data, &query)
+        .unwrap()
+        .disks
+        .iter()
+        .map(|disk| {
+          let position = ids.iter().position(|id| *id == disk.id).unwrap() + 1;
+          (position, (disk.score * 1000.0).round() / 1000.0)
+        })
+        .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+      scores(default().order_by(DiskOrder::Score, true)),
+      [(4, 5.0), (2, 4.0), (1, 3.0), (3, 2.0)]
+    );
+    assert_eq!(
+      scores(default().order_by(DiskOrder::Score, false)),
+      [(3, 2.0), (1, 3.0), (2, 4.0), (4, 5.0)]
+    );
+    // Only weighted stats count, and ties are ordered by ID
+    assert_eq!(
+      scores(default().weight("crit_rate", 2.0).order_by(DiskOrder::Score, true)),
+      [(1, 4.0), (2, 2.0), (3, 0.0), (4, 0.0)]
+    );
+    assert_eq!(
+      scores(
+        default()
+          .weight("crit_dmg", 1.0)
+          .weight("atk", 0.5)
+          .order_by(DiskOrder::Score, true)
+      ),
+      [(2, 3.0), (4, 2.0), (1, 0.5), (3, 0.5)]
+    );
+  }
+
+  #[test]
+  fn orders_by_several_fields() {
+    assert_eq!(found(default().order_by(DiskOrder::Level, true)), [1, 2, 3, 4]);
+    assert_eq!(
+      found(default().order_by(DiskOrder::Set, true).order_by(DiskOrder::Slot, false)),
+      [4, 3, 1, 2]
+    );
+    assert_eq!(found(default().order_by(DiskOrder::ScannedAt, true)), [3, 2, 1, 4]);
+    // Disks without the stat come last when descending
+    assert_eq!(
+      found(default().order_by(DiskOrder::Secondary(stat("crit_dmg")), true)),
+      [2, 4, 1, 3]
+    );
+  }
+
+  #[test]
+  fn pages_through_the_results() {
+    assert_eq!(find(default().page(0, 3)), (vec![1, 2, 3], 4));
+    assert_eq!(find(default().page(1, 3)), (vec![4], 4));
+    assert_eq!(find(default().page(2, 3)), (vec![], 4));
+    assert_eq!(find(default().page(0, 4)), (vec![1, 2, 3, 4], 4));
+    assert_eq!(find(default().slot(1).page(1, 1)), (vec![4], 2));
+    assert_eq!(
+      find(default().order_by(DiskOrder::Score, true).page(1, 2)),
+      (vec![1, 3], 4)
+    );
+  }
+
+  #[test]
+  fn filters_only_the_account_by_default() {
+    let sql = where_sql(&DiskQuery::new("main"));
+    assert_eq!(
+      sql.text,
+      "disks.account = ? AND disks.deleted_at IS NULL AND disks.id NOT IN (SELECT \
+       disk_id FROM quarantine)"
+    );
+    assert_eq!(sql.parameters, [text("main")]);
+
+    let sql = where_sql(&DiskQuery::new("main").equipped(EquippedFilter::Unequipped));
+    assert!(sql
+      .text
+      .ends_with(" AND disks.equipped_by IS NULL AND disks.equipped_by_raw IS NULL"));
+    assert_eq!(sql.parameters.len(), 1);
+  }
+
+  #[test]
+  fn rejects_unknown_names() {
+    let game_data = GameData::bundled();
+    let query = DiskQuery::new("main")
+      .set("no_such_set")
+      .order_by(DiskOrder::Secondary(stat("no_such_stat")), false)
+      .level_range(Some(15), Some(9));
+    let error = query.validate(&game_data).unwrap_err();
+    assert!(error.starts_with("3 errors:"));
+    assert!(DiskQuery::new("main").set("chaos_jazz").validate(&game_data).is_ok());
+  }
+}
@@ -0,0 +1,372 @@
+use {
+  crate::{
+    database::{
+      database::{
+        execute,
+        last_insert_id,
+        next_row,
+        now,
+        prepare,
+        transaction,
+        with_connection,
+        LoadedDisks,
+        RowError,
+        ScanSession
+      },
+      journal::DiskSnapshot
+    },
+    disk::diff::InventoryDiff,
+    source::game_data::GameData
+  },
+  sqlite::{Connection, Value}
+};
+
+/// A copy of the inventory of an account at some point, such as the end of a
+/// scan sweep.
+#[derive(Clone, Debug)]
+pub struct InventorySnapshot {
+  pub id: i64,
+  pub name: Option<String>,
+  /// The scan session the snapshot was taken after, if any.
+  pub session_id: Option<i64>,
+  pub taken_at: i64,
+  /// Number of disks in the snapshot.
+  pub disks: i64
+}
+
+/// Copies the inventory of `account`, leaving out deleted and quarantined
+/// disks, and returns the snapshot. Taken after a scan sweep, the snapshot
+/// only holds the disks seen in `session`, so that disks dismantled since the
+/// last sweep are left out of it; otherwise it holds every disk.
+pub async fn take_snapshot(
+  account: &str,
+  name: Option<&str>,
+  session: Option<&ScanSession>
+) -> Result<InventorySnapshot, String> {
+  let account = account.to_string();
+  let name = name.map(|name| name.to_string());
+  let session = session.cloned();
+
+  with_connection(move |connection| {
+    transaction(connection, || {
+      write_snapshot(connection, &account, name.as_deref(), session.as_ref())
+    })
+  })
+  .await
+}
+
+fn write_snapshot(
+  connection: &Connection,
+  account: &str,
+  name: Option<&str>,
+  session: Option<&ScanSession>
+) -> Result<InventorySnapshot, String> {
+  let session_id = session.and_then(|session| session.id());
+  let taken_at = now();
+  execute(
+    connection,
+    r#"
+    INSERT INTO snapshots (account, name, session_id, taken_at)
+    VALUES (:account, :name, :session_id, :taken_at);
+    "#,
+    &[
+      (":account", Value::String(account.to_string())),
+      (":name", name.map_or(Value::Null, |name| Value::String(name.to_string()))),
+      (":session_id", session_id.map_or(Value::Null, Value::Integer)),
+      (":taken_at", Value::Integer(taken_at))
+    ]
+  )?;
+  let id = last_insert_id(connection)?;
+
+  let mut statement = prepare(
+    connection,
+    "SELECT id FROM disks WHERE account = ? AND deleted_at IS NULL AND id NOT IN \
+     (SELECT disk_id FROM quarantine) ORDER BY id",
+    &[Value::String(account.to_string())]
+  )?;
+  let mut disk_ids = vec![];
+  while next_row(&mut statement)? {
+    let disk_id = statement
+      .read::<i64, _>("id")
+      .map_err(|error| format!("Failed to read id: {}", error))?;
+    if session.is_none_or(|session| session.seen.contains(&disk_id)) {
+      disk_ids.push(disk_id);
+    }
+  }
+
+  for disk_id in disk_ids.iter() {
+    let state = DiskSnapshot::read(connection, *disk_id)?;
+    execute(
+      connection,
+      r#"
+      INSERT INTO snapshot_disks (snapshot_id, disk_id, state)
+      VALUES (:snapshot_id, :disk_id, :state);
+      "#,
+      &[
+        (":snapshot_id", Value::Integer(id)),
+        (":disk_id", Value::Integer(*disk_id)),
+        (":state", Value::String(state.to_json()?))
+      ]
+    )?;
+  }
+
+  Ok(InventorySnapshot {
+    id,
+    name: name.map(|name| name.to_string()),
+    session_id,
+    taken_at,
+    disks: disk_ids.len() as i64
+  })
+}
+
+fn read_snapshot_rows(
+  connection: &Connection,
+  filter: &str,
+  parameters: &[Value]
+) -> Result<Vec<InventorySnapshot>, String> {
+  let mut statement = prepare(
+    connection,
+    &format!(
+      "SELECT snapshots.id, snapshots.name, snapshots.session_id, snapshots.taken_at, \
+       COUNT(snapshot_disks.disk_id) AS disks FROM snapshots LEFT JOIN snapshot_disks \
+       ON snapshot_disks.snapshot_id = snapshots.id WHERE {} GROUP BY snapshots.id \
+       ORDER BY snapshots.id",
+      filter
+    ),
+    parameters
+  )?;
+
+  let mut result = vec![];
+  while next_row(&mut statement)? {
+    if let (Ok(id), Ok(name), Ok(session_id), Ok(taken_at), Ok(disks)) = (
+      statement.read::<i64, _>("id"),
+      statement.read::<Option<String>, _>("name"),
+      statement.read::<Option<i64>, _>("session_id"),
+      statement.read::<i64, _>("taken_at"),
+      statement.read::<i64, _>("disks")
+    ) {
+      result.push(InventorySnapshot { id, name, session_id, taken_at, disks });
+    } else {
+      return Err("Failed to read snapshot".to_string());
+    }
+  }
+
+  Ok(result)
+}
+
+/// Reads the snapshots of `account`, oldest first.
+pub async fn read_snapshots(account: &str) -> Result<Vec<InventorySnapshot>, String> {
+  let account = account.to_string();
+  with_connection(move |connection| {
+    read_snapshot_rows(connection, "snapshots.account = ?", &[Value::String(account)])
+  })
+  .await
+}
+
+/// Deletes the snapshot `id` of `account`. Returns whether it existed.
+pub async fn delete_snapshot(account: &str, id: i64) -> Result<bool, String> {
+  let account = account.to_string();
+  with_connection(move |connection| {
+    let existed = !read_snapshot_rows(
+      connection,
+      "snapshots.id = ? AND snapshots.account = ?",
+      &[Value::Integer(id), Value::String(account.clone())]
+    )?
+    .is_empty();
+    execute(
+      connection,
+      "DELETE FROM snapshots WHERE id = :id AND account = :account",
+      &[(":id", Value::Integer(id)), (":account", Value::String(account))]
+    )?;
+    Ok(existed)
+  })
+  .await
+}
+
+/// Reads the disks of the snapshot `id` of `account`, with the disks that no
+/// longer fit the game data as errors.
+pub async fn read_snapshot(
+  game_data: &GameData,
+  account: &str,
+  id: i64
+) -> Result<(InventorySnapshot, LoadedDisks), String> {
+  let account = account.to_string();
+  let (snapshot, states) =
+    with_connection(move |connection| read_snapshot_states(connection, &account, id))
+      .await?;
+  Ok((snapshot, parse_snapshot_states(game_data, states)))
+}
+
+/// Reads the snapshot `id` of `account` with the stored state of each of its
+/// disks.
+fn read_snapshot_states(
+  connection: &Connection,
+  account: &str,
+  id: i64
+) -> Result<(InventorySnapshot, Vec<(i64, String)>), String> {
+  let Some(snapshot) = read_snapshot_rows(
+    connection,
+    "snapshots.id = ? AND snapshots.account = ?",
+    &[Value::Integer(id), Value::String(account.to_string())]
+  )?
+  .pop() else {
+    return Err(format!("No snapshot {}", id));
+  };
+
+  let mut statement = prepare(
+    connection,
+    "SELECT disk_id, state FROM snapshot_disks WHERE snapshot_id = ?",
+    &[Value::Integer(id)]
+  )?;
+  let mut states = vec![];
+  while next_row(&mut statement)? {
+    if let (Ok(disk_id), Ok(state)) =
+      (statement.read::<i64, _>("disk_id"), statement.read::<String, _>("state"))
+    {
+      states.push((disk_id, state));
+    } else {
+      return Err(format!("Failed to read the disks of snapshot {}", id));
+    }
+  }
+  Ok((snapshot, states))
+}
+
+fn parse_snapshot_states(
+  game_data: &GameData,
+  states: Vec<(i64, String)>
+) -> LoadedDisks {
+  let mut rows = vec![];
+  let mut affixes = vec![];
+  for (disk_id, state) in states {
+    match DiskSnapshot::from_json(&state) {
+      Ok(state) => {
+        let (row, affix_rows) = state.to_rows(disk_id);
+        rows.push(Ok(row));
+        affixes.extend(affix_rows.into_iter().map(Ok));
+      },
+      Err(error) => rows.push(Err(RowError { id: disk_id, error }))
+    }
+  }
+
+  LoadedDisks::parse(game_data, rows, affixes)
+}
+
+/// Compares the snapshots `from` and `to` of `account`. Disks of either
+/// snapshot that no longer fit the game data are left out and returned as
+/// errors.
+pub async fn diff_snapshots(
+  game_data: &GameData,
+  account: &str,
+  from: i64,
+  to: i64
+) -> Result<(InventoryDiff, Vec<RowError>), String> {
+  let (_, before) = read_snapshot(game_data, account, from).await?;
+  let (_, after) = read_snapshot(game_data, account, to).await?;
+
+  let mut errors = before.errors;
+  errors.extend(after.errors);
+  Ok((InventoryDiff::new(before.disks, after.disks), errors))
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    crate::{
+      database::database::{open, save_disk},
+      disk::disk::{Affix, Disk}
+    },
+    std::path::Path
+  };
+
+  fn disk(
+    game_data: &GameData,
+    set: &str,
+    primary: f64,
+    crit_rate: f64,
+    level: u8
+  ) -> Disk {
+    let affix = |stat: &str, value: f64, value_type: &str| {
+      Affix::parse(game_data, stat, value, value_type).unwrap()
+    };
+    Disk::new(
+      game_data,
+      set,
+      1,
+      affix("hp", primary, "flat"),
+      Some(affix("crit_rate", crit_rate, "percentage")),
+      Some(affix("atk", 19.0, "flat")),
+      None,
+      None
+    )
+    .unwrap()
+    .with_level(Some(level))
+    .unwrap()
+  }
+
+  fn sweep(
+    connection: &Connection,
+    game_data: &GameData,
+    disks: &[Disk]
+  ) -> (ScanSession, InventorySnapshot) {
+    let mut session = ScanSession::new();
+    for disk in disks {
+      let saved =
+        save_disk(connection, game_data, "default", disk, &session.seen).unwrap();
+      session.seen.insert(saved.id());
+    }
+    let snapshot = write_snapshot(connection, "default", None, Some(&session)).unwrap();
+    (session, snapshot)
+  }
+
+  fn load(connection: &Connection, game_data: &GameData, id: i64) -> LoadedDisks {
+    let (_, states) = read_snapshot_states(connection, "default", id).unwrap();
+    parse_snapshot_states(game_data, states)
+  }
+
+  #[test]
+  fn sweep_snapshots_show_enhanced_and_dismantled_disks() {
+    let game_data = GameData::bundled();
+    let connection = open(Path::new(":memory:")).unwrap();
+
+    let kept = disk(&game_data, "chaos_jazz", 550.0, 2.4, 0);
+    let enhanced = disk(&game_data, "proto_punk", 550.0, 2.4, 0);
+    let dismantled = disk(&game_data, "swing_jazz", 550.0, 2.4, 0);
+    let (_, before) =
+      sweep(&connection, &game_data, &[kept.clone(), enhanced, dismantled]);
+
+    let enhanced = disk(&game_data, "proto_punk", 1100.0, 4.8, 3);
+    let (session, after) = sweep(&connection, &game_data, &[kept, enhanced]);
+    assert_eq!(session.seen.len(), 2);
+    assert_eq!(after.disks, 2);
+
+    let before = load(&connection, &game_data, before.id);
+    let after = load(&connection, &game_data, after.id);
+    assert!(before.errors.is_empty() && after.errors.is_empty());
+
+    let diff = InventoryDiff::new(before.disks, after.disks);
+    assert!(diff.added.is_empty());
+    assert!(diff.changed.is_empty());
+    assert_eq!(diff.removed.len(), 1);
+    assert_eq!(diff.removed[0].1.set, "swing_jazz");
+    assert_eq!(diff.upgraded.len(), 1);
+    let upgrade = &diff.upgraded[0];
+    assert_eq!(upgrade.before_id, upgrade.after_id);
+    assert_eq!(upgrade.after.set, "proto_punk");
+    assert_eq!((upgrade.before.level, upgrade.after.level), (Some(0), Some(3)));
+    assert_eq!(upgrade.affixes.len(), 2);
+  }
+
+  #[test]
+  fn snapshots_without_a_session_hold_every_disk() {
+    let game_data = GameData::bundled();
+    let connection = open(Path::new(":memory:")).unwrap();
+
+    sweep(&connection, &game_data, &[disk(&game_data, "chaos_jazz", 550.0, 2.4, 0)]);
+    sweep(&connection, &game_data, &[disk(&game_data, "proto_punk", 550.0, 2.4, 0)]);
+
+    let snapshot = write_snapshot(&connection, "default", Some("all"), None).unwrap();
+    assert_eq!(snapshot.disks, 2);
+    assert_eq!(load(&connection, &game_data, snapshot.id).disks.len(), 2);
+  }
+}
@@ -0,0 +1,396 @@
+use {
+  crate::{
+    disk::disk::{Affix, AffixValueType, Disk},
+    source::game_data::GameData
+  },
+  std::{collections::HashMap, fmt::Display}
+};
+
+/// How the value of one affix differs between two states of a disk.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AffixChange {
+  /// ID of the stat in the game data.
+  pub stat: String,
+  pub value_type: AffixValueType,
+  pub primary: bool,
+  /// `None` for an affix the disk gained.
+  pub before: Option<f64>,
+  /// `None` for an affix the disk lost.
+  pub after: Option<f64>
+}
+
+/// A disk found in both inventories with a different level, affixes,
+/// equipped agent or marks.
+#[derive(Debug)]
+pub struct DiskChange {
+  /// ID of the disk in the earlier inventory.
+  pub before_id: i64,
+  /// ID of the disk in the later inventory, another one when the upgraded
+  /// disk was saved again rather than updated.
+  pub after_id: i64,
+  pub before: Disk,
+  pub after: Disk,
+  /// Affixes whose value changed, or that were gained or lost.
+  pub affixes: Vec<AffixChange>
+}
+
+impl DiskChange {
+  fn new(before_id: i64, before: Disk, after_id: i64, after: Disk) -> Self {
+    let affixes = affix_changes(&before, &after);
+    Self { before_id, after_id, before, after, affixes }
+  }
+
+  /// Whether the disk was enhanced, raising its level or its affixes.
+  pub fn is_upgrade(&self) -> bool {
+    self.before.level != self.after.level || !self.affixes.is_empty()
+  }
+
+  fn is_change(&self) -> bool {
+    self.is_upgrade()
+      || self.before.equipped_by != self.after.equipped_by
+      || self.before.rarity != self.after.rarity
+      || self.before.locked != self.after.locked
+      || self.before.trashed != self.after.trashed
+  }
+}
+
+fn affixes_of(disk: &Disk) -> Vec<(&Affix, bool)> {
+  let secondary = disk.secondary_affixes.iter().flatten().map(|affix| (affix, false));
+  [(&disk.primary_affix, true)].into_iter().chain(secondary).collect()
+}
+
+fn affix_changes(before: &Disk, after: &Disk) -> Vec<AffixChange> {
+  let key =
+    |affix: &Affix, primary: bool| (affix.stat.clone(), affix.value_type, primary);
+  let before_affixes = affixes_of(before);
+  let after_affixes = affixes_of(after);
+
+  let mut result = vec![];
+  for (affix, primary) in before_affixes.iter() {
+    let after = after_affixes
+      .iter()
+      .find(|(other, other_primary)| key(other, *other_primary) == key(affix, *primary))
+      .map(|(other, _)| other.value);
+    if after != Some(affix.value) {
+      result.push(AffixChange {
+        stat: affix.stat.clone(),
+        value_type: affix.value_type,
+        primary: *primary,
+        before: Some(affix.value),
+        after
+      });
+    }
+  }
+  for (affix, primary) in after_affixes.iter() {
+    if !before_affixes
+      .iter()
+      .any(|(other, other_primary)| key(other, *other_primary) == key(affix, *primary))
+    {
+      result.push(AffixChange {
+        stat: affix.stat.clone(),
+        value_type: affix.value_type,
+        primary: *primary,
+        before: None,
+        after: Some(affix.value)
+      });
+    }
+  }
+  result
+}
+
+/// The differences between two inventories of an account.
+#[derive(Debug, Default)]
+pub struct InventoryDiff {
+  /// Disks only in the later inventory, by ID.
+  pub added: Vec<(i64, Disk)>,
+  /// Disks only in the earlier inventory, such as dismantled ones, by ID.
+  pub removed: Vec<(i64, Disk)>,
+  /// Disks that were enhanced, which may have other changes too.
+  pub upgraded: Vec<DiskChange>,
+  /// Disks whose equipped agent, rarity or marks changed, but not their
+  /// level or affixes.
+  pub changed: Vec<DiskChange>
+}
+
+impl InventoryDiff {
+  /// Compares two inventories. Disks are matched by ID first; a disk left in
+  /// the earlier inventory is then paired with a new disk it may have been
+  /// enhanced into, for when the enhanced disk was saved again rather than
+  /// matched to the stored one.
+  pub fn new(before: HashMap<i64, Disk>, mut after: HashMap<i64, Disk>) -> Self {
+    let mut result = Self::default();
+
+    let mut before: Vec<_> = before.into_iter().collect();
+    before.sort_by_key(|(id, _)| *id);
+    let mut unmatched = vec![];
+    for (id, disk) in before {
+      match after.remove(&id) {
+        Some(other) => result.push_change(DiskChange::new(id, disk, id, other)),
+        None => unmatched.push((id, disk))
+      }
+    }
+
+    let mut after: Vec<_> = after.into_iter().collect();
+    after.sort_by_key(|(id, _)| *id);
+    for (id, disk) in unmatched {
+      match after.iter().position(|(_, other)| other.is_upgrade_of(&disk)) {
+        Some(index) => {
+          let (after_id, other) = after.remove(index);
+          result.push_change(DiskChange::new(id, disk, after_id, other));
+        },
+        None => result.removed.push((id, disk))
+      }
+    }
+    result.added = after;
+
+    result
+  }
+
+  fn push_change(&mut self, change: DiskChange) {
+    if change.is_upgrade() {
+      self.upgraded.push(change);
+    } else if change.is_change() {
+      self.changed.push(change);
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.added.is_empty()
+      && self.removed.is_empty()
+      && self.upgraded.is_empty()
+      && self.changed.is_empty()
+  }
+
+  /// Renders the differences as a report naming sets, stats and agents in
+  /// `locale`.
+  pub fn report<'a>(
+    &'a self,
+    game_data: &'a GameData,
+    locale: &'a str
+  ) -> DiffReport<'a> {
+    DiffReport { diff: self, game_data, locale }
+  }
+}
+
+pub struct DiffReport<'a> {
+  diff: &'a InventoryDiff,
+  game_data: &'a GameData,
+  locale: &'a str
+}
+
+impl DiffReport<'_> {
+  fn write_value(
+    &self,
+    f: &mut std::fmt::Formatter<'_>,
+    value: Option<f64>,
+    value_type: AffixValueType
+  ) -> std::fmt::Result {
+    match (value, value_type) {
+      (Some(value), AffixValueType::Flat) => write!(f, "+{}", value),
+      (Some(value), AffixValueType::Percentage) => write!(f, "+{}%", value),
+      (None, _) => write!(f, "-")
+    }
+  }
+
+  fn write_change(
+    &self,
+    f: &mut std::fmt::Formatter<'_>,
+    change: &DiskChange
+  ) -> std::fmt::Result {
+    let (before, after) = (&change.before, &change.after);
+    if change.before_id == change.after_id {
+      write!(f, "  #{}", change.after_id)?;
+    } else {
+      write!(f, "  #{} → #{}", change.before_id, change.after_id)?;
+    }
+    write!(f, " {} [{}]", self.game_data.set_name(&after.set, self.locale), after.slot)?;
+    if before.level != after.level {
+      let level =
+        |level: Option<u8>| level.map_or("?".to_string(), |l| format!("+{}", l));
+      write!(f, " {} → {}", level(before.level), level(after.level))?;
+    }
+    if before.rarity != after.rarity {
+      let rarity =
+        |rarity: Option<_>| rarity.map_or("?".to_string(), |r| format!("{}", r));
+      write!(f, ", rarity {} → {}", rarity(before.rarity), rarity(after.rarity))?;
+    }
+    if before.equipped_by != after.equipped_by {
+      let agent = |disk: &Disk| {
+        disk.equipped_by.as_ref().map_or("none".to_string(), |agent| {
+          agent.name(self.game_data, self.locale).to_string()
+        })
+      };
+      write!(f, ", equipped {} → {}", agent(before), agent(after))?;
+    }
+    if before.locked != after.locked {
+      write!(f, ", {}", if after.locked { "locked" } else { "unlocked" })?;
+    }
+    if before.trashed != after.trashed {
+      write!(f, ", {}", if after.trashed { "marked as trash" } else { "unmarked" })?;
+    }
+    writeln!(f)?;
+
+    for affix in change.affixes.iter() {
+      write!(f, "    {} ", self.game_data.stat_name(&affix.stat, self.locale))?;
+      self.write_value(f, affix.before, affix.value_type)?;
+      write!(f, " → ")?;
+      self.write_value(f, affix.after, affix.value_type)?;
+      if affix.primary {
+        write!(f, " (primary)")?;
+      }
+      writeln!(f)?;
+    }
+    Ok(())
+  }
+}
+
+impl Display for DiffReport<'_> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let diff = self.diff;
+
+    if !diff.added.is_empty() {
+      writeln!(f, "New disks ({}):", diff.added.len())?;
+      for (id, disk) in diff.added.iter() {
+        writeln!(f, "  #{} {}", id, disk.display(self.game_data, self.locale))?;
+      }
+    }
+    if !diff.removed.is_empty() {
+      writeln!(f, "Gone disks ({}):", diff.removed.len())?;
+      for (id, disk) in diff.removed.iter() {
+        writeln!(f, "  #{} {}", id, disk.display(self.game_data, self.locale))?;
+      }
+    }
+    if !diff.upgraded.is_empty() {
+      writeln!(f, "Upgraded disks ({}):", diff.upgraded.len())?;
+      for change in diff.upgraded.iter() {
+        self.write_change(f, change)?;
+      }
+    }
+    if !diff.changed.is_empty() {
+      writeln!(f, "Changed disks ({}):", diff.changed.len())?;
+      for change in diff.changed.iter() {
+        self.write_change(f, change)?;
+      }
+    }
+
+    write!(
+      f,
+      "{} new, {} gone, {} upgraded, {} changed",
+      diff.added.len(),
+      diff.removed.len(),
+      diff.upgraded.len(),
+      diff.changed.len()
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {super::*, crate::disk::disk::EquippedBy};
+
+  fn disk(game_data: &GameData, set: &str, crit_rate: f64, level: u8) -> Disk {
+    let affix = |stat: &str, value: f64, value_type: &str| {
+      Affix::parse(game_data, stat, value, value_type).unwrap()
+    };
+    Disk::new(
+      game_data,
+      set,
+      1,
+      affix("hp", 550.0, "flat"),
+      Some(affix("crit_rate", crit_rate, "percentage")),
+      Some(affix("atk", 19.0, "flat")),
+      None,
+      None
+    )
+    .unwrap()
+    .with_level(Some(level))
+    .unwrap()
+  }
+
+  fn ids(changes: &[DiskChange]) -> Vec<(i64, i64)> {
+    changes.iter().map(|change| (change.before_id, change.after_id)).collect()
+  }
+
+  #[test]
+  fn matches_disks_by_id() {
+    let game_data = GameData::bundled();
+    let before = HashMap::from([
+      (1, disk(&game_data, "chaos_jazz", 2.4, 0)),
+      (2, disk(&game_data, "chaos_jazz", 2.4, 0)),
+      (3, disk(&game_data, "woodpecker_electro", 2.4, 0))
+    ]);
+    let equipped = disk(&game_data, "chaos_jazz", 2.4, 0)
+      .with_equipped_by(Some(EquippedBy::Agent("anby".to_string())));
+    let after = HashMap::from([
+      (1, disk(&game_data, "chaos_jazz", 4.8, 3)),
+      (2, equipped),
+      (3, disk(&game_data, "woodpecker_electro", 2.4, 0))
+    ]);
+
+    let diff = InventoryDiff::new(before, after);
+    assert_eq!(ids(&diff.upgraded), [(1, 1)]);
+    assert_eq!(
+      diff.upgraded[0].affixes,
+      [AffixChange {
+        stat: "crit_rate".to_string(),
+        value_type: AffixValueType::Percentage,
+        primary: false,
+        before: Some(2.4),
+        after: Some(4.8)
+      }]
+    );
+    assert_eq!(ids(&diff.changed), [(2, 2)]);
+    assert!(!diff.changed[0].is_upgrade());
+    assert!(diff.added.is_empty() && diff.removed.is_empty());
+  }
+
+  #[test]
+  fn pairs_upgrades_saved_again() {
+    let game_data = GameData::bundled();
+    let before = HashMap::from([
+      (1, disk(&game_data, "chaos_jazz", 2.4, 0)),
+      (2, disk(&game_data, "woodpecker_electro", 2.4, 0))
+    ]);
+    let after = HashMap::from([
+      (4, disk(&game_data, "chaos_jazz", 2.4, 0)),
+      (5, disk(&game_data, "chaos_jazz", 4.8, 3)),
+      (6, disk(&game_data, "woodpecker_electro", 1.2, 3))
+    ]);
+
+    let diff = InventoryDiff::new(before, after);
+    // The unchanged copy comes first, so it is paired rather than the upgrade
+    assert!(diff.upgraded.is_empty() && diff.changed.is_empty());
+    let added: Vec<_> = diff.added.iter().map(|(id, _)| *id).collect();
+    assert_eq!(added, [5, 6]);
+    let removed: Vec<_> = diff.removed.iter().map(|(id, _)| *id).collect();
+    assert_eq!(removed, [2]);
+  }
+
+  #[test]
+  fn reports_added_and_removed_disks() {
+    let game_data = GameData::bundled();
+    let before = HashMap::from([
+      (1, disk(&game_data, "chaos_jazz", 2.4, 0)),
+      (2, disk(&game_data, "woodpecker_electro", 2.4, 0))
+    ]);
+    let after = HashMap::from([
+      (2, disk(&game_data, "woodpecker_electro", 2.4, 0)),
+      (3, disk(&game_data, "woodpecker_electro", 4.8, 0)),
+      (4, disk(&game_data, "chaos_jazz", 2.4, 3))
+    ]);
+
+    let diff = InventoryDiff::new(before, after);
+    assert_eq!(ids(&diff.upgraded), [(1, 4)]);
+    assert!(diff.upgraded[0].affixes.is_empty());
+    let added: Vec<_> = diff.added.iter().map(|(id, _)| *id).collect();
+    assert_eq!(added, [3]);
+    assert!(diff.removed.is_empty() && diff.changed.is_empty());
+
+    let diff = InventoryDiff::new(
+      HashMap::from([(1, disk(&game_data, "chaos_jazz", 2.4, 0))]),
+      HashMap::new()
+    );
+    assert_eq!(diff.removed.len(), 1);
+    assert!(InventoryDiff::new(HashMap::new(), HashMap::new()).is_empty());
+  }
+}
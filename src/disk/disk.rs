@@ -1,9 +1,6 @@
-use {
-  crate::source::source::{DISK_AFFIX_NAMES, DISK_SETS},
-  std::fmt::Display
-};
+use {crate::source::game_data::GameData, std::fmt::Display};
 
-#[derive(Copy, Clone, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum AffixValueType {
   Flat,
   Percentage
@@ -31,42 +28,136 @@ impl Display for AffixValueType {
   }
 }
 
-#[derive(Clone)]
+/// Rarity of a disk, which bounds its level.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Rarity {
+  B,
+  A,
+  S
+}
+
+impl Rarity {
+  pub const ALL: [Self; 3] = [Self::S, Self::A, Self::B];
+
+  /// The highest level a disk of this rarity can be enhanced to.
+  pub fn max_level(&self) -> u8 {
+    match self {
+      Self::B => 9,
+      Self::A => 12,
+      Self::S => 15
+    }
+  }
+}
+
+impl TryFrom<&str> for Rarity {
+  type Error = String;
+
+  fn try_from(value: &str) -> Result<Self, Self::Error> {
+    match value {
+      "S" => Ok(Self::S),
+      "A" => Ok(Self::A),
+      "B" => Ok(Self::B),
+      _ => Err(format!("Invalid rarity: {}", value))
+    }
+  }
+}
+
+impl Display for Rarity {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let str = match self {
+      Self::S => "S",
+      Self::A => "A",
+      Self::B => "B"
+    };
+    write!(f, "{}", str)
+  }
+}
+
+#[derive(Clone, Debug)]
 pub struct Affix {
-  pub name: &'static str,
+  /// ID of the stat in the game data.
+  pub stat: String,
   pub value: f64,
   pub value_type: AffixValueType
 }
 
 impl Affix {
-  pub fn new(name: &str, value: f64, value_type: AffixValueType) -> Result<Self, String> {
-    let name: &'static str = match DISK_AFFIX_NAMES.iter().find(|&&str| str == name) {
-      Some(str) => str,
-      None => return Err(format!("Invalid affix name: {}", name))
+  pub fn new(
+    game_data: &GameData,
+    stat: &str,
+    value: f64,
+    value_type: AffixValueType
+  ) -> Result<Self, String> {
+    let stat = match game_data.stat(stat) {
+      Some(stat) => stat,
+      None => return Err(format!("Invalid affix stat: {}", stat))
     };
 
-    Ok(Self { name, value, value_type })
+    if !stat.value_types.contains(&value_type) {
+      return Err(format!("Invalid value type for {}: {}", stat.id, value_type));
+    }
+
+    Ok(Self { stat: stat.id.clone(), value, value_type })
+  }
+
+  pub fn new_simple(
+    game_data: &GameData,
+    stat: &str,
+    value: f64
+  ) -> Result<Self, String> {
+    Self::new(game_data, stat, value, AffixValueType::Flat)
+  }
+
+  /// Builds an affix from its stored representation, with the value type
+  /// given as a string.
+  pub fn parse(
+    game_data: &GameData,
+    stat: &str,
+    value: f64,
+    value_type: &str
+  ) -> Result<Self, String> {
+    let value_type = AffixValueType::try_from(value_type)?;
+    Self::new(game_data, stat, value, value_type)
+  }
+
+  /// Number of rolls that make up the value of a secondary affix, from the
+  /// value of one roll of its stat; `None` for stats without rolls.
+  pub fn rolls(&self, game_data: &GameData) -> Option<u32> {
+    let roll = game_data.stat(&self.stat)?.substat_roll(self.value_type)?;
+    let rolls = (self.value / roll).round();
+    (rolls >= 1.0).then_some(rolls as u32)
   }
 
-  pub fn new_simple(name: &str, value: f64) -> Result<Self, String> {
-    Self::new(name, value, AffixValueType::Flat)
+  /// Renders the affix with the stat name of `locale`, e.g. `暴击率 +2.4%`.
+  pub fn display<'a>(
+    &'a self,
+    game_data: &'a GameData,
+    locale: &'a str
+  ) -> AffixDisplay<'a> {
+    AffixDisplay { affix: self, game_data, locale }
   }
 }
 
-impl TryFrom<(&str, f64, &str)> for Affix {
-  type Error = String;
+pub struct AffixDisplay<'a> {
+  affix: &'a Affix,
+  game_data: &'a GameData,
+  locale: &'a str
+}
 
-  fn try_from(value: (&str, f64, &str)) -> Result<Self, Self::Error> {
-    let (name, value, value_type) = value;
-    let value_type = AffixValueType::try_from(value_type)?;
-    Self::new(name, value, value_type)
+impl Display for AffixDisplay<'_> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let name = self.game_data.stat_name(&self.affix.stat, self.locale);
+    match self.affix.value_type {
+      AffixValueType::Flat => write!(f, "{} +{}", name, self.affix.value),
+      AffixValueType::Percentage => write!(f, "{} +{}%", name, self.affix.value)
+    }
   }
 }
 
 impl PartialEq for Affix {
   fn eq(&self, other: &Self) -> bool {
-    self as *const _ == other as *const _
-      || (self.name == other.name
+    std::ptr::eq(self, other)
+      || (self.stat == other.stat
         && self.value == other.value
         && self.value_type == other.value_type)
   }
@@ -75,15 +166,58 @@ impl PartialEq for Affix {
 impl Eq for Affix {
 }
 
+/// The agent a disk is equipped on, as read from the panel.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EquippedBy {
+  /// ID of an agent in the game data.
+  Agent(String),
+  /// A name that matched no known agent, kept as read.
+  Unknown(String)
+}
+
+impl EquippedBy {
+  /// Identifies the agent shown as `name` by the client of `locale`, keeping
+  /// the name itself when no agent has it.
+  pub fn from_name(game_data: &GameData, locale: &str, name: &str) -> Self {
+    match game_data.find_agent(locale, name) {
+      Some(agent) => Self::Agent(agent.id.clone()),
+      None => Self::Unknown(name.to_string())
+    }
+  }
+
+  /// Returns the agent's name in `locale`, or the raw text of an unknown
+  /// agent.
+  pub fn name<'a>(&'a self, game_data: &'a GameData, locale: &str) -> &'a str {
+    match self {
+      Self::Agent(id) => game_data.agent_name(id, locale),
+      Self::Unknown(raw) => raw
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
 pub struct Disk {
-  pub set: &'static str,
+  /// ID of the set in the game data.
+  pub set: String,
   pub slot: u8,
   pub primary_affix: Affix,
-  pub secondary_affixes: [Option<Affix>; 4]
+  pub secondary_affixes: [Option<Affix>; 4],
+  /// The agent the disk is equipped on; `None` for unequipped disks.
+  pub equipped_by: Option<EquippedBy>,
+  /// `None` when the rarity wasn't read.
+  pub rarity: Option<Rarity>,
+  /// Enhancement level; `None` when it wasn't read.
+  pub level: Option<u8>,
+  /// Whether the disk is locked in the game.
+  pub locked: bool,
+  /// Whether the disk is marked as trash in the game.
+  pub trashed: bool
 }
 
 impl Disk {
+  #[allow(clippy::too_many_arguments)]
   pub fn new(
+    game_data: &GameData,
     set: &str,
     slot: u8,
     primary_affix: Affix,
@@ -92,11 +226,25 @@ impl Disk {
     secondary_affix_3: Option<Affix>,
     secondary_affix_4: Option<Affix>
   ) -> Result<Self, String> {
-    let set: &'static str = match DISK_SETS.iter().find(|&&str| str == set) {
-      Some(str) => str,
+    let set = match game_data.set(set) {
+      Some(set) => set.id.clone(),
       None => return Err(format!("Invalid disk set: {}", set))
     };
 
+    let slot_rule = match game_data.slot(slot) {
+      Some(slot_rule) => slot_rule,
+      None => return Err(format!("Invalid slot: {}", slot))
+    };
+
+    if !slot_rule.primary.iter().any(|(stat, value_type)| {
+      *stat == primary_affix.stat && *value_type == primary_affix.value_type
+    }) {
+      return Err(format!(
+        "Invalid primary affix for slot {}: {} ({})",
+        slot, primary_affix.stat, primary_affix.value_type
+      ));
+    }
+
     Ok(Self {
       set,
       slot,
@@ -106,11 +254,48 @@ impl Disk {
         secondary_affix_2,
         secondary_affix_3,
         secondary_affix_4
-      ]
+      ],
+      equipped_by: None,
+      rarity: None,
+      level: None,
+      locked: false,
+      trashed: false
     })
   }
 
+  pub fn with_equipped_by(mut self, equipped_by: Option<EquippedBy>) -> Self {
+    self.equipped_by = equipped_by;
+    self
+  }
+
+  pub fn with_rarity(mut self, rarity: Option<Rarity>) -> Self {
+    self.rarity = rarity;
+    self
+  }
+
+  /// Sets the level, which must not exceed the maximum of the disk's rarity,
+  /// or of S rank when the rarity is unknown.
+  pub fn with_level(mut self, level: Option<u8>) -> Result<Self, String> {
+    let max_level = self.rarity.unwrap_or(Rarity::S).max_level();
+    if let Some(level) = level.filter(|level| *level > max_level) {
+      return Err(format!("Invalid level: {} (at most {})", level, max_level));
+    }
+    self.level = level;
+    Ok(self)
+  }
+
+  pub fn with_locked(mut self, locked: bool) -> Self {
+    self.locked = locked;
+    self
+  }
+
+  pub fn with_trashed(mut self, trashed: bool) -> Self {
+    self.trashed = trashed;
+    self
+  }
+
   pub fn new_simple(
+    game_data: &GameData,
     set: &str,
     slot: u8,
     primary_affix: Affix,
@@ -119,6 +304,7 @@ impl Disk {
     secondary_affix_3: Affix
   ) -> Result<Self, String> {
     Self::new(
+      game_data,
       set,
       slot,
       primary_affix,
@@ -140,18 +326,98 @@ impl Disk {
         && self.secondary_affixes[3] == other.secondary_affixes[3])
   }
 
-  pub fn get_static_set_name(set: &str) -> Option<&'static str> {
-    DISK_SETS.iter().find(|&&str| str == set).map(|&str| str)
+  /// Whether both disks have the same set, slot and affixes, in whatever
+  /// order the secondary affixes are.
+  pub fn same_affixes(&self, other: &Self) -> bool {
+    self.set == other.set
+      && self.slot == other.slot
+      && self.primary_affix == other.primary_affix
+      && self.secondary_affixes.iter().flatten().count()
+        == other.secondary_affixes.iter().flatten().count()
+      && pair_secondaries(other, self, |before, after| before == after)
+  }
+
+  /// Whether the disk may be `before` enhanced: the same set, slot and
+  /// primary stat, at least the same level, and every secondary stat of
+  /// `before` kept at no lower value.
+  pub fn is_upgrade_of(&self, before: &Self) -> bool {
+    let level_kept = match (before.level, self.level) {
+      (Some(before), Some(after)) => after >= before,
+      _ => true
+    };
+
+    self.set == before.set
+      && self.slot == before.slot
+      && self.primary_affix.stat == before.primary_affix.stat
+      && self.primary_affix.value_type == before.primary_affix.value_type
+      && self.primary_affix.value >= before.primary_affix.value
+      && level_kept
+      && pair_secondaries(before, self, |before, after| {
+        after.stat == before.stat
+          && after.value_type == before.value_type
+          && after.value >= before.value
+      })
+  }
+}
+
+/// Whether every secondary affix of `before` can be paired with a different
+/// secondary affix of `after` for which `pairs` holds.
+fn pair_secondaries(
+  before: &Disk,
+  after: &Disk,
+  pairs: impl Fn(&Affix, &Affix) -> bool
+) -> bool {
+  let mut unpaired: Vec<&Affix> = after.secondary_affixes.iter().flatten().collect();
+  before.secondary_affixes.iter().flatten().all(|affix| {
+    match unpaired.iter().position(|other| pairs(affix, other)) {
+      Some(index) => {
+        unpaired.remove(index);
+        true
+      },
+      None => false
+    }
+  })
+}
+
+impl Disk {
+  /// Renders the disk with the set and stat names of `locale`.
+  pub fn display<'a>(
+    &'a self,
+    game_data: &'a GameData,
+    locale: &'a str
+  ) -> DiskDisplay<'a> {
+    DiskDisplay { disk: self, game_data, locale }
   }
+}
 
-  pub fn get_static_affix_name(name: &str) -> Option<&'static str> {
-    DISK_AFFIX_NAMES.iter().find(|&&str| str == name).map(|&str| str)
+pub struct DiskDisplay<'a> {
+  disk: &'a Disk,
+  game_data: &'a GameData,
+  locale: &'a str
+}
+
+impl Display for DiskDisplay<'_> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "{} [{}] {}",
+      self.game_data.set_name(&self.disk.set, self.locale),
+      self.disk.slot,
+      self.disk.primary_affix.display(self.game_data, self.locale)
+    )?;
+    for affix in self.disk.secondary_affixes.iter().flatten() {
+      write!(f, ", {}", affix.display(self.game_data, self.locale))?;
+    }
+    if let Some(equipped_by) = self.disk.equipped_by.as_ref() {
+      write!(f, " @ {}", equipped_by.name(self.game_data, self.locale))?;
+    }
+    Ok(())
   }
 }
 
 impl PartialEq for Disk {
   fn eq(&self, other: &Self) -> bool {
-    self as *const _ == other as *const _
+    std::ptr::eq(self, other)
   }
 }
 
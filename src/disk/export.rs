@@ -0,0 +1,63 @@
+use crate::{disk::disk::Disk, source::game_data::GameData};
+
+pub fn escape_csv(field: &str) -> String {
+  if field.contains([',', '"', '\n']) {
+    format!("\"{}\"", field.replace('"', "\"\""))
+  } else {
+    field.to_string()
+  }
+}
+
+/// Writes the disks as CSV with one row per disk, naming sets and stats in
+/// `locale`.
+pub fn disks_to_csv(
+  disks: &[(i64, &Disk)],
+  game_data: &GameData,
+  locale: &str
+) -> String {
+  let mut result =
+    String::from("id,set,slot,primary_affix,primary_value,primary_value_type");
+  for i in 1 ..= 4 {
+    result.push_str(&format!(
+      ",secondary_affix_{0},secondary_value_{0},secondary_value_type_{0}",
+      i
+    ));
+  }
+  result.push_str(",equipped_by,rarity,level,locked,trashed\n");
+
+  for (id, disk) in disks {
+    result.push_str(&format!(
+      "{},{},{},{},{},{}",
+      id,
+      escape_csv(game_data.set_name(&disk.set, locale)),
+      disk.slot,
+      escape_csv(game_data.stat_name(&disk.primary_affix.stat, locale)),
+      disk.primary_affix.value,
+      disk.primary_affix.value_type
+    ));
+    for affix in disk.secondary_affixes.iter() {
+      match affix {
+        Some(affix) => {
+          result.push_str(&format!(
+            ",{},{},{}",
+            escape_csv(game_data.stat_name(&affix.stat, locale)),
+            affix.value,
+            affix.value_type
+          ))
+        },
+        None => result.push_str(",,,")
+      }
+    }
+    let equipped_by = disk.equipped_by.as_ref().map_or("", |e| e.name(game_data, locale));
+    result.push_str(&format!(
+      ",{},{},{},{},{}\n",
+      escape_csv(equipped_by),
+      disk.rarity.map_or(String::new(), |rarity| rarity.to_string()),
+      disk.level.map_or(String::new(), |level| level.to_string()),
+      disk.locked,
+      disk.trashed
+    ));
+  }
+
+  result
+}
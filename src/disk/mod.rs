@@ -1 +1,4 @@
+pub mod diff;
+#[allow(clippy::module_inception)]
 pub mod disk;
+pub mod export;
@@ -0,0 +1,5 @@
+pub mod database;
+pub mod disk;
+pub mod ocr;
+pub mod scan;
+pub mod source;
@@ -1,7 +1,1526 @@
-mod disk;
-mod source;
-mod database;
+use {
+  clap::{Args, Parser, Subcommand},
+  image::GrayImage,
+  std::{
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode
+  },
+  zenless_disk_ocr::{
+    database::{
+      account::{
+        delete_account,
+        insert_account,
+        read_account,
+        read_accounts,
+        DEFAULT_ACCOUNT
+      },
+      alias::{delete_alias, insert_alias, prune_aliases, read_aliases},
+      database::{
+        database_path,
+        default_database_path,
+        delete_disk,
+        get_disk,
+        init_database,
+        legacy_database_path,
+        read_disk,
+        set_database_path,
+        update_disk,
+        RowError,
+        Saved
+      },
+      journal::{read_journal, redo, undo, JournalEntry},
+      provenance::{
+        insert_scanned_disk,
+        read_scan_records,
+        start_scan_session,
+        ScanRecord
+      },
+      quarantine::{read_quarantine, release_quarantine},
+      query::{query_disks, DiskOrder, DiskQuery, EquippedFilter, StatFilter},
+      snapshot::{
+        delete_snapshot,
+        read_snapshot,
+        read_snapshots,
+        take_snapshot,
+        InventorySnapshot
+      }
+    },
+    disk::{
+      diff::InventoryDiff,
+      disk::{Affix, Disk, EquippedBy, Rarity},
+      export::{disks_to_csv, escape_csv}
+    },
+    ocr::engine::TesseractEngine,
+    scan::{
+      calibrate::{annotate, calibrate, ANNOTATION_COLORS},
+      input::{
+        default_templates_path,
+        detect_input_method,
+        InputMethod,
+        PromptTemplates
+      },
+      language::detect_language,
+      layout::{Layouts, Rect, Region},
+      profile::ScanProfile,
+      scanner::DiskScanner,
+      screenshot::ScreenshotLayout
+    },
+    source::{
+      alias::{AliasTable, WordScope},
+      ambiguity::get_ambiguity_report,
+      game_data::GameData,
+      vocabulary::Vocabulary
+    }
+  }
+};
 
-fn main() {
-  println!("Hello, world!");
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+  /// Load game data from this TOML or JSON bundle instead of the bundled one
+  #[arg(long, global = true)]
+  game_data: Option<PathBuf>,
+
+  /// Load scan settings, such as the client language, from this profile
+  #[arg(long, global = true)]
+  profile: Option<PathBuf>,
+
+  /// Locale of the game client, used for matching and for rendering names;
+  /// overrides the profile and defaults to the default locale of the game
+  /// data
+  #[arg(long, global = true)]
+  locale: Option<String>,
+
+  /// ID of the font profile matching the client's font scheme; overrides the
+  /// profile, and every font profile is tried when neither selects one
+  #[arg(long, global = true)]
+  font: Option<String>,
+
+  /// Load layout profiles from this TOML or JSON bundle instead of the
+  /// bundled ones
+  #[arg(long, global = true)]
+  layouts: Option<PathBuf>,
+
+  /// ID of the layout profile to use; overrides the profile, and the layout
+  /// is picked from each screenshot's aspect ratio when neither selects one
+  #[arg(long, global = true)]
+  layout: Option<String>,
+
+  /// Control layout of the client, `keyboard_mouse` or `controller`;
+  /// overrides the profile, and it is detected from the button prompts when
+  /// neither sets it
+  #[arg(long, global = true)]
+  input: Option<String>,
+
+  /// Directory of button prompt templates, with a `keyboard_mouse` and a
+  /// `controller` subdirectory of PNG files; defaults to the bundled
+  /// `data/templates`
+  #[arg(long, global = true)]
+  templates: Option<PathBuf>,
+
+  /// Use the regions of the layout as they are instead of locating the disk
+  /// panel in each screenshot
+  #[arg(long, global = true)]
+  fixed_regions: bool,
+
+  /// Database file to use; overrides the profile and defaults to
+  /// `zenlessDiskOCR/database.db` in the user's data directory, or to the
+  /// `database.db` an earlier version left in the working directory until it
+  /// is moved there
+  #[arg(long, global = true)]
+  database: Option<PathBuf>,
+
+  /// UID of the game account whose inventory is used; overrides the profile
+  /// and defaults to the `default` account
+  #[arg(long, global = true)]
+  account: Option<String>,
+
+  #[command(subcommand)]
+  command: Command
+}
+
+#[derive(Subcommand)]
+enum Command {
+  /// List vocabulary words that OCR is likely to confuse with each other
+  Ambiguity {
+    /// Maximum edit distance between two words to report them
+    #[arg(short, long, default_value_t = 1)]
+    distance: usize,
+
+    /// Read the vocabulary from a file with one word per line instead of the
+    /// bundled one
+    #[arg(long)]
+    vocabulary: Option<PathBuf>
+  },
+
+  /// Manage corrections learned from fixed OCR misreads, those of the chosen
+  /// locale
+  #[command(subcommand)]
+  Aliases(AliasCommand),
+
+  /// Manage the game accounts kept in the database
+  #[command(subcommand)]
+  Accounts(AccountCommand),
+
+  /// Manage the stored disks that don't fit the game data
+  #[command(subcommand)]
+  Quarantine(QuarantineCommand),
+
+  /// Take snapshots of the account's inventory and compare them
+  #[command(subcommand)]
+  Snapshots(SnapshotCommand),
+
+  /// Validate the game data bundle and print a summary of it
+  GameData,
+
+  /// Detect the client language from a screenshot of a disk panel
+  DetectLanguage {
+    /// Screenshot of the panel
+    image: PathBuf,
+
+    /// Save the detected language, or the one given with `--locale`, as a
+    /// scan profile
+    #[arg(long)]
+    save_profile: Option<PathBuf>
+  },
+
+  /// Show the layout picked for a screenshot and where its regions are
+  Layout {
+    /// Screenshot to lay out
+    image: PathBuf
+  },
+
+  /// Fit the layouts to a reference screenshot and save the regions as a
+  /// custom layout profile
+  Calibrate {
+    /// Reference screenshot showing a disk panel
+    image: PathBuf,
+
+    /// Layouts file to add the profile to, created if missing
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// ID of the new layout profile
+    #[arg(long, default_value = "custom")]
+    id: String,
+
+    /// Write the screenshot with the regions outlined to this PNG file
+    #[arg(long)]
+    annotated: Option<PathBuf>,
+
+    /// Correct a region in pixels of the screenshot, e.g.
+    /// `panel=500x760+1360+140`; may be repeated
+    #[arg(long = "region", value_parser = parse_region_rect)]
+    regions: Vec<(Region, Rect)>
+  },
+
+  /// Read disks from screenshots of the disk panel and save them to the
+  /// account's inventory. The screenshots of one run are one sweep of the
+  /// inventory: a disk read twice is two disks with the same stats
+  Scan {
+    /// Screenshots of disk panels
+    #[arg(required = true)]
+    images: Vec<PathBuf>,
+
+    /// Store the crop of each region with the record of the scan, as shown by
+    /// `provenance --crops`
+    #[arg(long)]
+    keep_crops: bool
+  },
+
+  /// Save the current settings as a scan profile
+  SaveProfile {
+    /// Path of the profile to write
+    path: PathBuf
+  },
+
+  /// List the disks of the account that match the filters, a page at a time
+  Disks {
+    #[command(flatten)]
+    query: QueryArgs,
+
+    /// Page to show, counting from 1
+    #[arg(long, default_value_t = 1)]
+    page: u32,
+
+    /// Number of disks per page
+    #[arg(long, default_value_t = 20)]
+    page_size: u32
+  },
+
+  /// Write the disks of the account that match the filters as CSV, with
+  /// names in the chosen locale
+  Export {
+    #[command(flatten)]
+    query: QueryArgs,
+
+    /// Write to this file instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>
+  },
+
+  /// Correct a stored disk; fields not given are kept
+  Update {
+    /// ID of the disk, as listed by `disks`
+    id: i64,
+
+    #[command(flatten)]
+    changes: DiskChanges
+  },
+
+  /// Delete stored disks; deletions can be undone
+  Delete {
+    /// IDs of the disks, as listed by `disks`
+    #[arg(required = true)]
+    ids: Vec<i64>
+  },
+
+  /// Undo the last changes to the account's disks
+  Undo {
+    /// Number of changes to undo
+    #[arg(short = 'n', long, default_value_t = 1)]
+    count: u32
+  },
+
+  /// Redo the last undone changes to the account's disks
+  Redo {
+    /// Number of changes to redo
+    #[arg(short = 'n', long, default_value_t = 1)]
+    count: u32
+  },
+
+  /// List the last changes to the account's disks, newest first
+  History {
+    /// Number of changes to list
+    #[arg(long, default_value_t = 20)]
+    limit: u32
+  },
+
+  /// Show the recorded scans that saved a disk and what was read from each
+  /// region
+  Provenance {
+    /// ID of the disk, as listed by `disks`
+    id: i64,
+
+    /// Write the stored crops of the regions to this directory as PNG files
+    #[arg(long)]
+    crops: Option<PathBuf>
+  }
+}
+
+/// Corrections to a stored disk. Sets, stats and agents are given by ID or by
+/// name in the chosen locale.
+#[derive(Args)]
+struct DiskChanges {
+  #[arg(long)]
+  set: Option<String>,
+
+  #[arg(long)]
+  slot: Option<u8>,
+
+  /// `S`, `A` or `B`
+  #[arg(long, value_parser = parse_rarity)]
+  rarity: Option<Rarity>,
+
+  #[arg(long)]
+  level: Option<u8>,
+
+  #[arg(long)]
+  locked: Option<bool>,
+
+  #[arg(long)]
+  trashed: Option<bool>,
+
+  /// Agent the disk is equipped on, or `none`
+  #[arg(long)]
+  equipped: Option<String>,
+
+  /// Primary affix as `STAT=VALUE`, with `:flat` or `:percentage` after the
+  /// stat when it has both
+  #[arg(long)]
+  primary: Option<String>,
+
+  /// Secondary affix at a position from 1 to 4, as `POSITION=STAT=VALUE`
+  /// with the stat written as for `--primary`; may be repeated
+  #[arg(long = "secondary")]
+  secondaries: Vec<String>,
+
+  /// Remove the secondary affix at a position from 1 to 4; may be repeated
+  #[arg(long = "remove-secondary")]
+  removed_secondaries: Vec<usize>
+}
+
+/// Filters and ordering of stored disks. Sets, stats and agents are given by
+/// ID or by name in the chosen locale.
+#[derive(Args)]
+struct QueryArgs {
+  /// Only disks of this set; may be repeated
+  #[arg(long = "set")]
+  sets: Vec<String>,
+
+  /// Only disks in this slot; may be repeated
+  #[arg(long = "slot")]
+  slots: Vec<u8>,
+
+  /// Only disks of this rarity, `S`, `A` or `B`; may be repeated
+  #[arg(long = "rarity", value_parser = parse_rarity)]
+  rarities: Vec<Rarity>,
+
+  #[arg(long)]
+  min_level: Option<u8>,
+
+  #[arg(long)]
+  max_level: Option<u8>,
+
+  /// Only disks with this primary stat, optionally followed by `:flat` or
+  /// `:percentage`; may be repeated
+  #[arg(long = "primary")]
+  primary_stats: Vec<String>,
+
+  /// Only disks with this secondary stat, written as for `--primary` and
+  /// optionally followed by a minimum value, e.g. `crit_dmg>=20`; may be
+  /// repeated
+  #[arg(long = "secondary")]
+  secondary_stats: Vec<String>,
+
+  /// Only locked disks with `true`, or unlocked ones with `false`
+  #[arg(long)]
+  locked: Option<bool>,
+
+  /// Only disks marked as trash with `true`, or unmarked ones with `false`
+  #[arg(long)]
+  trashed: Option<bool>,
+
+  /// Only disks equipped on this agent, on `any` agent, or on `none`
+  #[arg(long)]
+  equipped: Option<String>,
+
+  /// Only disks scanned on or after this day, `YYYY-MM-DD` in UTC
+  #[arg(long, value_parser = parse_date)]
+  scanned_after: Option<i64>,
+
+  /// Only disks scanned before this day, `YYYY-MM-DD` in UTC
+  #[arg(long, value_parser = parse_date)]
+  scanned_before: Option<i64>,
+
+  /// Order by `id`, `set`, `slot`, `rarity`, `level`, `primary_value`,
+  /// `scanned_at`, `score` or a secondary stat, descending with a `-`
+  /// prefix; may be repeated
+  #[arg(long = "sort", allow_hyphen_values = true)]
+  order: Vec<String>,
+
+  /// Weight of a secondary stat in the score, as `STAT=WEIGHT`; may be
+  /// repeated, and every stat weighs 1 when none is given
+  #[arg(long = "weight")]
+  weights: Vec<String>
+}
+
+#[derive(Subcommand)]
+enum AccountCommand {
+  /// List the accounts and the size of their inventories
+  List,
+
+  /// Add an account, or rename an existing one
+  Add {
+    /// The account's UID in the game
+    uid: String,
+
+    /// Name to show for the account
+    #[arg(long)]
+    name: Option<String>
+  },
+
+  /// Delete an account together with its inventory
+  Remove { uid: String }
+}
+
+#[derive(Subcommand)]
+enum QuarantineCommand {
+  /// List the quarantined disks of the account and why they can't be read
+  List,
+
+  /// Read every disk of the account, quarantining those that can't be read
+  Check,
+
+  /// Check quarantined disks again after repairing them, releasing those that
+  /// can be read now
+  Release {
+    /// IDs of the disks; every quarantined disk when none is given
+    ids: Vec<i64>
+  }
+}
+
+#[derive(Subcommand)]
+enum SnapshotCommand {
+  /// List the snapshots of the account, oldest first
+  List,
+
+  /// Save a snapshot of the account's current inventory
+  Take {
+    /// Name to show for the snapshot, e.g. the week it was taken
+    #[arg(long)]
+    name: Option<String>
+  },
+
+  /// Delete a snapshot
+  Remove { id: i64 },
+
+  /// Report the disks gained, lost, upgraded and changed between two
+  /// snapshots
+  Diff {
+    /// ID of the earlier snapshot
+    from: i64,
+
+    /// ID of the later snapshot; the latest one when not given
+    to: Option<i64>
+  }
+}
+
+#[derive(Subcommand)]
+enum AliasCommand {
+  /// List the learned aliases
+  List,
+
+  /// Learn that `raw` read from a region of `scope` means `canonical`
+  Add {
+    /// One of `set`, `affix`, `fixed` or `agent`
+    #[arg(value_parser = parse_scope)]
+    scope: WordScope,
+    raw: String,
+    canonical: String,
+
+    #[arg(long)]
+    vocabulary: Option<PathBuf>
+  },
+
+  /// Forget a learned alias
+  Remove {
+    #[arg(value_parser = parse_scope)]
+    scope: WordScope,
+    raw: String
+  },
+
+  /// Write the learned aliases as CSV
+  Export {
+    /// Write to this file instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>
+  },
+
+  /// Delete aliases that no longer apply
+  Prune {
+    /// Delete aliases whose target left the vocabulary or whose raw text is a
+    /// vocabulary word itself
+    #[arg(long)]
+    stale: bool,
+
+    /// Delete aliases not recorded again for this many days
+    #[arg(long)]
+    older_than: Option<u64>,
+
+    #[arg(long)]
+    vocabulary: Option<PathBuf>
+  }
+}
+
+fn parse_region_rect(value: &str) -> Result<(Region, Rect), String> {
+  let (region, rect) = value
+    .split_once('=')
+    .ok_or(format!("Expected REGION=WIDTHxHEIGHT+X+Y: {}", value))?;
+  Ok((Region::try_from(region.trim())?, Rect::try_from(rect)?))
+}
+
+fn parse_rarity(value: &str) -> Result<Rarity, String> {
+  Rarity::try_from(value)
+}
+
+/// Parses a `YYYY-MM-DD` date into the Unix time of its start in UTC.
+fn parse_date(value: &str) -> Result<i64, String> {
+  let invalid = || format!("Invalid date, expected YYYY-MM-DD: {}", value);
+  let mut parts = value.splitn(3, '-').map(|part| part.parse::<i64>());
+  let (Some(Ok(year)), Some(Ok(month)), Some(Ok(day))) =
+    (parts.next(), parts.next(), parts.next())
+  else {
+    return Err(invalid());
+  };
+  if !(1 ..= 12).contains(&month) || !(1 ..= 31).contains(&day) {
+    return Err(invalid());
+  }
+
+  // Days since 1970-01-01 in the proleptic Gregorian calendar, counting
+  // years from March so that leap days come last
+  let year = if month <= 2 { year - 1 } else { year };
+  let era = year.div_euclid(400);
+  let year_of_era = year - era * 400;
+  let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+  let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+  Ok((era * 146097 + day_of_era - 719468) * 24 * 60 * 60)
+}
+
+fn parse_scope(value: &str) -> Result<WordScope, String> {
+  WordScope::try_from(value)
+}
+
+fn load_vocabulary(
+  game_data: &GameData,
+  locale: &str,
+  path: Option<PathBuf>
+) -> Result<Vocabulary, String> {
+  match path {
+    Some(path) => Vocabulary::from_file(path),
+    None => Ok(Vocabulary::from_game_data(game_data, locale))
+  }
+}
+
+/// Loads the vocabulary words of `scope` are corrected to: the agent names
+/// for the agent scope, unless a vocabulary file is given.
+fn load_scope_vocabulary(
+  game_data: &GameData,
+  locale: &str,
+  scope: WordScope,
+  path: Option<PathBuf>
+) -> Result<Vocabulary, String> {
+  match (scope, path) {
+    (WordScope::Agent, None) => Ok(Vocabulary::agents_from_game_data(game_data, locale)),
+    (_, path) => load_vocabulary(game_data, locale, path)
+  }
+}
+
+fn write_output(output: Option<PathBuf>, content: &str) -> Result<(), String> {
+  match output {
+    Some(path) => {
+      fs::write(&path, content)
+        .map_err(|error| format!("Failed to write {}: {}", path.display(), error))
+    },
+    None => {
+      print!("{}", content);
+      Ok(())
+    }
+  }
+}
+
+fn aliases_to_csv(aliases: &AliasTable) -> String {
+  let mut result = String::from("locale,scope,raw,canonical,updated_at\n");
+  for alias in aliases.aliases() {
+    result.push_str(&format!(
+      "{},{},{},{},{}\n",
+      escape_csv(&alias.locale),
+      alias.scope,
+      escape_csv(&alias.raw),
+      escape_csv(&alias.canonical),
+      alias.updated_at
+    ));
+  }
+  result
+}
+
+async fn run_account_command(command: AccountCommand) -> Result<(), String> {
+  init_database().await?;
+
+  match command {
+    AccountCommand::List => {
+      let accounts = read_accounts().await?;
+      for account in accounts.iter() {
+        println!(
+          "{}\t{}\t{} disks",
+          account.uid,
+          account.name.as_deref().unwrap_or("-"),
+          account.disks
+        );
+      }
+      println!("{} accounts in {}", accounts.len(), database_path().display());
+    },
+    AccountCommand::Add { uid, name } => insert_account(&uid, name.as_deref()).await?,
+    AccountCommand::Remove { uid } => {
+      if !delete_account(&uid).await? {
+        return Err(format!("No account {}", uid));
+      }
+    },
+  }
+
+  Ok(())
+}
+
+async fn run_quarantine_command(
+  game_data: &GameData,
+  account: &str,
+  command: QuarantineCommand
+) -> Result<(), String> {
+  check_account(account).await?;
+
+  match command {
+    QuarantineCommand::List => {
+      let quarantined = read_quarantine(account).await?;
+      for disk in quarantined.iter() {
+        println!("#{}\t{}\t{}", disk.id, format_time(disk.quarantined_at), disk.error);
+      }
+      println!("{} disks quarantined", quarantined.len());
+    },
+    QuarantineCommand::Check => {
+      let loaded = read_disk(game_data, account, true).await?;
+      for error in loaded.errors.iter() {
+        println!("Quarantined #{}: {}", error.id, error.error);
+      }
+      println!("{} disks read, {} quarantined", loaded.disks.len(), loaded.errors.len());
+    },
+    QuarantineCommand::Release { ids } => {
+      let loaded = release_quarantine(game_data, account, &ids).await?;
+      let mut released: Vec<_> = loaded.disks.keys().collect();
+      released.sort();
+      for id in released {
+        println!("Released #{}", id);
+      }
+      for error in loaded.errors.iter() {
+        println!("Still quarantined #{}: {}", error.id, error.error);
+      }
+      println!("{} disks released", loaded.disks.len());
+    }
+  }
+
+  Ok(())
+}
+
+fn print_snapshot(snapshot: &InventorySnapshot) {
+  println!(
+    "{}\t{}\t{} disks\t{}",
+    snapshot.id,
+    format_time(snapshot.taken_at),
+    snapshot.disks,
+    snapshot.name.as_deref().unwrap_or("-")
+  );
+}
+
+async fn run_snapshot_command(
+  game_data: &GameData,
+  locale: &str,
+  account: &str,
+  command: SnapshotCommand
+) -> Result<(), String> {
+  check_account(account).await?;
+
+  match command {
+    SnapshotCommand::List => {
+      let snapshots = read_snapshots(account).await?;
+      snapshots.iter().for_each(print_snapshot);
+      println!("{} snapshots", snapshots.len());
+    },
+    SnapshotCommand::Take { name } => {
+      print_snapshot(&take_snapshot(account, name.as_deref(), None).await?);
+    },
+    SnapshotCommand::Remove { id } => {
+      if !delete_snapshot(account, id).await? {
+        return Err(format!("No snapshot {}", id));
+      }
+    },
+    SnapshotCommand::Diff { from, to } => {
+      let to = match to {
+        Some(to) => to,
+        None => {
+          match read_snapshots(account).await?.last() {
+            Some(snapshot) => snapshot.id,
+            None => return Err(format!("No snapshot {}", from))
+          }
+        },
+      };
+      let (from, before) = read_snapshot(game_data, account, from).await?;
+      let (to, after) = read_snapshot(game_data, account, to).await?;
+
+      warn_row_errors(&before.errors);
+      warn_row_errors(&after.errors);
+      println!(
+        "Changes from snapshot {} ({}) to snapshot {} ({})",
+        from.id,
+        format_time(from.taken_at),
+        to.id,
+        format_time(to.taken_at)
+      );
+      let diff = InventoryDiff::new(before.disks, after.disks);
+      println!("{}", diff.report(game_data, locale));
+    }
+  }
+
+  Ok(())
+}
+
+/// Warns about the disks of a query that couldn't be read.
+fn warn_row_errors(errors: &[RowError]) {
+  for error in errors {
+    eprintln!("{} (skipped; quarantine it with `quarantine check`)", error);
+  }
+}
+
+/// Checks that the account disks are read from or saved to exists.
+async fn check_account(account: &str) -> Result<(), String> {
+  match read_account(account).await? {
+    Some(_) => Ok(()),
+    None => {
+      Err(format!("Unknown account: {}; add it with `accounts add {}`", account, account))
+    },
+  }
+}
+
+async fn run_alias_command(
+  game_data: &GameData,
+  locale: &str,
+  command: AliasCommand
+) -> Result<(), String> {
+  init_database().await?;
+
+  match command {
+    AliasCommand::List => {
+      let aliases = read_aliases(locale).await?;
+      for alias in aliases.aliases() {
+        println!("[{}] {} → {}", alias.scope, alias.raw, alias.canonical);
+      }
+      println!("{} aliases", aliases.len());
+    },
+    AliasCommand::Add { scope, raw, canonical, vocabulary } => {
+      if !load_scope_vocabulary(game_data, locale, scope, vocabulary)?
+        .contains(&canonical)
+      {
+        return Err(format!("Not a vocabulary word: {}", canonical));
+      }
+      insert_alias(locale, scope, &raw, &canonical).await?;
+    },
+    AliasCommand::Remove { scope, raw } => {
+      if !delete_alias(locale, scope, &raw).await? {
+        return Err(format!("No alias for [{}] {}", scope, raw));
+      }
+    },
+    AliasCommand::Export { output } => {
+      write_output(output, &aliases_to_csv(&read_aliases(locale).await?))?;
+    },
+    AliasCommand::Prune { stale, older_than, vocabulary } => {
+      if !stale && older_than.is_none() {
+        return Err("Nothing to prune; pass --stale and/or --older-than".to_string());
+      }
+
+      let agents =
+        load_scope_vocabulary(game_data, locale, WordScope::Agent, vocabulary.clone())?;
+      let vocabulary = load_vocabulary(game_data, locale, vocabulary)?;
+      let cutoff = older_than.map(|days| {
+        let now = std::time::SystemTime::now()
+          .duration_since(std::time::UNIX_EPOCH)
+          .map(|d| d.as_secs())
+          .unwrap_or(0);
+        now.saturating_sub(days * 24 * 60 * 60) as i64
+      });
+
+      let pruned = prune_aliases(locale, move |alias| {
+        let vocabulary = match alias.scope {
+          WordScope::Agent => &agents,
+          _ => &vocabulary
+        };
+        (stale && alias.is_stale(vocabulary))
+          || cutoff.is_some_and(|cutoff| alias.updated_at < cutoff)
+      })
+      .await?;
+      for alias in pruned.iter() {
+        println!("Pruned [{}] {} → {}", alias.scope, alias.raw, alias.canonical);
+      }
+      println!("{} aliases pruned", pruned.len());
+    }
+  }
+
+  Ok(())
+}
+
+fn resolve_set(game_data: &GameData, locale: &str, set: &str) -> Result<String, String> {
+  match game_data.set(set).or(game_data.find_set(locale, set)) {
+    Some(set) => Ok(set.id.clone()),
+    None => Err(format!("Unknown set: {}", set))
+  }
+}
+
+/// Parses a stat filter whose stat is given by ID or by name in `locale`.
+fn resolve_stat(
+  game_data: &GameData,
+  locale: &str,
+  value: &str
+) -> Result<StatFilter, String> {
+  let mut filter = StatFilter::try_from(value)?;
+  match game_data.stat(&filter.stat).or(game_data.find_stat(locale, &filter.stat)) {
+    Some(stat) => filter.stat = stat.id.clone(),
+    None => return Err(format!("Unknown stat: {}", filter.stat))
+  }
+  Ok(filter)
+}
+
+fn build_query(
+  game_data: &GameData,
+  locale: &str,
+  account: &str,
+  args: QueryArgs
+) -> Result<DiskQuery, String> {
+  let mut query = DiskQuery::new(account)
+    .level_range(args.min_level, args.max_level)
+    .scanned_between(args.scanned_after, args.scanned_before);
+
+  for set in args.sets.iter() {
+    query = query.set(&resolve_set(game_data, locale, set)?);
+  }
+  for slot in args.slots {
+    query = query.slot(slot);
+  }
+  for rarity in args.rarities {
+    query = query.rarity(rarity);
+  }
+  for stat in args.primary_stats.iter() {
+    query = query.primary_stat(resolve_stat(game_data, locale, stat)?);
+  }
+  for stat in args.secondary_stats.iter() {
+    let (stat, min_value) = match stat.split_once(">=") {
+      Some((stat, min_value)) => {
+        let min_value = min_value
+          .trim()
+          .parse::<f64>()
+          .map_err(|_| format!("Invalid minimum value: {}", min_value))?;
+        (stat.trim(), Some(min_value))
+      },
+      None => (stat.as_str(), None)
+    };
+    query = query.secondary_stat(resolve_stat(game_data, locale, stat)?, min_value);
+  }
+  if let Some(locked) = args.locked {
+    query = query.locked(locked);
+  }
+  if let Some(trashed) = args.trashed {
+    query = query.trashed(trashed);
+  }
+  if let Some(equipped) = args.equipped.as_deref() {
+    query = query.equipped(match EquippedFilter::try_from(equipped)? {
+      EquippedFilter::Agent(agent) => {
+        match game_data.agent(&agent).or(game_data.find_agent(locale, &agent)) {
+          Some(agent) => EquippedFilter::Agent(agent.id.clone()),
+          None => return Err(format!("Unknown agent: {}", agent))
+        }
+      },
+      filter => filter
+    });
+  }
+  for order in args.order.iter() {
+    let (order, descending) = match order.strip_prefix('-') {
+      Some(order) => (order, true),
+      None => (order.as_str(), false)
+    };
+    let order = match DiskOrder::try_from(order)? {
+      DiskOrder::Secondary(_) => {
+        DiskOrder::Secondary(resolve_stat(game_data, locale, order)?)
+      },
+      order => order
+    };
+    query = query.order_by(order, descending);
+  }
+  for weight in args.weights.iter() {
+    let (stat, value) =
+      weight.split_once('=').ok_or(format!("Expected STAT=WEIGHT: {}", weight))?;
+    let stat = resolve_stat(game_data, locale, stat.trim())?;
+    let value =
+      value.trim().parse::<f64>().map_err(|_| format!("Invalid weight: {}", value))?;
+    query = query.weight(&stat.stat, value);
+  }
+
+  Ok(query)
+}
+
+/// Parses an affix written as `STAT[:TYPE]=VALUE`, whose stat is given by ID
+/// or by name in `locale`. The value type may be left out for stats with only
+/// one.
+fn resolve_affix(
+  game_data: &GameData,
+  locale: &str,
+  value: &str
+) -> Result<Affix, String> {
+  let (stat, number) =
+    value.rsplit_once('=').ok_or(format!("Expected STAT=VALUE: {}", value))?;
+  let stat = resolve_stat(game_data, locale, stat.trim())?;
+  let number =
+    number.trim().parse::<f64>().map_err(|_| format!("Invalid value: {}", number))?;
+  let value_type = match stat.value_type {
+    Some(value_type) => value_type,
+    None => {
+      match game_data.stat(&stat.stat).map(|stat| stat.value_types.as_slice()) {
+        Some([value_type]) => *value_type,
+        _ => {
+          return Err(format!(
+            "Give the value type of {}, e.g. {}:percentage",
+            stat.stat, stat.stat
+          ))
+        },
+      }
+    },
+  };
+  Affix::new(game_data, &stat.stat, number, value_type)
+}
+
+/// Applies the corrections to a disk, checking the result like a scanned
+/// disk.
+fn apply_changes(
+  game_data: &GameData,
+  locale: &str,
+  disk: Disk,
+  changes: DiskChanges
+) -> Result<Disk, String> {
+  let set = match changes.set.as_deref() {
+    Some(set) => resolve_set(game_data, locale, set)?,
+    None => disk.set
+  };
+  let primary_affix = match changes.primary.as_deref() {
+    Some(primary) => resolve_affix(game_data, locale, primary)?,
+    None => disk.primary_affix
+  };
+
+  let mut secondary_affixes = disk.secondary_affixes;
+  let position = |position: usize| {
+    if (1 ..= 4).contains(&position) {
+      Ok(position - 1)
+    } else {
+      Err(format!("Invalid secondary affix position: {}", position))
+    }
+  };
+  for position_value in changes.removed_secondaries {
+    secondary_affixes[position(position_value)?] = None;
+  }
+  for secondary in changes.secondaries.iter() {
+    let (index, affix) = secondary
+      .split_once('=')
+      .ok_or(format!("Expected POSITION=STAT=VALUE: {}", secondary))?;
+    let index = index
+      .trim()
+      .parse::<usize>()
+      .map_err(|_| format!("Invalid secondary affix position: {}", index))?;
+    secondary_affixes[position(index)?] = Some(resolve_affix(game_data, locale, affix)?);
+  }
+
+  let equipped_by = match changes.equipped.as_deref() {
+    Some("none") => None,
+    Some(agent) => {
+      match game_data.agent(agent).or(game_data.find_agent(locale, agent)) {
+        Some(agent) => Some(EquippedBy::Agent(agent.id.clone())),
+        None => return Err(format!("Unknown agent: {}", agent))
+      }
+    },
+    None => disk.equipped_by
+  };
+
+  let [affix_1, affix_2, affix_3, affix_4] = secondary_affixes;
+  Ok(
+    Disk::new(
+      game_data,
+      &set,
+      changes.slot.unwrap_or(disk.slot),
+      primary_affix,
+      affix_1,
+      affix_2,
+      affix_3,
+      affix_4
+    )?
+    .with_equipped_by(equipped_by)
+    .with_rarity(changes.rarity.or(disk.rarity))
+    .with_level(changes.level.or(disk.level))?
+    .with_locked(changes.locked.unwrap_or(disk.locked))
+    .with_trashed(changes.trashed.unwrap_or(disk.trashed))
+  )
+}
+
+/// Formats a Unix time as `YYYY-MM-DD HH:MM` in UTC.
+fn format_time(time: i64) -> String {
+  // The inverse of the day count in `parse_date`
+  let (days, seconds) = (time.div_euclid(24 * 60 * 60), time.rem_euclid(24 * 60 * 60));
+  let days = days + 719468;
+  let era = days.div_euclid(146097);
+  let day_of_era = days - era * 146097;
+  let year_of_era =
+    (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+  let day_of_year =
+    day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let month = (5 * day_of_year + 2) / 153;
+  let day = day_of_year - (153 * month + 2) / 5 + 1;
+  let month = if month < 10 { month + 3 } else { month - 9 };
+  let year = year_of_era + era * 400 + i64::from(month <= 2);
+  format!(
+    "{:04}-{:02}-{:02} {:02}:{:02}",
+    year,
+    month,
+    day,
+    seconds / 3600,
+    seconds % 3600 / 60
+  )
+}
+
+fn print_journal_entry(entry: &JournalEntry) {
+  println!(
+    "{}\t{}\t#{}\t{}{}",
+    entry.id,
+    entry.operation,
+    entry.disk_id,
+    format_time(entry.created_at),
+    if entry.undone_at.is_some() { "\tundone" } else { "" }
+  );
+}
+
+fn open_image(path: &Path) -> Result<GrayImage, String> {
+  image::open(path)
+    .map(|image| image.to_luma8())
+    .map_err(|error| format!("Failed to open {}: {}", path.display(), error))
+}
+
+async fn run(cli: Cli) -> Result<(), String> {
+  let game_data = GameData::load(cli.game_data.as_deref())?;
+  let profile = cli.profile.as_deref().map(ScanProfile::load).transpose()?;
+  let locale_override = cli.locale.clone();
+  let database =
+    cli.database.or(profile.as_ref().and_then(|profile| profile.database.clone()));
+  match database.clone() {
+    Some(database) => set_database_path(database)?,
+    None => {
+      if let Some(legacy) = legacy_database_path() {
+        eprintln!(
+          "Using {} left by an earlier version; move it to {} to keep using it from any \
+           directory",
+          legacy.display(),
+          default_database_path().display()
+        );
+        set_database_path(legacy)?;
+      }
+    },
+  }
+  let account = cli
+    .account
+    .or(profile.as_ref().and_then(|profile| profile.account.clone()))
+    .unwrap_or_else(|| DEFAULT_ACCOUNT.to_string());
+  let font = cli.font.or(profile.as_ref().and_then(|profile| profile.font.clone()));
+  let layout = cli.layout.or(profile.as_ref().and_then(|profile| profile.layout.clone()));
+  let layouts = Layouts::load(cli.layouts.as_deref())?;
+  if let Some(layout) = layout.as_deref() {
+    layouts.get(layout)?;
+  }
+  let input_name =
+    cli.input.or(profile.as_ref().and_then(|profile| profile.input.clone()));
+  let input = input_name.as_deref().map(InputMethod::try_from).transpose()?;
+  // Only detecting the input method needs the prompt templates, so other
+  // commands don't depend on them being readable
+  let prompt_templates = || {
+    match input {
+      Some(_) => Ok(PromptTemplates::default()),
+      None => {
+        PromptTemplates::load(
+          cli.templates.clone().unwrap_or_else(default_templates_path)
+        )
+        .map_err(|error| format!("{}; give the input method with --input", error))
+      },
+    }
+  };
+  let layout_screenshot = |image: &GrayImage| {
+    let templates = prompt_templates()?;
+    ScreenshotLayout::new(
+      image,
+      &layouts,
+      layout.as_deref(),
+      input,
+      &templates,
+      !cli.fixed_regions
+    )
+  };
+  let scan_profile = |locale: &str| {
+    ScanProfile {
+      font: font.clone(),
+      layout: layout.clone(),
+      input: input_name.clone(),
+      database: database.clone(),
+      account: (account != DEFAULT_ACCOUNT).then(|| account.clone()),
+      ..ScanProfile::new(locale)
+    }
+  };
+  let locale = cli
+    .locale
+    .or(profile.map(|profile| profile.language))
+    .unwrap_or_else(|| game_data.default_locale.clone());
+  if !game_data.locales().contains(locale.as_str()) {
+    return Err(format!("Unknown locale: {}", locale));
+  }
+  if game_data.language(&locale).is_ok_and(|language| language.experimental) {
+    eprintln!(
+      "The names of locale {} were not checked against its client yet; expect misreads",
+      locale
+    );
+  }
+  game_data.scan_fonts(font.as_deref())?;
+
+  match cli.command {
+    Command::Ambiguity { distance, vocabulary } => {
+      let vocabulary = load_vocabulary(&game_data, &locale, vocabulary)?;
+      print!("{}", get_ambiguity_report(&vocabulary, distance));
+      Ok(())
+    },
+    Command::Accounts(command) => run_account_command(command).await,
+    Command::Snapshots(command) => {
+      run_snapshot_command(&game_data, &locale, &account, command).await
+    },
+    Command::Quarantine(command) => {
+      run_quarantine_command(&game_data, &account, command).await
+    },
+    Command::Aliases(command) => run_alias_command(&game_data, &locale, command).await,
+    Command::GameData => {
+      println!(
+        "Game data version {} for game version {}: {} sets, {} stats, {} agents, \
+         locales {}, fonts {}",
+        game_data.version,
+        game_data.game_version,
+        game_data.sets.len(),
+        game_data.stats.len(),
+        game_data.agents.len(),
+        game_data.locales().into_iter().collect::<Vec<_>>().join(", "),
+        game_data.fonts.keys().cloned().collect::<Vec<_>>().join(", ")
+      );
+      Ok(())
+    },
+    Command::DetectLanguage { image, save_profile } => {
+      let image = open_image(&image)?;
+      let panel = layout_screenshot(&image)?
+        .crop(&image, Region::Panel)
+        .unwrap_or_else(|| image.clone());
+      let detection_font =
+        game_data.font(font.as_deref().unwrap_or(&game_data.default_font))?;
+      let detection =
+        detect_language(&TesseractEngine::new(), &panel, detection_font, &game_data)?;
+
+      for (language, error) in detection.skipped.iter() {
+        eprintln!("Skipped {}: {}", language, error);
+      }
+      for (locale, score) in detection.scores.iter() {
+        println!("{}\t{:.2}", locale, score);
+      }
+      println!(
+        "Detected {} with score {:.2}, {:.2} ahead of the next language{}",
+        detection.locale,
+        detection.score(),
+        detection.margin(),
+        if detection.is_confident() { "" } else { " (low confidence)" }
+      );
+
+      let locale = match locale_override {
+        Some(locale) => {
+          println!("Using {} as given by --locale", locale);
+          locale
+        },
+        None => detection.locale
+      };
+      match save_profile {
+        Some(path) => scan_profile(&locale).save(path),
+        None => Ok(())
+      }
+    },
+    Command::Layout { image } => {
+      let image = open_image(&image)?;
+      let layout = layout_screenshot(&image)?;
+      let profile = &layout.profile;
+      println!(
+        "Layout {} ({}) for {}x{}, safe area {}",
+        profile.id,
+        profile.name,
+        image.width(),
+        image.height(),
+        profile.safe_area(image.width(), image.height())
+      );
+      match layout.detection.as_ref() {
+        Some(detection) => {
+          println!(
+            "Detected input method {} with score {:.2}, {:.2} ahead{}",
+            layout.input,
+            detection.score(),
+            detection.margin(),
+            if detection.is_confident() { "" } else { " (low confidence)" }
+          )
+        },
+        None => println!("Input method {}", layout.input)
+      }
+      match layout.panel {
+        Some(panel) => {
+          println!("Panel found at {} with contrast {:.1}", panel.rect, panel.contrast)
+        },
+        None if cli.fixed_regions => {},
+        None => println!("Panel not found, using the regions of the layout")
+      }
+      for (region, rect) in layout.regions() {
+        println!("{}\t{}", region, rect);
+      }
+      Ok(())
+    },
+    Command::Calibrate { image, output, id, annotated, regions: corrections } => {
+      let image = open_image(&image)?;
+      let (width, height) = (image.width(), image.height());
+      let input = match input {
+        Some(input) => input,
+        None => {
+          let layout = layouts.select(width, height);
+          let detection = detect_input_method(&image, layout, &prompt_templates()?)?;
+          if !detection.is_confident() {
+            return Err(format!(
+              "Input method {} detected with low confidence; give it with --input",
+              detection.input
+            ));
+          }
+          detection.input
+        }
+      };
+      println!("Input method {}", input);
+      let calibration = calibrate(&image, &layouts, input, &corrections);
+
+      match calibration.panel {
+        Some(panel) => {
+          println!(
+            "Fitted layout {} with the panel at {}, contrast {:.1}",
+            calibration.base.id, panel.rect, panel.contrast
+          )
+        },
+        None => println!("Panel not found, starting from layout {}", calibration.base.id)
+      }
+      for (index, (region, rect)) in calibration.regions.iter().enumerate() {
+        let color = ANNOTATION_COLORS[index % ANNOTATION_COLORS.len()].0;
+        println!("{}\t{}\t{}", region, rect, color);
+      }
+
+      if let Some(annotated) = annotated {
+        annotate(&image, &calibration.regions).save(&annotated).map_err(|error| {
+          format!("Failed to write {}: {}", annotated.display(), error)
+        })?;
+        println!("Wrote the annotated screenshot to {}", annotated.display());
+      }
+
+      if !calibration.is_fitted(&corrections) {
+        return Err(
+          "The panel was not found; check the regions and give the panel with --region \
+           panel=WIDTHxHEIGHT+X+Y"
+            .to_string()
+        );
+      }
+
+      let mut custom = if output.exists() {
+        Layouts::from_file(&output)?
+      } else {
+        Layouts { version: Layouts::SUPPORTED_VERSION, profiles: Default::default() }
+      };
+      let name = format!("Calibrated for {}x{}", width, height);
+      custom.profiles.insert(id.clone(), calibration.profile(&id, &name, width, height));
+      custom.save(&output)?;
+      println!(
+        "Saved layout {} to {}; use it with --layouts {} --layout {}",
+        id,
+        output.display(),
+        output.display(),
+        id
+      );
+      Ok(())
+    },
+    Command::Scan { images, keep_crops } => {
+      check_account(&account).await?;
+      let aliases = read_aliases(&locale).await?;
+      let engine = TesseractEngine::new();
+      let scanner = DiskScanner::new(
+        &engine,
+        &game_data,
+        &locale,
+        game_data.scan_fonts(font.as_deref())?,
+        &aliases
+      )?
+      .with_crops(keep_crops);
+
+      let mut session = start_scan_session(&account, &scan_profile(&locale)).await?;
+      let (mut inserted, mut updated, mut skipped, mut failed) = (0, 0, 0, 0);
+      for path in images.iter() {
+        let scanned = open_image(path).and_then(|image| {
+          let layout = layout_screenshot(&image)?;
+          let scanned = scanner.read(&image, &layout)?;
+          let record = ScanRecord {
+            regions: scanned.readings,
+            ..ScanRecord::from_file(path)?.with_layout(&layout.profile.id)
+          };
+          Ok((scanned.disk, record))
+        });
+        let saved = match scanned {
+          Ok((disk, record)) => {
+            insert_scanned_disk(&game_data, &account, &disk, &record, &mut session)
+              .await
+              .map(|saved| (saved, disk))
+          },
+          Err(error) => Err(error)
+        };
+
+        match saved {
+          Ok((saved, disk)) => {
+            match saved {
+              Saved::Inserted(_) => inserted += 1,
+              Saved::Updated(_) => updated += 1,
+              Saved::Skipped(_) => skipped += 1
+            }
+            println!(
+              "{}\t{} #{}\t{}",
+              path.display(),
+              saved,
+              saved.id(),
+              disk.display(&game_data, &locale)
+            );
+          },
+          Err(error) => {
+            failed += 1;
+            eprintln!("{}: {}", path.display(), error);
+          }
+        }
+      }
+      println!(
+        "{} inserted, {} updated, {} skipped, {} failed",
+        inserted, updated, skipped, failed
+      );
+      Ok(())
+    },
+    Command::SaveProfile { path } => {
+      game_data.language(&locale)?;
+      scan_profile(&locale).save(path)
+    },
+    Command::Disks { query, page, page_size } => {
+      check_account(&account).await?;
+      let query = build_query(&game_data, &locale, &account, query)?
+        .page(page.saturating_sub(1), page_size.max(1));
+      let result = query_disks(&game_data, &query).await?;
+
+      for queried in result.disks.iter() {
+        let disk = &queried.disk;
+        println!(
+          "#{}\t{}\t{}\t{:.1}\t{}",
+          queried.id,
+          disk.rarity.map_or("-".to_string(), |rarity| rarity.to_string()),
+          disk.level.map_or("-".to_string(), |level| format!("+{}", level)),
+          queried.score,
+          disk.display(&game_data, &locale)
+        );
+      }
+      warn_row_errors(&result.errors);
+      let pages = result.total.div_ceil(u64::from(page_size.max(1))).max(1);
+      println!("Page {} of {}, {} disks", page.max(1), pages, result.total);
+      Ok(())
+    },
+    Command::Export { query, output } => {
+      check_account(&account).await?;
+      let query = build_query(&game_data, &locale, &account, query)?;
+      let result = query_disks(&game_data, &query).await?;
+      warn_row_errors(&result.errors);
+      let disks: Vec<(i64, &_)> =
+        result.disks.iter().map(|queried| (queried.id, &queried.disk)).collect();
+      write_output(output, &disks_to_csv(&disks, &game_data, &locale))
+    },
+    Command::Update { id, changes } => {
+      check_account(&account).await?;
+      let disk =
+        get_disk(&game_data, &account, id).await?.ok_or(format!("No disk {}", id))?;
+      let disk = apply_changes(&game_data, &locale, disk, changes)?;
+      update_disk(&game_data, &account, id, &disk).await?;
+      println!("#{}\t{}", id, disk.display(&game_data, &locale));
+      Ok(())
+    },
+    Command::Delete { ids } => {
+      check_account(&account).await?;
+      let mut missing = vec![];
+      for id in ids {
+        if delete_disk(&account, id).await? {
+          println!("Deleted #{}", id);
+        } else {
+          missing.push(id.to_string());
+        }
+      }
+      if !missing.is_empty() {
+        return Err(format!("No disk {}", missing.join(", ")));
+      }
+      Ok(())
+    },
+    Command::Undo { count } => {
+      check_account(&account).await?;
+      let entries = undo(&account, count).await?;
+      entries.iter().for_each(print_journal_entry);
+      println!("{} changes undone", entries.len());
+      Ok(())
+    },
+    Command::Redo { count } => {
+      check_account(&account).await?;
+      let entries = redo(&account, count).await?;
+      entries.iter().for_each(print_journal_entry);
+      println!("{} changes redone", entries.len());
+      Ok(())
+    },
+    Command::History { limit } => {
+      check_account(&account).await?;
+      read_journal(&account, limit).await?.iter().for_each(print_journal_entry);
+      Ok(())
+    },
+    Command::Provenance { id, crops } => {
+      check_account(&account).await?;
+      let records = read_scan_records(&account, id).await?;
+      if records.is_empty() {
+        return Err(format!("No recorded scans of disk {}", id));
+      }
+      if let Some(crops) = crops.as_ref() {
+        fs::create_dir_all(crops)
+          .map_err(|error| format!("Failed to create {}: {}", crops.display(), error))?;
+      }
+
+      for stored in records.iter() {
+        let record = &stored.record;
+        println!(
+          "Scan {} ({}) at {}, session {} started at {}",
+          stored.id,
+          stored.outcome,
+          format_time(stored.scanned_at),
+          stored.session_id,
+          format_time(stored.session_started_at)
+        );
+        println!(
+          "  language {}, font {}, layout {}, input {}",
+          stored.language.as_deref().unwrap_or("-"),
+          stored.font.as_deref().unwrap_or("-"),
+          record.layout.as_deref().unwrap_or("-"),
+          stored.input.as_deref().unwrap_or("-")
+        );
+        if let Some(path) = record.source_path.as_ref() {
+          println!(
+            "  source {} {}",
+            path.display(),
+            record.source_hash.as_deref().unwrap_or("")
+          );
+        }
+
+        for reading in record.regions.iter() {
+          println!(
+            "  {}\t{:?}\t→ {}\t{:.2}{}",
+            reading.region,
+            reading.raw,
+            reading.matched.as_deref().unwrap_or("-"),
+            reading.confidence,
+            reading
+              .correction
+              .as_ref()
+              .map_or(String::new(), |correction| format!("\t({})", correction))
+          );
+          if let (Some(crops), Some(crop)) = (crops.as_ref(), reading.crop.as_ref()) {
+            let path = crops.join(format!("{}-{}.png", stored.id, reading.region));
+            fs::write(&path, crop).map_err(|error| {
+              format!("Failed to write {}: {}", path.display(), error)
+            })?;
+          }
+        }
+      }
+      Ok(())
+    }
+  }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+  match run(Cli::parse()).await {
+    Ok(()) => ExitCode::SUCCESS,
+    Err(error) => {
+      eprintln!("{}", error);
+      ExitCode::FAILURE
+    }
+  }
 }
@@ -0,0 +1,183 @@
+use {
+  crate::source::game_data::Language,
+  image::{GrayImage, ImageFormat},
+  std::{
+    io::{Cursor, Write},
+    path::PathBuf,
+    process::{Command, Stdio}
+  }
+};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum OcrMode {
+  Text,
+  /// Reads affix values. Recognition isn't restricted to value characters,
+  /// so that misread ones reach the numeric parser, which corrects them and
+  /// ranks the readings by the cost of those corrections; word dictionaries
+  /// are turned off instead, as they only bend numbers into words.
+  Digits
+}
+
+#[derive(Clone, Debug)]
+pub struct OcrOptions {
+  pub language: String,
+  pub mode: OcrMode,
+  /// Tesseract page segmentation mode; 7 treats the image as a single line.
+  pub page_segmentation: u8,
+  /// Extra Tesseract variables, such as those of a font profile.
+  pub variables: Vec<(String, String)>
+}
+
+impl Default for OcrOptions {
+  fn default() -> Self {
+    Self {
+      language: "chi_sim".to_string(),
+      mode: OcrMode::Text,
+      page_segmentation: 7,
+      variables: vec![]
+    }
+  }
+}
+
+impl OcrOptions {
+  pub fn for_language(language: &Language) -> Self {
+    Self { language: language.tesseract.clone(), ..Self::default() }
+  }
+
+  pub fn digits(&self) -> Self {
+    Self { mode: OcrMode::Digits, ..self.clone() }
+  }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct OcrText {
+  pub text: String,
+  /// Mean word confidence reported by the engine, between 0 and 1.
+  pub confidence: f32
+}
+
+pub trait OcrEngine {
+  fn recognize(&self, image: &GrayImage, options: &OcrOptions)
+    -> Result<OcrText, String>;
+}
+
+/// Runs the `tesseract` executable, feeding the image through stdin and
+/// reading TSV output so word confidences are available.
+#[derive(Clone, Debug)]
+pub struct TesseractEngine {
+  executable: PathBuf,
+  data_path: Option<PathBuf>
+}
+
+impl Default for TesseractEngine {
+  fn default() -> Self {
+    Self { executable: PathBuf::from("tesseract"), data_path: None }
+  }
+}
+
+impl TesseractEngine {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn with_executable(mut self, executable: impl Into<PathBuf>) -> Self {
+    self.executable = executable.into();
+    self
+  }
+
+  pub fn with_data_path(mut self, data_path: impl Into<PathBuf>) -> Self {
+    self.data_path = Some(data_path.into());
+    self
+  }
+
+  fn parse_tsv(tsv: &str) -> OcrText {
+    let mut lines: Vec<(i64, Vec<String>)> = vec![];
+    let mut confidence_sum = 0.0;
+    let mut word_count = 0;
+
+    for row in tsv.lines().skip(1) {
+      let columns: Vec<&str> = row.split('\t').collect();
+      if columns.len() < 12 || columns[0] != "5" {
+        continue;
+      }
+
+      let text = columns[11].trim();
+      let confidence: f32 = columns[10].parse().unwrap_or(-1.0);
+      if text.is_empty() || confidence < 0.0 {
+        continue;
+      }
+
+      // Block, paragraph and line numbers together identify a line
+      let line_id = columns[2 .. 5]
+        .iter()
+        .fold(0i64, |id, column| id * 1000 + column.parse::<i64>().unwrap_or(0));
+      match lines.last_mut() {
+        Some((id, words)) if *id == line_id => words.push(text.to_string()),
+        _ => lines.push((line_id, vec![text.to_string()]))
+      }
+
+      confidence_sum += confidence;
+      word_count += 1;
+    }
+
+    let text: Vec<String> = lines.into_iter().map(|(_, words)| words.join(" ")).collect();
+    let confidence =
+      if word_count > 0 { confidence_sum / word_count as f32 / 100.0 } else { 0.0 };
+
+    OcrText { text: text.join("\n"), confidence }
+  }
+}
+
+impl OcrEngine for TesseractEngine {
+  fn recognize(
+    &self,
+    image: &GrayImage,
+    options: &OcrOptions
+  ) -> Result<OcrText, String> {
+    let mut png = Cursor::new(vec![]);
+    image
+      .write_to(&mut png, ImageFormat::Png)
+      .map_err(|error| format!("Failed to encode image: {}", error))?;
+
+    let mut command = Command::new(&self.executable);
+    command
+      .args(["stdin", "stdout", "-l", &options.language, "--psm"])
+      .arg(options.page_segmentation.to_string());
+    if let Some(data_path) = self.data_path.as_ref() {
+      command.arg("--tessdata-dir").arg(data_path);
+    }
+    if options.mode == OcrMode::Digits {
+      command.args(["-c", "load_system_dawg=0", "-c", "load_freq_dawg=0"]);
+    }
+    for (name, value) in options.variables.iter() {
+      command.arg("-c").arg(format!("{}={}", name, value));
+    }
+    command.arg("tsv");
+
+    let mut child = command
+      .stdin(Stdio::piped())
+      .stdout(Stdio::piped())
+      .stderr(Stdio::piped())
+      .spawn()
+      .map_err(|error| format!("Failed to start tesseract: {}", error))?;
+
+    child
+      .stdin
+      .take()
+      .ok_or("Failed to open tesseract stdin".to_string())?
+      .write_all(png.get_ref())
+      .map_err(|error| format!("Failed to write image to tesseract: {}", error))?;
+
+    let output = child
+      .wait_with_output()
+      .map_err(|error| format!("Failed to run tesseract: {}", error))?;
+    if !output.status.success() {
+      return Err(format!(
+        "Tesseract failed: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+      ));
+    }
+
+    Ok(Self::parse_tsv(&String::from_utf8_lossy(&output.stdout)))
+  }
+}
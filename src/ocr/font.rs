@@ -0,0 +1,157 @@
+use {
+  crate::ocr::{engine::OcrOptions, preprocess::Preprocessing},
+  std::collections::BTreeMap
+};
+
+/// A character OCR reads in place of another one with a given font.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Confusion {
+  pub read: char,
+  pub actual: char,
+  pub penalty: u32
+}
+
+/// Everything that depends on the font scheme chosen in the game settings:
+/// how regions are preprocessed, extra engine parameters, and the characters
+/// the font's glyphs get misread as.
+#[derive(Clone, Debug)]
+pub struct FontProfile {
+  pub id: String,
+  pub name: String,
+  pub preprocessing: Preprocessing,
+  /// Tesseract variables set for every recognition with this font.
+  pub variables: BTreeMap<String, String>,
+  /// Misreads specific to this font, consulted before the general ones.
+  pub confusions: Vec<Confusion>
+}
+
+impl Default for FontProfile {
+  /// The "Global Light" font scheme, which the default settings are tuned
+  /// for.
+  fn default() -> Self {
+    Self {
+      id: "global_light".to_string(),
+      name: "Global Light".to_string(),
+      preprocessing: Preprocessing::default(),
+      variables: BTreeMap::new(),
+      confusions: vec![]
+    }
+  }
+}
+
+impl FontProfile {
+  /// Adds the font's engine variables to `options`.
+  pub fn apply_options(&self, options: OcrOptions) -> OcrOptions {
+    let mut options = options;
+    options
+      .variables
+      .extend(self.variables.iter().map(|(name, value)| (name.clone(), value.clone())));
+    options
+  }
+
+  /// Returns the character `c` most likely is with this font, if the font
+  /// is known to produce it as a misread.
+  pub fn confusion(&self, c: char) -> Option<(char, u32)> {
+    self
+      .confusions
+      .iter()
+      .find(|confusion| confusion.read == c)
+      .map(|confusion| (confusion.actual, confusion.penalty))
+  }
+}
+
+/// Runs `recognize` with every font profile and keeps the result with the
+/// highest score, together with the font that produced it. Fails only if
+/// every profile fails, with the last error.
+pub fn recognize_with_fonts<'a, T, F>(
+  fonts: &[&'a FontProfile],
+  mut recognize: F
+) -> Result<(T, &'a FontProfile), String>
+where
+  F: FnMut(&FontProfile) -> Result<(T, f32), String>
+{
+  let mut best: Option<(T, f32, &FontProfile)> = None;
+  let mut last_error = "No font profiles to recognize with".to_string();
+
+  for &font in fonts {
+    match recognize(font) {
+      Ok((result, score)) => {
+        if best.as_ref().is_none_or(|(_, best_score, _)| score > *best_score) {
+          best = Some((result, score, font));
+        }
+      },
+      Err(error) => last_error = error
+    }
+  }
+
+  match best {
+    Some((result, _, font)) => Ok((result, font)),
+    None => Err(last_error)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn font(id: &str) -> FontProfile {
+    FontProfile { id: id.to_string(), ..FontProfile::default() }
+  }
+
+  // Runs `recognize_with_fonts` with fixed results per font, returning the
+  // kept result and font, and the fonts tried in order.
+  fn run(
+    fonts: &[&FontProfile],
+    results: &[(&str, Result<f32, &str>)]
+  ) -> (Result<(String, String), String>, Vec<String>) {
+    let mut tried = vec![];
+    let result = recognize_with_fonts(fonts, |font| {
+      tried.push(font.id.clone());
+      match results.iter().find(|(id, _)| *id == font.id).unwrap().1 {
+        Ok(score) => Ok((format!("read with {}", font.id), score)),
+        Err(error) => Err(error.to_string())
+      }
+    });
+    (result.map(|(result, font)| (result, font.id.clone())), tried)
+  }
+
+  #[test]
+  fn keeps_the_best_score() {
+    let (a, b, c) = (font("a"), font("b"), font("c"));
+    let (result, tried) =
+      run(&[&a, &b, &c], &[("a", Ok(0.4)), ("b", Ok(0.9)), ("c", Ok(0.6))]);
+
+    assert_eq!(result.unwrap(), ("read with b".to_string(), "b".to_string()));
+    assert_eq!(tried, vec!["a", "b", "c"]);
+  }
+
+  #[test]
+  fn keeps_the_earlier_font_on_ties() {
+    let (a, b) = (font("a"), font("b"));
+
+    let (result, _) = run(&[&a, &b], &[("a", Ok(0.5)), ("b", Ok(0.5))]);
+    assert_eq!(result.unwrap().1, "a");
+    let (result, _) = run(&[&b, &a], &[("a", Ok(0.5)), ("b", Ok(0.5))]);
+    assert_eq!(result.unwrap().1, "b");
+  }
+
+  #[test]
+  fn falls_back_past_failing_fonts() {
+    let (a, b, c) = (font("a"), font("b"), font("c"));
+    let (result, tried) =
+      run(&[&a, &b, &c], &[("a", Err("no text")), ("b", Ok(0.0)), ("c", Err("crash"))]);
+
+    assert_eq!(result.unwrap().1, "b");
+    assert_eq!(tried, vec!["a", "b", "c"]);
+  }
+
+  #[test]
+  fn fails_with_the_last_error() {
+    let (a, b) = (font("a"), font("b"));
+    let (result, tried) = run(&[&a, &b], &[("a", Err("first")), ("b", Err("second"))]);
+
+    assert_eq!(result.unwrap_err(), "second");
+    assert_eq!(tried, vec!["a", "b"]);
+    assert_eq!(run(&[], &[]).0.unwrap_err(), "No font profiles to recognize with");
+  }
+}
@@ -0,0 +1,4 @@
+pub mod engine;
+pub mod font;
+pub mod numeric;
+pub mod preprocess;
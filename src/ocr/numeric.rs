@@ -0,0 +1,296 @@
+use {
+  crate::{
+    disk::disk::AffixValueType,
+    ocr::{
+      engine::{OcrEngine, OcrOptions},
+      font::{recognize_with_fonts, FontProfile}
+    },
+    source::game_data::Language
+  },
+  image::GrayImage
+};
+
+/// Characters of affix values as the client shows them.
+pub static VALUE_CHARS: &str = "0123456789.%+";
+
+/// A possible reading of an affix value. `penalty` counts the corrections
+/// that were needed to produce it, so lower is more likely.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValueCandidate {
+  pub value: f64,
+  pub value_type: AffixValueType,
+  pub penalty: u32
+}
+
+/// Maps a character read by OCR to the value character it most likely is,
+/// along with the penalty of that correction. Returns `None` for characters
+/// that should be dropped.
+fn normalize_char(c: char) -> Option<(char, u32)> {
+  match c {
+    c if VALUE_CHARS.contains(c) => Some((c, 0)),
+    '０' ..= '９' => {
+      char::from_u32(c as u32 - '０' as u32 + '0' as u32).map(|c| (c, 0))
+    },
+    '％' => Some(('%', 0)),
+    '．' => Some(('.', 0)),
+    '＋' => Some(('+', 0)),
+    'O' | 'o' | 'D' | 'Q' | 'U' => Some(('0', 1)),
+    'l' | 'I' | 'i' | '|' | '!' | 'L' | 'j' => Some(('1', 1)),
+    'Z' | 'z' => Some(('2', 1)),
+    'A' => Some(('4', 1)),
+    'S' | 's' => Some(('5', 1)),
+    'b' | 'G' => Some(('6', 1)),
+    'T' => Some(('7', 1)),
+    'B' => Some(('8', 1)),
+    'g' | 'q' => Some(('9', 1)),
+    ',' | '，' | '。' | '·' => Some(('.', 1)),
+    _ => None
+  }
+}
+
+fn push_candidate(
+  candidates: &mut Vec<ValueCandidate>,
+  digits: &str,
+  value_type: AffixValueType,
+  penalty: u32
+) {
+  if digits.is_empty() || digits.matches('.').count() > 1 {
+    return;
+  }
+
+  let value: f64 = match digits.parse() {
+    Ok(value) => value,
+    Err(_) => return
+  };
+
+  match candidates.iter_mut().find(|c| c.value == value && c.value_type == value_type) {
+    Some(candidate) => candidate.penalty = candidate.penalty.min(penalty),
+    None => candidates.push(ValueCandidate { value, value_type, penalty })
+  }
+}
+
+/// The text read from a value region and the values it may stand for.
+#[derive(Clone, Debug)]
+pub struct RecognizedValue {
+  pub raw: String,
+  pub candidates: Vec<ValueCandidate>,
+  pub confidence: f32
+}
+
+/// Parses raw OCR text of an affix value shown by the client of `language`
+/// in `font` into candidate values, best first.
+///
+/// Besides correcting characters commonly confused with digits, this accounts
+/// for `%` read as `96`, a missing `%` and a lost decimal point in
+/// percentages, and a spurious decimal point in flat values.
+pub fn parse_value_candidates(
+  raw: &str,
+  language: &Language,
+  font: &FontProfile
+) -> Vec<ValueCandidate> {
+  let mut text = String::new();
+  let mut penalty = 0;
+  for c in
+    raw.chars().filter(|c| !c.is_whitespace() && Some(*c) != language.thousands_separator)
+  {
+    match font.confusion(c).or_else(|| normalize_char(c)) {
+      Some((c, p)) => {
+        text.push(c);
+        penalty += p;
+      },
+      None => penalty += 2
+    }
+  }
+
+  let text = text.trim_start_matches('+');
+
+  let mut readings = vec![];
+  if let Some(digits) = text.strip_suffix('%') {
+    readings.push((digits, AffixValueType::Percentage, 0));
+  } else if let Some(digits) = text.strip_suffix("96") {
+    readings.push((text, AffixValueType::Flat, 0));
+    readings.push((digits, AffixValueType::Percentage, 1));
+    readings.push((text, AffixValueType::Percentage, 2));
+  } else {
+    readings.push((text, AffixValueType::Flat, 0));
+    readings.push((text, AffixValueType::Percentage, 2));
+  }
+
+  let mut candidates = vec![];
+  for (digits, value_type, reading_penalty) in readings {
+    let digits = digits.trim_start_matches('+');
+    let penalty = penalty + reading_penalty;
+
+    match value_type {
+      AffixValueType::Flat if digits.contains('.') => {
+        push_candidate(
+          &mut candidates,
+          &digits.replace('.', ""),
+          value_type,
+          penalty + 1
+        );
+      },
+      AffixValueType::Percentage if !digits.contains('.') && digits.len() >= 2 => {
+        push_candidate(&mut candidates, digits, value_type, penalty);
+        let (integer, fraction) = digits.split_at(digits.len() - 1);
+        push_candidate(
+          &mut candidates,
+          &format!("{}.{}", integer, fraction),
+          value_type,
+          penalty + 1
+        );
+      },
+      _ => push_candidate(&mut candidates, digits, value_type, penalty)
+    }
+  }
+
+  candidates.sort_by_key(|candidate| candidate.penalty);
+  candidates
+}
+
+/// Picks the best candidate with one of the `allowed` value types, which are
+/// usually those of the affix's stat.
+pub fn select_value_candidate<'a>(
+  candidates: &'a [ValueCandidate],
+  allowed: &[AffixValueType]
+) -> Option<&'a ValueCandidate> {
+  candidates.iter().find(|candidate| allowed.contains(&candidate.value_type))
+}
+
+/// Reads a value region in digits mode and returns the candidate values,
+/// keeping only those with an `allowed` value type when given.
+pub fn recognize_value(
+  engine: &dyn OcrEngine,
+  image: &GrayImage,
+  language: &Language,
+  font: &FontProfile,
+  allowed: Option<&[AffixValueType]>
+) -> Result<RecognizedValue, String> {
+  let options = font.apply_options(OcrOptions::for_language(language).digits());
+  let text = engine.recognize(&font.preprocessing.apply(image), &options)?;
+  let mut candidates = parse_value_candidates(&text.text, language, font);
+
+  if let Some(allowed) = allowed {
+    candidates.retain(|candidate| allowed.contains(&candidate.value_type));
+  }
+
+  Ok(RecognizedValue { raw: text.text, candidates, confidence: text.confidence })
+}
+
+/// Reads a value region with every font profile and keeps the reading with
+/// the highest confidence among those that produced a candidate.
+pub fn recognize_value_with_fonts<'a>(
+  engine: &dyn OcrEngine,
+  image: &GrayImage,
+  language: &Language,
+  fonts: &[&'a FontProfile],
+  allowed: Option<&[AffixValueType]>
+) -> Result<(RecognizedValue, &'a FontProfile), String> {
+  recognize_with_fonts(fonts, |font| {
+    let value = recognize_value(engine, image, language, font, allowed)?;
+    let score = if value.candidates.is_empty() { 0.0 } else { value.confidence };
+    Ok((value, score))
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    crate::{ocr::font::Confusion, source::game_data::GameData}
+  };
+
+  fn font() -> FontProfile {
+    FontProfile {
+      confusions: vec![Confusion { read: 'a', actual: '8', penalty: 1 }],
+      ..FontProfile::default()
+    }
+  }
+
+  fn readings(raw: &str) -> Vec<(f64, AffixValueType, u32)> {
+    let game_data = GameData::bundled();
+    let language = game_data.language("en").unwrap();
+    parse_value_candidates(raw, language, &font())
+      .into_iter()
+      .map(|candidate| (candidate.value, candidate.value_type, candidate.penalty))
+      .collect()
+  }
+
+  #[test]
+  fn reads_percentages_and_flat_values() {
+    use AffixValueType::*;
+
+    assert_eq!(readings("4.8%"), vec![(4.8, Percentage, 0)]);
+    assert_eq!(
+      readings("+112"),
+      vec![(112.0, Flat, 0), (112.0, Percentage, 2), (11.2, Percentage, 3)]
+    );
+    assert_eq!(
+      readings("1,024"),
+      vec![(1024.0, Flat, 0), (1024.0, Percentage, 2), (102.4, Percentage, 3)]
+    );
+  }
+
+  #[test]
+  fn restores_a_lost_decimal_point() {
+    use AffixValueType::*;
+
+    assert_eq!(readings("48%"), vec![(48.0, Percentage, 0), (4.8, Percentage, 1)]);
+    assert_eq!(readings("9"), vec![(9.0, Flat, 0), (9.0, Percentage, 2)]);
+  }
+
+  #[test]
+  fn reads_96_as_a_percent_sign() {
+    use AffixValueType::*;
+
+    assert_eq!(
+      readings("4.896"),
+      vec![(4896.0, Flat, 1), (4.8, Percentage, 1), (4.896, Percentage, 2)]
+    );
+    assert_eq!(
+      readings("2496"),
+      vec![
+        (2496.0, Flat, 0),
+        (24.0, Percentage, 1),
+        (2.4, Percentage, 2),
+        (2496.0, Percentage, 2),
+        (249.6, Percentage, 3)
+      ]
+    );
+  }
+
+  #[test]
+  fn corrects_misread_characters() {
+    use AffixValueType::*;
+
+    assert_eq!(readings("１２．５％"), vec![(12.5, Percentage, 0)]);
+    assert_eq!(
+      readings("4O"),
+      vec![(40.0, Flat, 1), (40.0, Percentage, 3), (4.0, Percentage, 4)]
+    );
+    assert_eq!(readings("1a%")[0], (18.0, Percentage, 1));
+    assert_eq!(readings("1#2%")[0], (12.0, Percentage, 2));
+    assert!(readings("").is_empty());
+    assert!(readings("1.2.3%").is_empty());
+  }
+
+  #[test]
+  fn selects_the_best_allowed_candidate() {
+    use AffixValueType::*;
+
+    let game_data = GameData::bundled();
+    let language = game_data.language("en").unwrap();
+    let candidates = parse_value_candidates("2496", language, &font());
+
+    let best = select_value_candidate(&candidates, &[Flat, Percentage]).unwrap();
+    assert_eq!((best.value, best.value_type), (2496.0, Flat));
+    let best = select_value_candidate(&candidates, &[Percentage]).unwrap();
+    assert_eq!((best.value, best.value_type), (24.0, Percentage));
+    let best = select_value_candidate(&candidates, &[Flat]).unwrap();
+    assert_eq!((best.value, best.value_type), (2496.0, Flat));
+
+    let candidates = parse_value_candidates("48%", language, &font());
+    assert_eq!(select_value_candidate(&candidates, &[Flat]), None);
+    assert_eq!(select_value_candidate(&candidates, &[]), None);
+  }
+}
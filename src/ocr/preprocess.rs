@@ -0,0 +1,138 @@
+use image::{imageops::FilterType, GrayImage, Luma};
+
+/// How a region is prepared before it is given to the OCR engine.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Preprocessing {
+  /// Factor the region is upscaled by; Tesseract reads small text poorly.
+  pub scale: u32,
+  /// Brightness that separates text from the background. `None` picks it per
+  /// region with Otsu's method.
+  pub threshold: Option<u8>,
+  /// Whether the text is lighter than the background, as on the disk panel,
+  /// and so has to be inverted into the dark-on-light text Tesseract expects.
+  pub invert: bool
+}
+
+impl Default for Preprocessing {
+  fn default() -> Self {
+    Self { scale: 2, threshold: None, invert: true }
+  }
+}
+
+/// Returns the threshold that best separates the brightness histogram of the
+/// image into two classes.
+fn otsu_threshold(image: &GrayImage) -> u8 {
+  let mut histogram = [0u64; 256];
+  for pixel in image.pixels() {
+    histogram[pixel[0] as usize] += 1;
+  }
+
+  let total: u64 = histogram.iter().sum();
+  let sum: f64 = histogram.iter().enumerate().map(|(i, &n)| i as f64 * n as f64).sum();
+
+  let mut best_threshold = 0;
+  let mut best_variance = 0.0;
+  let mut background_count = 0;
+  let mut background_sum = 0.0;
+
+  for (threshold, &count) in histogram.iter().enumerate() {
+    background_count += count;
+    if background_count == 0 {
+      continue;
+    }
+    let foreground_count = total - background_count;
+    if foreground_count == 0 {
+      break;
+    }
+
+    background_sum += threshold as f64 * count as f64;
+    let background_mean = background_sum / background_count as f64;
+    let foreground_mean = (sum - background_sum) / foreground_count as f64;
+    let variance = background_count as f64
+      * foreground_count as f64
+      * (background_mean - foreground_mean).powi(2);
+
+    if variance > best_variance {
+      best_variance = variance;
+      best_threshold = threshold as u8;
+    }
+  }
+
+  best_threshold
+}
+
+impl Preprocessing {
+  /// Upscales and binarizes the image into black text on white.
+  pub fn apply(&self, image: &GrayImage) -> GrayImage {
+    let mut image = if self.scale > 1 {
+      image::imageops::resize(
+        image,
+        image.width() * self.scale,
+        image.height() * self.scale,
+        FilterType::Triangle
+      )
+    } else {
+      image.clone()
+    };
+
+    let threshold = self.threshold.unwrap_or_else(|| otsu_threshold(&image));
+    for pixel in image.pixels_mut() {
+      let is_text =
+        if self.invert { pixel[0] > threshold } else { pixel[0] <= threshold };
+      *pixel = Luma([if is_text { 0 } else { 255 }]);
+    }
+
+    image
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Dark background around a bright block, each with some spread in
+  // brightness, as on a value region of the disk panel.
+  fn bimodal_image() -> GrayImage {
+    GrayImage::from_fn(40, 20, |x, y| {
+      let noise = ((x * 7 + y * 13) % 21) as u8;
+      if (10 .. 30).contains(&x) && (5 .. 15).contains(&y) {
+        Luma([190 + noise])
+      } else {
+        Luma([30 + noise])
+      }
+    })
+  }
+
+  #[test]
+  fn separates_the_two_modes() {
+    let threshold = otsu_threshold(&bimodal_image());
+    assert!((50 .. 190).contains(&threshold), "{}", threshold);
+  }
+
+  #[test]
+  fn handles_flat_images() {
+    assert_eq!(otsu_threshold(&GrayImage::from_pixel(4, 4, Luma([120]))), 0);
+  }
+
+  #[test]
+  fn binarizes_light_text_into_dark_text() {
+    let image = bimodal_image();
+    let binarized =
+      Preprocessing { scale: 1, threshold: None, invert: true }.apply(&image);
+
+    for (x, y, pixel) in binarized.enumerate_pixels() {
+      let expected = if image.get_pixel(x, y)[0] >= 190 { 0 } else { 255 };
+      assert_eq!(pixel[0], expected, "pixel {}, {}", x, y);
+    }
+
+    let upscaled = Preprocessing::default().apply(&image);
+    assert_eq!(upscaled.dimensions(), (80, 40));
+    assert_eq!(upscaled.get_pixel(40, 20)[0], 0);
+    assert_eq!(upscaled.get_pixel(2, 2)[0], 255);
+
+    let dark_text = Preprocessing { scale: 1, threshold: Some(100), invert: false };
+    let binarized = dark_text.apply(&image);
+    assert_eq!(binarized.get_pixel(20, 10)[0], 255);
+    assert_eq!(binarized.get_pixel(2, 2)[0], 0);
+  }
+}
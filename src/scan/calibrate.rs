@@ -0,0 +1,256 @@
+use {
+  crate::scan::{
+    input::InputMethod,
+    layout::{LayoutProfile, Layouts, Rect, Region},
+    panel::{locate_panel, PanelLocation}
+  },
+  image::{GrayImage, Rgb, RgbImage}
+};
+
+/// Colors the regions are outlined with in annotated images, in turn.
+pub const ANNOTATION_COLORS: [(&str, [u8; 3]); 6] = [
+  ("red", [230, 40, 40]),
+  ("green", [40, 200, 60]),
+  ("blue", [50, 110, 240]),
+  ("yellow", [240, 210, 30]),
+  ("magenta", [220, 50, 220]),
+  ("cyan", [40, 210, 220])
+];
+
+/// The regions fitted to a reference screenshot.
+#[derive(Clone, Debug)]
+pub struct Calibration {
+  /// The built-in layout the regions were derived from.
+  pub base: LayoutProfile,
+  /// The input method the screenshot was taken with.
+  pub input: InputMethod,
+  /// Where the panel was found, if it was.
+  pub panel: Option<PanelLocation>,
+  /// Every region in the screenshot, after the corrections were applied.
+  pub regions: Vec<(Region, Rect)>
+}
+
+impl Calibration {
+  /// Whether the regions are anchored to a panel, found or given, rather than
+  /// just copied from the layout.
+  pub fn is_fitted(&self, corrections: &[(Region, Rect)]) -> bool {
+    self.panel.is_some() || corrections.iter().any(|(region, _)| *region == Region::Panel)
+  }
+
+  /// Builds a layout profile for screenshots of the reference's size.
+  pub fn profile(&self, id: &str, name: &str, width: u32, height: u32) -> LayoutProfile {
+    LayoutProfile::calibrated(
+      &self.base,
+      id,
+      name,
+      width,
+      height,
+      self.input,
+      &self.regions
+    )
+  }
+}
+
+/// Fits the layouts to a reference screenshot taken with `input`: every layout
+/// is tried, and the one whose panel frame stands out most is kept, with its
+/// regions placed relative to the frame. `corrections` replace regions, given
+/// in pixels of the screenshot; a corrected panel moves the regions derived
+/// from it. When no panel is found or given, the regions of the layout
+/// matching the aspect ratio are used as they are.
+pub fn calibrate(
+  image: &GrayImage,
+  layouts: &Layouts,
+  input: InputMethod,
+  corrections: &[(Region, Rect)]
+) -> Calibration {
+  let (width, height) = (image.width(), image.height());
+
+  let mut best: Option<(&LayoutProfile, PanelLocation)> = None;
+  for profile in layouts.profiles.values() {
+    if let Some(found) = locate_panel(image, &profile.for_input(input)) {
+      if best.is_none_or(|(_, best)| found.contrast > best.contrast) {
+        best = Some((profile, found));
+      }
+    }
+  }
+
+  let base = best.map_or_else(|| layouts.select(width, height), |(profile, _)| profile);
+  let placed = base.for_input(input);
+  let panel = best.map(|(_, found)| found);
+  let panel_rect = corrections
+    .iter()
+    .find(|(region, _)| *region == Region::Panel)
+    .map(|&(_, rect)| rect)
+    .or(panel.map(|found| found.rect));
+
+  let mut regions: Vec<(Region, Rect)> = placed
+    .regions
+    .keys()
+    .filter_map(|&region| {
+      let rect = match (region, panel_rect) {
+        (Region::Panel, Some(panel)) => Some(panel),
+        (Region::Prompts, _) | (_, None) => placed.region(region, width, height),
+        (_, Some(panel)) => placed.region_in_panel(region, panel, width, height)
+      };
+      rect.map(|rect| (region, rect))
+    })
+    .collect();
+
+  for &(region, rect) in corrections {
+    match regions.iter_mut().find(|(r, _)| *r == region) {
+      Some((_, existing)) => *existing = rect,
+      None => regions.push((region, rect))
+    }
+  }
+  regions.sort_by_key(|(region, _)| *region);
+
+  Calibration { base: base.clone(), input, panel, regions }
+}
+
+/// Draws the outline of every region on a color copy of the screenshot,
+/// cycling through [`ANNOTATION_COLORS`].
+pub fn annotate(image: &GrayImage, regions: &[(Region, Rect)]) -> RgbImage {
+  let mut annotated = RgbImage::from_fn(image.width(), image.height(), |x, y| {
+    let value = image.get_pixel(x, y)[0];
+    Rgb([value, value, value])
+  });
+
+  for (index, (_, rect)) in regions.iter().enumerate() {
+    let color = Rgb(ANNOTATION_COLORS[index % ANNOTATION_COLORS.len()].1);
+    let right = (rect.x + rect.width).min(image.width());
+    let bottom = (rect.y + rect.height).min(image.height());
+
+    for thickness in 0 .. 2 {
+      for x in rect.x .. right {
+        for y in [rect.y + thickness, bottom.saturating_sub(1 + thickness)] {
+          if y < bottom {
+            annotated.put_pixel(x, y, color);
+          }
+        }
+      }
+      for y in rect.y .. bottom {
+        for x in [rect.x + thickness, right.saturating_sub(1 + thickness)] {
+          if x < right {
+            annotated.put_pixel(x, y, color);
+          }
+        }
+      }
+    }
+  }
+
+  annotated
+}
+
+#[cfg(test)]
+mod tests {
+  use {super::*, image::Luma};
+
+  // A layout whose panel moves 20 pixels up with a controller.
+  const LAYOUTS: &str = r#"
+    version = 1
+
+    [layouts.16_9]
+    name = "16:9"
+    aspect_ratio = "16:9"
+    min_aspect = "16:9"
+
+    [layouts.16_9.regions]
+    panel = { anchor = "right", x = -560, y = 140, width = 500, height = 760 }
+    set_name = { anchor = "right", x = -530, y = 170, width = 400, height = 40 }
+    primary_value = { anchor = "right", x = -220, y = 330, width = 160, height = 36 }
+    equipped = { anchor = "right", x = -530, y = 840, width = 440, height = 36 }
+    prompts = { anchor = "right", x = -960, y = 1000, width = 960, height = 80 }
+
+    [layouts.16_9.input_offsets]
+    controller = { x = 0, y = -20 }
+  "#;
+
+  fn rect(x: u32, y: u32, width: u32, height: u32) -> Rect {
+    Rect { x, y, width, height }
+  }
+
+  // A dark screenshot with a light frame around `frame`.
+  fn screenshot(width: u32, height: u32, frame: Rect) -> GrayImage {
+    GrayImage::from_fn(width, height, |x, y| {
+      let inside = (frame.x .. frame.x + frame.width).contains(&x)
+        && (frame.y .. frame.y + frame.height).contains(&y);
+      let on_frame = inside
+        && (x < frame.x + 2
+          || y < frame.y + 2
+          || x >= frame.x + frame.width - 2
+          || y >= frame.y + frame.height - 2);
+      Luma([if on_frame { 210 } else { 40 }])
+    })
+  }
+
+  #[test]
+  fn fits_regions_to_the_found_panel() {
+    let layouts = Layouts::from_toml(LAYOUTS).unwrap();
+    let frame = rect(1300, 100, 550, 836);
+    let calibration =
+      calibrate(&screenshot(1920, 1080, frame), &layouts, InputMethod::Controller, &[]);
+
+    assert_eq!(calibration.panel.map(|panel| panel.rect), Some(frame));
+    assert!(calibration.is_fitted(&[]));
+    assert_eq!(
+      calibration.regions,
+      vec![
+        (Region::Panel, frame),
+        (Region::SetName, rect(1333, 133, 440, 44)),
+        (Region::PrimaryValue, rect(1674, 309, 176, 40)),
+        (Region::Equipped, rect(1333, 870, 484, 40)),
+        (Region::Prompts, rect(960, 1000, 960, 80))
+      ]
+    );
+  }
+
+  #[test]
+  fn takes_the_input_offset_off_calibrated_profiles() {
+    let layouts = Layouts::from_toml(LAYOUTS).unwrap();
+    let base = layouts.get("16_9").unwrap();
+    // Where the controller layout expects the panel
+    let frame = rect(1360, 120, 500, 760);
+    let image = screenshot(1920, 1080, frame);
+
+    let calibration = calibrate(&image, &layouts, InputMethod::Controller, &[]);
+    assert_eq!(
+      calibration.regions,
+      base.for_input(InputMethod::Controller).regions(1920, 1080)
+    );
+
+    let profile = calibration.profile("custom", "Custom", 1920, 1080);
+    assert_eq!(profile.regions, base.regions);
+    assert_eq!(profile.input_offsets, base.input_offsets);
+  }
+
+  #[test]
+  fn lays_out_the_reference_like_it_was_calibrated() {
+    let layouts = Layouts::from_toml(LAYOUTS).unwrap();
+    let corrections = [(Region::SetName, rect(1320, 140, 420, 46))];
+
+    for (input, frame) in [
+      (InputMethod::KeyboardMouse, rect(1310, 150, 550, 836)),
+      (InputMethod::Controller, rect(1280, 110, 550, 836))
+    ] {
+      let image = screenshot(1920, 1080, frame);
+      let calibration = calibrate(&image, &layouts, input, &corrections);
+      let profile = calibration.profile("custom", "Custom", 1920, 1080);
+
+      let regions = profile.for_input(input).regions(1920, 1080);
+      assert_eq!(regions, calibration.regions, "{}", input);
+      assert!(regions.contains(&corrections[0]));
+
+      let annotated = annotate(&image, &regions);
+      for (index, (_, rect)) in regions.iter().enumerate() {
+        let color = Rgb(ANNOTATION_COLORS[index % ANNOTATION_COLORS.len()].1);
+        assert_eq!(*annotated.get_pixel(rect.x, rect.y), color, "{}", input);
+        assert_eq!(
+          *annotated.get_pixel(rect.x + rect.width - 1, rect.y + rect.height - 1),
+          color,
+          "{}",
+          input
+        );
+      }
+    }
+  }
+}
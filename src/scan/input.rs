@@ -0,0 +1,320 @@
+use {
+  crate::scan::{
+    layout::{LayoutProfile, Region, REFERENCE_HEIGHT},
+    template::{match_template, scale_template}
+  },
+  image::GrayImage,
+  std::{
+    env,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf}
+  }
+};
+
+/// Lowest template match score that counts as finding a prompt glyph.
+const CONFIDENT_SCORE: f32 = 0.7;
+const CONFIDENT_MARGIN: f32 = 0.1;
+
+/// The control layout of the client, which changes the button prompts shown
+/// at the bottom of the screen and, with them, where the disk panel sits.
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum InputMethod {
+  KeyboardMouse,
+  Controller
+}
+
+impl InputMethod {
+  pub const ALL: [Self; 2] = [Self::KeyboardMouse, Self::Controller];
+}
+
+impl TryFrom<&str> for InputMethod {
+  type Error = String;
+
+  fn try_from(value: &str) -> Result<Self, Self::Error> {
+    match value {
+      "keyboard_mouse" => Ok(Self::KeyboardMouse),
+      "controller" => Ok(Self::Controller),
+      _ => Err(format!("Invalid input method: {}", value))
+    }
+  }
+}
+
+impl Display for InputMethod {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::KeyboardMouse => write!(f, "keyboard_mouse"),
+      Self::Controller => write!(f, "controller")
+    }
+  }
+}
+
+/// Where the prompt templates are looked for by default: the
+/// `data/templates` directory next to the executable, as in a release, or in
+/// the working directory, as in a checkout of the sources.
+pub fn default_templates_path() -> PathBuf {
+  let bundled = env::current_exe()
+    .ok()
+    .and_then(|executable| executable.parent().map(|dir| dir.join("data/templates")))
+    .filter(|dir| dir.is_dir());
+  bundled.unwrap_or_else(|| PathBuf::from("data/templates"))
+}
+
+/// Images of button prompt glyphs, captured from a screen with a safe area
+/// [`REFERENCE_HEIGHT`] pixels high.
+#[derive(Clone, Debug, Default)]
+pub struct PromptTemplates {
+  pub templates: Vec<(InputMethod, String, GrayImage)>
+}
+
+impl PromptTemplates {
+  /// Loads the PNG files in the `keyboard_mouse` and `controller`
+  /// subdirectories of `directory`. Fails unless every input method has at
+  /// least one, since telling them apart needs both.
+  pub fn load(directory: impl AsRef<Path>) -> Result<Self, String> {
+    let mut templates = vec![];
+
+    for input in InputMethod::ALL {
+      let directory = directory.as_ref().join(input.to_string());
+      if !directory.is_dir() {
+        return Err(format!(
+          "No {} prompt templates: {} is not a directory",
+          input,
+          directory.display()
+        ));
+      }
+
+      let entries = fs::read_dir(&directory).map_err(|error| {
+        format!("Failed to read templates {}: {}", directory.display(), error)
+      })?;
+      let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "png"))
+        .collect();
+      paths.sort();
+
+      for path in paths {
+        let image = image::open(&path)
+          .map_err(|error| {
+            format!("Failed to open template {}: {}", path.display(), error)
+          })?
+          .to_luma8();
+        let name = path.file_stem().map_or(String::new(), |s| s.to_string_lossy().into());
+        templates.push((input, name, image));
+      }
+      if !templates.iter().any(|(i, _, _)| *i == input) {
+        return Err(format!(
+          "No {} prompt templates: no PNG files in {}",
+          input,
+          directory.display()
+        ));
+      }
+    }
+
+    Ok(Self { templates })
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.templates.is_empty()
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct InputDetection {
+  /// The input method whose prompts matched best.
+  pub input: InputMethod,
+  /// Best template match score of each input method, best first.
+  pub scores: Vec<(InputMethod, f32)>
+}
+
+impl InputDetection {
+  pub fn score(&self) -> f32 {
+    self.scores.first().map_or(0.0, |(_, score)| *score)
+  }
+
+  /// How far the best score is ahead of the second best.
+  pub fn margin(&self) -> f32 {
+    match self.scores.get(1) {
+      Some((_, second)) => self.score() - second,
+      None => self.score()
+    }
+  }
+
+  pub fn is_confident(&self) -> bool {
+    self.score() >= CONFIDENT_SCORE && self.margin() >= CONFIDENT_MARGIN
+  }
+}
+
+/// Detects the control layout of a screenshot by matching the prompt glyph
+/// templates against the prompt bar of the layout.
+pub fn detect_input_method(
+  image: &GrayImage,
+  layout: &LayoutProfile,
+  templates: &PromptTemplates
+) -> Result<InputDetection, String> {
+  if templates.is_empty() {
+    return Err("No prompt templates to detect the input method with".to_string());
+  }
+
+  let prompts = layout
+    .region(Region::Prompts, image.width(), image.height())
+    .ok_or(format!("Layout {} has no prompts region in the image", layout.id))?
+    .crop(image);
+  let scale = layout.safe_area(image.width(), image.height()).height as f64
+    / REFERENCE_HEIGHT as f64;
+
+  let mut scores: Vec<(InputMethod, f32)> = vec![];
+  for (input, _, template) in templates.templates.iter() {
+    let score = match match_template(&prompts, &scale_template(template, scale)) {
+      Some(found) => found.score,
+      None => continue
+    };
+    match scores.iter_mut().find(|(i, _)| i == input) {
+      Some((_, best)) => *best = best.max(score),
+      None => scores.push((*input, score))
+    }
+  }
+
+  scores.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+  match scores.first() {
+    Some((input, _)) => Ok(InputDetection { input: *input, scores }),
+    None => Err("No prompt template fits in the prompts region".to_string())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    crate::scan::layout::Layouts,
+    image::{imageops::FilterType, Luma}
+  };
+
+  // A key cap with a bar in it, like the keyboard prompts.
+  fn key_glyph() -> GrayImage {
+    GrayImage::from_fn(28, 28, |x, y| {
+      let border = !(3 ..= 24).contains(&x) || !(3 ..= 24).contains(&y);
+      let bar = (8 .. 20).contains(&x) && (12 .. 16).contains(&y);
+      Luma([if border || bar { 220 } else { 60 }])
+    })
+  }
+
+  // A ring with a dot in it, like the controller button prompts.
+  fn button_glyph() -> GrayImage {
+    GrayImage::from_fn(28, 28, |x, y| {
+      let distance = ((x as f64 - 13.5).powi(2) + (y as f64 - 13.5).powi(2)).sqrt();
+      let lit = (9.0 .. 13.0).contains(&distance) || distance < 4.0;
+      Luma([if lit { 220 } else { 60 }])
+    })
+  }
+
+  fn templates() -> PromptTemplates {
+    PromptTemplates {
+      templates: vec![
+        (InputMethod::KeyboardMouse, "key".to_string(), key_glyph()),
+        (InputMethod::Controller, "button".to_string(), button_glyph()),
+      ]
+    }
+  }
+
+  // A 1920x1080 screenshot showing `glyph` in the prompt bar, in the bottom
+  // right quarter of the screen.
+  fn screenshot(glyph: &GrayImage) -> GrayImage {
+    let mut image =
+      GrayImage::from_fn(1920, 1080, |x, y| Luma([((x + y) % 50) as u8 + 20]));
+    for x in 960 .. 1920 {
+      for y in 1000 .. 1080 {
+        image.put_pixel(x, y, Luma([60]));
+      }
+    }
+    image::imageops::replace(&mut image, glyph, 1600, 1026);
+    image
+  }
+
+  #[test]
+  fn detects_the_input_method_of_the_prompts() {
+    let layouts = Layouts::bundled();
+    let layout = layouts.select(1920, 1080);
+
+    let detection =
+      detect_input_method(&screenshot(&button_glyph()), layout, &templates()).unwrap();
+    assert_eq!(detection.input, InputMethod::Controller);
+    assert!(detection.score() > 0.99, "{}", detection.score());
+    assert!(detection.is_confident());
+
+    let detection =
+      detect_input_method(&screenshot(&key_glyph()), layout, &templates()).unwrap();
+    assert_eq!(detection.input, InputMethod::KeyboardMouse);
+    assert!(detection.is_confident());
+  }
+
+  #[test]
+  fn scales_templates_to_the_screenshot() {
+    let layouts = Layouts::bundled();
+    let image = image::imageops::resize(
+      &screenshot(&button_glyph()),
+      1280,
+      720,
+      FilterType::Triangle
+    );
+
+    let detection =
+      detect_input_method(&image, layouts.select(1280, 720), &templates()).unwrap();
+    assert_eq!(detection.input, InputMethod::Controller);
+    assert!(detection.is_confident(), "{:?}", detection.scores);
+  }
+
+  #[test]
+  fn doubts_prompts_without_any_glyph() {
+    let layouts = Layouts::bundled();
+    let image = screenshot(&GrayImage::from_pixel(28, 28, Luma([60])));
+
+    let detection =
+      detect_input_method(&image, layouts.select(1920, 1080), &templates()).unwrap();
+    assert!(!detection.is_confident(), "{:?}", detection.scores);
+  }
+
+  #[test]
+  fn needs_templates() {
+    let layouts = Layouts::bundled();
+    let image = screenshot(&button_glyph());
+
+    assert!(detect_input_method(
+      &image,
+      layouts.select(1920, 1080),
+      &PromptTemplates::default()
+    )
+    .is_err());
+  }
+
+  #[test]
+  fn fails_to_load_without_templates_of_each_input_method() {
+    let directory =
+      env::temp_dir().join(format!("prompt_templates_{}", std::process::id()));
+    let keyboard_mouse = directory.join("keyboard_mouse");
+    fs::create_dir_all(&keyboard_mouse).unwrap();
+    key_glyph().save(keyboard_mouse.join("key.png")).unwrap();
+
+    let error = PromptTemplates::load(&directory).unwrap_err();
+    assert!(error.contains("No controller prompt templates"), "{}", error);
+
+    fs::create_dir_all(directory.join("controller")).unwrap();
+    let error = PromptTemplates::load(&directory).unwrap_err();
+    assert!(error.contains("no PNG files"), "{}", error);
+
+    button_glyph().save(directory.join("controller/button.png")).unwrap();
+    let templates = PromptTemplates::load(&directory).unwrap();
+    fs::remove_dir_all(&directory).unwrap();
+
+    let names: Vec<(InputMethod, &str)> = templates
+      .templates
+      .iter()
+      .map(|(input, name, _)| (*input, name.as_str()))
+      .collect();
+    assert_eq!(
+      names,
+      vec![(InputMethod::KeyboardMouse, "key"), (InputMethod::Controller, "button")]
+    );
+    assert_eq!(templates.templates[1].2, button_glyph());
+  }
+}
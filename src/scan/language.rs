@@ -0,0 +1,279 @@
+use {
+  crate::{
+    ocr::{
+      engine::{OcrEngine, OcrOptions},
+      font::FontProfile
+    },
+    source::game_data::GameData
+  },
+  image::GrayImage,
+  levenshtein::levenshtein,
+  std::collections::{BTreeMap, BTreeSet}
+};
+
+/// Minimum score of the best language and lead over the runner-up for a
+/// detection to be trusted without asking the user.
+const CONFIDENT_SCORE: f32 = 0.6;
+const CONFIDENT_MARGIN: f32 = 0.15;
+
+#[derive(Clone, Debug)]
+pub struct LanguageDetection {
+  /// The locale with the highest score.
+  pub locale: String,
+  /// Score of every locale with fixed words, best first. A score is the mean
+  /// similarity, between 0 and 1, of the locale's fixed words to their best
+  /// match in the OCR text.
+  pub scores: Vec<(String, f32)>,
+  /// Tesseract languages that couldn't be read, with the error.
+  pub skipped: Vec<(String, String)>
+}
+
+impl LanguageDetection {
+  pub fn score(&self) -> f32 {
+    self.scores.first().map_or(0.0, |(_, score)| *score)
+  }
+
+  /// How far the best score is ahead of the second best.
+  pub fn margin(&self) -> f32 {
+    match self.scores.get(1) {
+      Some((_, second)) => self.score() - second,
+      None => self.score()
+    }
+  }
+
+  pub fn is_confident(&self) -> bool {
+    self.score() >= CONFIDENT_SCORE && self.margin() >= CONFIDENT_MARGIN
+  }
+}
+
+/// Returns the similarity between `word` and the closest substring of `text`,
+/// between 0 and 1.
+fn best_substring_similarity(word: &str, text: &str) -> f32 {
+  let word_length = word.chars().count();
+  if word_length == 0 {
+    return 0.0;
+  }
+
+  let chars: Vec<char> = text.chars().collect();
+  let mut best_distance = word_length;
+
+  for length in word_length.saturating_sub(1) ..= word_length + 1 {
+    if length == 0 || length > chars.len() {
+      continue;
+    }
+    for window in chars.windows(length) {
+      let candidate: String = window.iter().collect();
+      best_distance = best_distance.min(levenshtein(word, &candidate));
+    }
+  }
+
+  1.0 - best_distance as f32 / word_length as f32
+}
+
+/// Scores how well the OCR text of a panel matches the fixed words of every
+/// locale that has them, leaving out experimental ones. `texts` holds the text
+/// read with each Tesseract language; locales whose language wasn't read are
+/// left out too.
+pub fn score_languages(
+  texts: &BTreeMap<String, String>,
+  game_data: &GameData
+) -> Vec<(String, f32)> {
+  let mut scores = vec![];
+
+  for (locale, words) in game_data.fixed_words.iter() {
+    let language = match game_data.languages.get(locale) {
+      Some(language) if !words.is_empty() && !language.experimental => language,
+      _ => continue
+    };
+    let text = match texts.get(&language.tesseract) {
+      Some(text) => text,
+      None => continue
+    };
+
+    let lines: Vec<String> =
+      text.lines().map(|line| language.normalize_text(line)).collect();
+    let total: f32 = words
+      .iter()
+      .map(|word| {
+        lines.iter().map(|line| best_substring_similarity(word, line)).fold(0.0, f32::max)
+      })
+      .sum();
+
+    scores.push((locale.clone(), total / words.len() as f32));
+  }
+
+  scores.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+  scores
+}
+
+/// Reads the headings of a panel screenshot once per Tesseract language, then
+/// picks the locale whose fixed words match best in the text read with its
+/// language. Languages that fail, usually because their data isn't installed,
+/// are skipped unless they all do.
+pub fn detect_language(
+  engine: &dyn OcrEngine,
+  image: &GrayImage,
+  font: &FontProfile,
+  game_data: &GameData
+) -> Result<LanguageDetection, String> {
+  let tesseract_languages: BTreeSet<&str> = game_data
+    .languages
+    .values()
+    .filter(|language| !language.experimental)
+    .map(|language| language.tesseract.as_str())
+    .collect();
+  if tesseract_languages.is_empty() {
+    return Err("No languages to detect".to_string());
+  }
+
+  let image = font.preprocessing.apply(image);
+  let mut texts = BTreeMap::new();
+  let mut skipped = vec![];
+  for tesseract_language in tesseract_languages {
+    let options = font.apply_options(OcrOptions {
+      language: tesseract_language.to_string(),
+      // Sparse text, since the headings are scattered over the panel
+      page_segmentation: 11,
+      ..OcrOptions::default()
+    });
+    match engine.recognize(&image, &options) {
+      Ok(text) => {
+        texts.insert(tesseract_language.to_string(), text.text);
+      },
+      Err(error) => skipped.push((tesseract_language.to_string(), error))
+    }
+  }
+  if texts.is_empty() {
+    return Err(match skipped.pop() {
+      Some((language, error)) => format!("Failed to read {}: {}", language, error),
+      None => "No languages to detect".to_string()
+    });
+  }
+
+  let scores = score_languages(&texts, game_data);
+  match scores.first() {
+    Some((locale, _)) => {
+      Ok(LanguageDetection { locale: locale.clone(), scores, skipped })
+    },
+    None => Err("No language has fixed words to detect".to_string())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    crate::ocr::engine::OcrText,
+    std::{cell::RefCell, collections::HashMap}
+  };
+
+  fn texts(texts: &[(&str, &str)]) -> BTreeMap<String, String> {
+    texts
+      .iter()
+      .map(|&(language, text)| (language.to_string(), text.to_string()))
+      .collect()
+  }
+
+  fn score(scores: &[(String, f32)], locale: &str) -> Option<f32> {
+    scores.iter().find(|(l, _)| l == locale).map(|&(_, score)| score)
+  }
+
+  fn detection(scores: &[(&str, f32)]) -> LanguageDetection {
+    LanguageDetection {
+      locale: scores[0].0.to_string(),
+      scores: scores.iter().map(|&(locale, score)| (locale.to_string(), score)).collect(),
+      skipped: vec![]
+    }
+  }
+
+  /// Answers each Tesseract language with fixed text, or fails for those
+  /// without any, and records the languages it was asked for.
+  struct StubEngine {
+    texts: HashMap<&'static str, &'static str>,
+    calls: RefCell<Vec<String>>
+  }
+
+  impl OcrEngine for StubEngine {
+    fn recognize(&self, _: &GrayImage, options: &OcrOptions) -> Result<OcrText, String> {
+      self.calls.borrow_mut().push(options.language.clone());
+      match self.texts.get(options.language.as_str()) {
+        Some(text) => Ok(OcrText { text: text.to_string(), confidence: 0.9 }),
+        None => Err(format!("Failed loading language '{}'", options.language))
+      }
+    }
+  }
+
+  #[test]
+  fn scores_fixed_words_in_the_text_of_each_language() {
+    let game_data = GameData::bundled();
+    let scores = score_languages(
+      &texts(&[
+        ("chi_sim", "主属性 攻击力\n副属性 暴击率"),
+        ("chi_tra", "主属性\n副屬性"),
+        ("eng", "Main Stat\nSub Stat5")
+      ]),
+      &game_data
+    );
+
+    assert_eq!(scores[0], ("zh-CN".to_string(), 1.0));
+    assert!((score(&scores, "zh-TW").unwrap() - 5.0 / 6.0).abs() < 1e-6);
+    let en = score(&scores, "en").unwrap();
+    assert!(en > 0.7 && en < 1.0, "{}", en);
+  }
+
+  #[test]
+  fn scores_only_read_and_checked_languages() {
+    let game_data = GameData::bundled();
+    let scores = score_languages(
+      &texts(&[
+        ("chi_sim", "主属性\n副属性"),
+        ("jpn", "メインステータス\nサブステータス"),
+        ("kor", "주 스탯\n보조 스탯")
+      ]),
+      &game_data
+    );
+
+    assert_eq!(scores, vec![("zh-CN".to_string(), 1.0)]);
+  }
+
+  #[test]
+  fn trusts_a_clear_lead() {
+    assert!(detection(&[("zh-CN", 1.0), ("zh-TW", 0.67), ("en", 0.0)]).is_confident());
+    assert!(detection(&[("en", 0.6)]).is_confident());
+
+    let close = detection(&[("zh-CN", 0.9), ("zh-TW", 0.8)]);
+    assert!((close.margin() - 0.1).abs() < 1e-6);
+    assert!(!close.is_confident());
+    assert!(!detection(&[("zh-CN", 0.5), ("en", 0.0)]).is_confident());
+  }
+
+  #[test]
+  fn reads_once_per_language_and_skips_failures() {
+    let game_data = GameData::bundled();
+    let engine = StubEngine {
+      texts: HashMap::from([("chi_sim", "主属性\n副属性"), ("eng", "Level 15")]),
+      calls: RefCell::new(vec![])
+    };
+    let image = GrayImage::new(8, 8);
+
+    let detection =
+      detect_language(&engine, &image, &FontProfile::default(), &game_data).unwrap();
+    assert_eq!(detection.locale, "zh-CN");
+    assert_eq!(detection.score(), 1.0);
+    assert_eq!(*engine.calls.borrow(), vec!["chi_sim", "chi_tra", "eng"]);
+    assert_eq!(detection.skipped.len(), 1);
+    assert_eq!(detection.skipped[0].0, "chi_tra");
+    assert_eq!(score(&detection.scores, "zh-TW"), None);
+  }
+
+  #[test]
+  fn fails_when_no_language_can_be_read() {
+    let game_data = GameData::bundled();
+    let engine = StubEngine { texts: HashMap::new(), calls: RefCell::new(vec![]) };
+    let image = GrayImage::new(8, 8);
+
+    let error =
+      detect_language(&engine, &image, &FontProfile::default(), &game_data).unwrap_err();
+    assert!(error.contains("eng"), "{}", error);
+  }
+}
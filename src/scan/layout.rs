@@ -0,0 +1,737 @@
+use {
+  crate::scan::input::InputMethod,
+  image::GrayImage,
+  serde::{Deserialize, Serialize},
+  std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Display,
+    fs,
+    path::Path
+  }
+};
+
+static BUNDLED_LAYOUTS: &str = include_str!("../../data/layouts.toml");
+
+/// Height of the safe area the coordinates of a layout are given in; they are
+/// scaled to the actual safe area, since the UI scales with the screen
+/// height.
+pub const REFERENCE_HEIGHT: u32 = 1080;
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct RawLayouts {
+  version: u32,
+  #[serde(default)]
+  layouts: BTreeMap<String, RawLayoutProfile>
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct RawLayoutProfile {
+  name: String,
+  aspect_ratio: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  min_aspect: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  max_aspect: Option<String>,
+  regions: BTreeMap<String, RawRegion>,
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  input_offsets: BTreeMap<String, RawOffset>
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct RawOffset {
+  x: i32,
+  y: i32
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct RawRegion {
+  anchor: String,
+  x: i32,
+  y: i32,
+  width: u32,
+  height: u32
+}
+
+/// A region of interest of the disk panel.
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Region {
+  /// The whole panel, including the fixed headings.
+  Panel,
+  SetName,
+  Slot,
+  PrimaryStat,
+  PrimaryValue,
+  /// Stat name of the secondary affix at the given position, from 1 to 4.
+  SecondaryStat(u8),
+  /// Value of the secondary affix at the given position, from 1 to 4.
+  SecondaryValue(u8),
+  /// The line naming the agent an equipped disk is on.
+  Equipped,
+  /// The bar of button prompts at the bottom of the screen.
+  Prompts
+}
+
+impl TryFrom<&str> for Region {
+  type Error = String;
+
+  fn try_from(value: &str) -> Result<Self, Self::Error> {
+    let secondary = |prefix: &str| {
+      value
+        .strip_prefix(prefix)
+        .and_then(|position| position.parse::<u8>().ok())
+        .filter(|position| (1 ..= 4).contains(position))
+    };
+
+    match value {
+      "panel" => Ok(Self::Panel),
+      "set_name" => Ok(Self::SetName),
+      "slot" => Ok(Self::Slot),
+      "primary_stat" => Ok(Self::PrimaryStat),
+      "primary_value" => Ok(Self::PrimaryValue),
+      "equipped" => Ok(Self::Equipped),
+      "prompts" => Ok(Self::Prompts),
+      _ => {
+        if let Some(position) = secondary("secondary_stat_") {
+          Ok(Self::SecondaryStat(position))
+        } else if let Some(position) = secondary("secondary_value_") {
+          Ok(Self::SecondaryValue(position))
+        } else {
+          Err(format!("Invalid region: {}", value))
+        }
+      },
+    }
+  }
+}
+
+impl Display for Region {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Panel => write!(f, "panel"),
+      Self::SetName => write!(f, "set_name"),
+      Self::Slot => write!(f, "slot"),
+      Self::PrimaryStat => write!(f, "primary_stat"),
+      Self::PrimaryValue => write!(f, "primary_value"),
+      Self::SecondaryStat(position) => write!(f, "secondary_stat_{}", position),
+      Self::SecondaryValue(position) => write!(f, "secondary_value_{}", position),
+      Self::Equipped => write!(f, "equipped"),
+      Self::Prompts => write!(f, "prompts")
+    }
+  }
+}
+
+/// Which point of the safe area a region's horizontal offset is measured
+/// from. Panels docked to a side of the screen move with that side when the
+/// screen is wider or narrower than 16:9.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Anchor {
+  Left,
+  Center,
+  Right
+}
+
+impl TryFrom<&str> for Anchor {
+  type Error = String;
+
+  fn try_from(value: &str) -> Result<Self, Self::Error> {
+    match value {
+      "left" => Ok(Self::Left),
+      "center" => Ok(Self::Center),
+      "right" => Ok(Self::Right),
+      _ => Err(format!("Invalid anchor: {}", value))
+    }
+  }
+}
+
+impl Display for Anchor {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Left => write!(f, "left"),
+      Self::Center => write!(f, "center"),
+      Self::Right => write!(f, "right")
+    }
+  }
+}
+
+/// A rectangle in image pixels.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Rect {
+  pub x: u32,
+  pub y: u32,
+  pub width: u32,
+  pub height: u32
+}
+
+impl Rect {
+  pub fn crop(&self, image: &GrayImage) -> GrayImage {
+    image::imageops::crop_imm(image, self.x, self.y, self.width, self.height).to_image()
+  }
+}
+
+impl Display for Rect {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}x{}+{}+{}", self.width, self.height, self.x, self.y)
+  }
+}
+
+impl TryFrom<&str> for Rect {
+  type Error = String;
+
+  /// Parses a rectangle written as `WIDTHxHEIGHT+X+Y`, as it is displayed.
+  fn try_from(value: &str) -> Result<Self, Self::Error> {
+    let invalid = || format!("Invalid rectangle, expected WIDTHxHEIGHT+X+Y: {}", value);
+
+    let (size, position) = value.trim().split_once('+').ok_or_else(invalid)?;
+    let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+    let (x, y) = position.split_once('+').ok_or_else(invalid)?;
+    match (width.parse(), height.parse(), x.parse(), y.parse()) {
+      (Ok(width), Ok(height), Ok(x), Ok(y)) if width > 0 && height > 0 => {
+        Ok(Self { x, y, width, height })
+      },
+      _ => Err(invalid())
+    }
+  }
+}
+
+/// A region positioned relative to an anchor of the safe area, in pixels of a
+/// safe area [`REFERENCE_HEIGHT`] pixels high. `x` is the offset of the
+/// region's left edge from the anchor and may be negative; `y` is measured
+/// from the top of the safe area.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct AnchoredRect {
+  pub anchor: Anchor,
+  pub x: i32,
+  pub y: i32,
+  pub width: u32,
+  pub height: u32
+}
+
+/// Where the regions of the disk panel are on screens of one aspect ratio.
+#[derive(Clone, Debug)]
+pub struct LayoutProfile {
+  pub id: String,
+  pub name: String,
+  /// Aspect ratio the profile is made for, as width and height.
+  pub aspect_ratio: (u32, u32),
+  /// The UI is letterboxed on screens narrower than this.
+  pub min_aspect: Option<(u32, u32)>,
+  /// The UI is pillarboxed on screens wider than this.
+  pub max_aspect: Option<(u32, u32)>,
+  pub regions: BTreeMap<Region, AnchoredRect>,
+  /// How far the panel regions move, in reference pixels, when the client
+  /// uses an input method other than keyboard and mouse.
+  pub input_offsets: BTreeMap<InputMethod, (i32, i32)>
+}
+
+fn aspect(ratio: (u32, u32)) -> f64 {
+  ratio.0 as f64 / ratio.1 as f64
+}
+
+fn format_aspect_ratio(ratio: (u32, u32)) -> String {
+  format!("{}:{}", ratio.0, ratio.1)
+}
+
+fn greatest_common_divisor(a: u32, b: u32) -> u32 {
+  if b == 0 {
+    a
+  } else {
+    greatest_common_divisor(b, a % b)
+  }
+}
+
+fn parse_aspect_ratio(value: &str) -> Result<(u32, u32), String> {
+  let (width, height) =
+    value.split_once(':').ok_or(format!("Invalid aspect ratio: {}", value))?;
+  match (width.trim().parse::<u32>(), height.trim().parse::<u32>()) {
+    (Ok(width), Ok(height)) if width > 0 && height > 0 => Ok((width, height)),
+    _ => Err(format!("Invalid aspect ratio: {}", value))
+  }
+}
+
+impl LayoutProfile {
+  /// Builds a profile for images of exactly `width` by `height` pixels from
+  /// regions found in one of them, anchoring each region like in `base`. The
+  /// safe area is the whole image, and the input offsets are those of `base`.
+  /// The image shows the client with `input`, so the offset of `input` is
+  /// taken off the panel regions, which then hold where they are with
+  /// keyboard and mouse, as in every profile.
+  pub fn calibrated(
+    base: &LayoutProfile,
+    id: &str,
+    name: &str,
+    width: u32,
+    height: u32,
+    input: InputMethod,
+    regions: &[(Region, Rect)]
+  ) -> Self {
+    let divisor = greatest_common_divisor(width, height).max(1);
+    let scale = height as f64 / REFERENCE_HEIGHT as f64;
+    let (offset_x, offset_y) = base.input_offsets.get(&input).copied().unwrap_or((0, 0));
+
+    let regions = regions
+      .iter()
+      .map(|&(region, rect)| {
+        let anchor = base.regions.get(&region).map_or(Anchor::Left, |r| r.anchor);
+        let anchor_x = match anchor {
+          Anchor::Left => 0.0,
+          Anchor::Center => width as f64 / 2.0,
+          Anchor::Right => width as f64
+        };
+        let (offset_x, offset_y) =
+          if region == Region::Prompts { (0, 0) } else { (offset_x, offset_y) };
+        let anchored = AnchoredRect {
+          anchor,
+          x: ((rect.x as f64 - anchor_x) / scale).round() as i32 - offset_x,
+          y: (rect.y as f64 / scale).round() as i32 - offset_y,
+          width: ((rect.width as f64 / scale).round() as u32).max(1),
+          height: ((rect.height as f64 / scale).round() as u32).max(1)
+        };
+        (region, anchored)
+      })
+      .collect();
+
+    Self {
+      id: id.to_string(),
+      name: name.to_string(),
+      aspect_ratio: (width / divisor, height / divisor),
+      min_aspect: None,
+      max_aspect: None,
+      regions,
+      input_offsets: base.input_offsets.clone()
+    }
+  }
+
+  /// Returns the profile with the panel regions moved by the offset of
+  /// `input`. The prompts region stays where it is.
+  pub fn for_input(&self, input: InputMethod) -> Self {
+    let mut profile = self.clone();
+    if let Some(&(x, y)) = self.input_offsets.get(&input) {
+      for (region, rect) in profile.regions.iter_mut() {
+        if *region != Region::Prompts {
+          rect.x += x;
+          rect.y += y;
+        }
+      }
+    }
+    profile
+  }
+
+  /// Returns the part of an image of the given size the UI is drawn in,
+  /// leaving out letterbox or pillarbox bars.
+  pub fn safe_area(&self, width: u32, height: u32) -> Rect {
+    let image_aspect = width as f64 / height as f64;
+
+    if let Some(min_aspect) = self.min_aspect.map(aspect).filter(|a| image_aspect < *a) {
+      let safe_height = (width as f64 / min_aspect).round() as u32;
+      return Rect { x: 0, y: (height - safe_height) / 2, width, height: safe_height };
+    }
+    if let Some(max_aspect) = self.max_aspect.map(aspect).filter(|a| image_aspect > *a) {
+      let safe_width = (height as f64 * max_aspect).round() as u32;
+      return Rect { x: (width - safe_width) / 2, y: 0, width: safe_width, height };
+    }
+
+    Rect { x: 0, y: 0, width, height }
+  }
+
+  /// Returns where `region` is in an image of the given size, clipped to the
+  /// image, or `None` if the profile doesn't define it or it falls outside.
+  pub fn region(&self, region: Region, width: u32, height: u32) -> Option<Rect> {
+    let rect = self.regions.get(&region)?;
+    let safe_area = self.safe_area(width, height);
+    let scale = safe_area.height as f64 / REFERENCE_HEIGHT as f64;
+
+    let anchor_x = match rect.anchor {
+      Anchor::Left => safe_area.x as f64,
+      Anchor::Center => safe_area.x as f64 + safe_area.width as f64 / 2.0,
+      Anchor::Right => (safe_area.x + safe_area.width) as f64
+    };
+    let left = (anchor_x + rect.x as f64 * scale).round() as i64;
+    let top = (safe_area.y as f64 + rect.y as f64 * scale).round() as i64;
+    let right = left + (rect.width as f64 * scale).round() as i64;
+    let bottom = top + (rect.height as f64 * scale).round() as i64;
+
+    let (left, top) = (left.max(0), top.max(0));
+    let (right, bottom) = (right.min(width as i64), bottom.min(height as i64));
+    if right <= left || bottom <= top {
+      return None;
+    }
+
+    Some(Rect {
+      x: left as u32,
+      y: top as u32,
+      width: (right - left) as u32,
+      height: (bottom - top) as u32
+    })
+  }
+
+  /// Returns every region of the profile in an image of the given size.
+  pub fn regions(&self, width: u32, height: u32) -> Vec<(Region, Rect)> {
+    self
+      .regions
+      .keys()
+      .filter_map(|&region| self.region(region, width, height).map(|rect| (region, rect)))
+      .collect()
+  }
+}
+
+impl From<&LayoutProfile> for RawLayoutProfile {
+  fn from(profile: &LayoutProfile) -> Self {
+    Self {
+      name: profile.name.clone(),
+      aspect_ratio: format_aspect_ratio(profile.aspect_ratio),
+      min_aspect: profile.min_aspect.map(format_aspect_ratio),
+      max_aspect: profile.max_aspect.map(format_aspect_ratio),
+      regions: profile
+        .regions
+        .iter()
+        .map(|(region, rect)| {
+          let raw = RawRegion {
+            anchor: rect.anchor.to_string(),
+            x: rect.x,
+            y: rect.y,
+            width: rect.width,
+            height: rect.height
+          };
+          (region.to_string(), raw)
+        })
+        .collect(),
+      input_offsets: profile
+        .input_offsets
+        .iter()
+        .map(|(input, &(x, y))| (input.to_string(), RawOffset { x, y }))
+        .collect()
+    }
+  }
+}
+
+/// The layout profiles known to the scanner, loaded from a versioned TOML or
+/// JSON bundle.
+#[derive(Clone, Debug)]
+pub struct Layouts {
+  pub version: u32,
+  pub profiles: BTreeMap<String, LayoutProfile>
+}
+
+impl Layouts {
+  pub const SUPPORTED_VERSION: u32 = 1;
+
+  /// The layouts compiled into the binary.
+  pub fn bundled() -> Self {
+    Self::from_toml(BUNDLED_LAYOUTS).expect("bundled layouts are invalid")
+  }
+
+  /// Loads the bundled layouts, adding those of `path` if given. Profiles
+  /// from `path` replace bundled ones with the same ID.
+  pub fn load(path: Option<&Path>) -> Result<Self, String> {
+    let mut layouts = Self::bundled();
+    if let Some(path) = path {
+      layouts.profiles.extend(Self::from_file(path)?.profiles);
+    }
+    Ok(layouts)
+  }
+
+  /// Writes the layouts to a `.json` or `.toml` file.
+  pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+    let path = path.as_ref();
+    let raw = RawLayouts {
+      version: self.version,
+      layouts: self
+        .profiles
+        .iter()
+        .map(|(id, profile)| (id.clone(), RawLayoutProfile::from(profile)))
+        .collect()
+    };
+
+    let content = match path.extension().and_then(|extension| extension.to_str()) {
+      Some("json") => {
+        serde_json::to_string_pretty(&raw).map_err(|error| error.to_string())
+      },
+      _ => toml::to_string(&raw).map_err(|error| error.to_string())
+    }
+    .map_err(|error| format!("Failed to serialize layouts: {}", error))?;
+
+    fs::write(path, content)
+      .map_err(|error| format!("Failed to write layouts {}: {}", path.display(), error))
+  }
+
+  /// Loads a bundle from a `.json` or `.toml` file.
+  pub fn from_file(path: impl AsRef<Path>) -> Result<Self, String> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path)
+      .map_err(|error| format!("Failed to read layouts {}: {}", path.display(), error))?;
+
+    let result = match path.extension().and_then(|extension| extension.to_str()) {
+      Some("json") => Self::from_json(&content),
+      _ => Self::from_toml(&content)
+    };
+    result.map_err(|error| format!("Invalid layouts {}: {}", path.display(), error))
+  }
+
+  pub fn from_toml(content: &str) -> Result<Self, String> {
+    let raw: RawLayouts = toml::from_str(content).map_err(|error| error.to_string())?;
+    Self::validate(raw)
+  }
+
+  pub fn from_json(content: &str) -> Result<Self, String> {
+    let raw: RawLayouts =
+      serde_json::from_str(content).map_err(|error| error.to_string())?;
+    Self::validate(raw)
+  }
+
+  fn validate(raw: RawLayouts) -> Result<Self, String> {
+    if raw.version != Self::SUPPORTED_VERSION {
+      return Err(format!(
+        "unsupported version {}, expected {}",
+        raw.version,
+        Self::SUPPORTED_VERSION
+      ));
+    }
+
+    let mut errors = vec![];
+    let mut profiles = BTreeMap::new();
+
+    for (id, layout) in raw.layouts {
+      let mut ratio = |value: &str| {
+        match parse_aspect_ratio(value) {
+          Ok(ratio) => Some(ratio),
+          Err(error) => {
+            errors.push(format!("layout {}: {}", id, error));
+            None
+          }
+        }
+      };
+      let aspect_ratio = ratio(&layout.aspect_ratio).unwrap_or((16, 9));
+      let min_aspect = layout.min_aspect.as_deref().and_then(&mut ratio);
+      let max_aspect = layout.max_aspect.as_deref().and_then(&mut ratio);
+      if let (Some(min), Some(max)) = (min_aspect, max_aspect) {
+        if aspect(min) > aspect(max) {
+          errors.push(format!("layout {}: min_aspect is wider than max_aspect", id));
+        }
+      }
+
+      let mut regions = BTreeMap::new();
+      let mut seen = HashSet::new();
+      for (name, rect) in layout.regions {
+        let region = match Region::try_from(&name as &str) {
+          Ok(region) => region,
+          Err(error) => {
+            errors.push(format!("layout {}: {}", id, error));
+            continue;
+          }
+        };
+        let anchor = match Anchor::try_from(&rect.anchor as &str) {
+          Ok(anchor) => anchor,
+          Err(error) => {
+            errors.push(format!("layout {}, region {}: {}", id, region, error));
+            continue;
+          }
+        };
+        if rect.width == 0 || rect.height == 0 {
+          errors.push(format!("layout {}, region {}: empty region", id, region));
+        }
+        if rect.y < 0 || rect.y as i64 + rect.height as i64 > REFERENCE_HEIGHT as i64 {
+          errors.push(format!(
+            "layout {}, region {}: outside the safe area vertically",
+            id, region
+          ));
+        }
+        seen.insert(region);
+        regions.insert(
+          region,
+          AnchoredRect {
+            anchor,
+            x: rect.x,
+            y: rect.y,
+            width: rect.width,
+            height: rect.height
+          }
+        );
+      }
+      if !seen.contains(&Region::Panel) {
+        errors.push(format!("layout {}: no panel region", id));
+      }
+
+      let mut input_offsets = BTreeMap::new();
+      for (input, offset) in layout.input_offsets {
+        match InputMethod::try_from(&input as &str) {
+          Ok(input) => {
+            input_offsets.insert(input, (offset.x, offset.y));
+          },
+          Err(error) => errors.push(format!("layout {}: {}", id, error))
+        }
+      }
+
+      profiles.insert(
+        id.clone(),
+        LayoutProfile {
+          id,
+          name: layout.name.trim().to_string(),
+          aspect_ratio,
+          min_aspect,
+          max_aspect,
+          regions,
+          input_offsets
+        }
+      );
+    }
+    if profiles.is_empty() {
+      errors.push("no layouts".to_string());
+    }
+
+    if !errors.is_empty() {
+      let list: String = errors.iter().map(|error| format!("\n  {}", error)).collect();
+      return Err(format!("{} errors:{}", errors.len(), list));
+    }
+
+    Ok(Self { version: raw.version, profiles })
+  }
+
+  pub fn get(&self, id: &str) -> Result<&LayoutProfile, String> {
+    self.profiles.get(id).ok_or(format!("Unknown layout {}", id))
+  }
+
+  /// Picks the profile whose aspect ratio is closest to that of an image of
+  /// the given size.
+  pub fn select(&self, width: u32, height: u32) -> &LayoutProfile {
+    let image_aspect = (width as f64 / height.max(1) as f64).ln();
+    self
+      .profiles
+      .values()
+      .min_by(|a, b| {
+        let distance = |profile: &LayoutProfile| {
+          (aspect(profile.aspect_ratio).ln() - image_aspect).abs()
+        };
+        distance(a).total_cmp(&distance(b))
+      })
+      .expect("layouts are never empty")
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const RATIOS: &str = r#"
+    version = 1
+
+    [layouts.16_9]
+    name = "16:9"
+    aspect_ratio = "16:9"
+    regions.panel = { anchor = "right", x = -560, y = 140, width = 500, height = 760 }
+
+    [layouts.16_10]
+    name = "16:10"
+    aspect_ratio = "16:10"
+    regions.panel = { anchor = "right", x = -560, y = 200, width = 500, height = 760 }
+
+    [layouts.21_9]
+    name = "21:9"
+    aspect_ratio = "64:27"
+    regions.panel = { anchor = "right", x = -600, y = 140, width = 500, height = 760 }
+
+    [layouts.4_3]
+    name = "4:3"
+    aspect_ratio = "4:3"
+    regions.panel = { anchor = "right", x = -480, y = 260, width = 440, height = 660 }
+  "#;
+
+  fn rect(x: u32, y: u32, width: u32, height: u32) -> Rect {
+    Rect { x, y, width, height }
+  }
+
+  #[test]
+  fn selects_the_closest_aspect_ratio() {
+    let layouts = Layouts::from_toml(RATIOS).unwrap();
+
+    for (width, height, id) in [
+      (1920, 1080, "16_9"),
+      (2560, 1440, "16_9"),
+      (1920, 1200, "16_10"),
+      (1680, 1050, "16_10"),
+      (2560, 1080, "21_9"),
+      (3440, 1440, "21_9"),
+      (1024, 768, "4_3"),
+      (1600, 1200, "4_3")
+    ] {
+      assert_eq!(layouts.select(width, height).id, id, "{}x{}", width, height);
+    }
+  }
+
+  #[test]
+  fn lays_out_other_ratios_with_the_bundled_profile() {
+    let layouts = Layouts::bundled();
+    let profile = layouts.select(1920, 1080);
+
+    for (width, height) in [(1920, 1200), (2560, 1080), (1024, 768)] {
+      assert_eq!(layouts.select(width, height).id, profile.id);
+    }
+
+    // Letterboxed to 1920x1080, 60 pixels down
+    assert_eq!(profile.safe_area(1920, 1200), rect(0, 60, 1920, 1080));
+    assert_eq!(
+      profile.region(Region::Panel, 1920, 1200),
+      Some(rect(1360, 200, 500, 760))
+    );
+    // Full width, the panel docked to the right edge
+    assert_eq!(profile.safe_area(2560, 1080), rect(0, 0, 2560, 1080));
+    assert_eq!(
+      profile.region(Region::Panel, 2560, 1080),
+      Some(rect(2000, 140, 500, 760))
+    );
+    assert_eq!(
+      profile.region(Region::Prompts, 2560, 1080),
+      Some(rect(1600, 1000, 960, 80))
+    );
+    // Letterboxed to 1024x576, 96 pixels down, at 8/15 of the reference scale
+    assert_eq!(profile.safe_area(1024, 768), rect(0, 96, 1024, 576));
+    assert_eq!(profile.region(Region::Panel, 1024, 768), Some(rect(725, 171, 267, 405)));
+  }
+
+  #[test]
+  fn places_regions_from_their_anchor() {
+    let layouts = Layouts::from_toml(
+      r#"
+      version = 1
+
+      [layouts.test]
+      name = "Test"
+      aspect_ratio = "16:9"
+      min_aspect = "4:3"
+      max_aspect = "16:9"
+      regions.panel = { anchor = "left", x = 100, y = 100, width = 200, height = 100 }
+      regions.slot = { anchor = "center", x = -50, y = 0, width = 100, height = 50 }
+      regions.prompts = { anchor = "right", x = -300, y = 1000, width = 400, height = 80 }
+      "#
+    )
+    .unwrap();
+    let profile = layouts.get("test").unwrap();
+
+    // Pillarboxed to 1920x1080, 320 pixels in
+    assert_eq!(profile.safe_area(2560, 1080), rect(320, 0, 1920, 1080));
+    assert_eq!(
+      profile.regions(2560, 1080),
+      vec![
+        (Region::Panel, rect(420, 100, 200, 100)),
+        (Region::Slot, rect(1230, 0, 100, 50)),
+        (Region::Prompts, rect(1940, 1000, 400, 80))
+      ]
+    );
+
+    // Scaled by 2 on 3840x2160; the prompts are clipped to the image
+    assert_eq!(
+      profile.regions(3840, 2160),
+      vec![
+        (Region::Panel, rect(200, 200, 400, 200)),
+        (Region::Slot, rect(1820, 0, 200, 100)),
+        (Region::Prompts, rect(3240, 2000, 600, 160))
+      ]
+    );
+
+    // Letterboxed to 4:3 on 5:4, 32 pixels down
+    assert_eq!(profile.safe_area(1280, 1024), rect(0, 32, 1280, 960));
+    assert_eq!(profile.region(Region::Slot, 1280, 1024), Some(rect(596, 32, 89, 44)));
+  }
+}
@@ -0,0 +1,10 @@
+pub mod calibrate;
+pub mod input;
+pub mod language;
+pub mod layout;
+pub mod panel;
+pub mod profile;
+pub mod recognize;
+pub mod scanner;
+pub mod screenshot;
+pub mod template;
@@ -0,0 +1,304 @@
+use {
+  crate::scan::layout::{LayoutProfile, Rect, Region, REFERENCE_HEIGHT},
+  image::{imageops::FilterType, GrayImage}
+};
+
+/// How much stronger the edges along the panel's frame must be than the
+/// average edge of the screenshot for the frame to count as found.
+const MIN_CONTRAST: f32 = 3.0;
+
+/// Screenshots are searched at most this high, to keep the search fast on
+/// large screens.
+const SEARCH_HEIGHT: u32 = 1080;
+
+/// Panel sizes tried, relative to the size the layout expects.
+const SCALES: [f64; 9] = [0.8, 0.85, 0.9, 0.95, 1.0, 1.05, 1.1, 1.15, 1.2];
+
+/// Where the frame of the disk panel was found in a screenshot.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PanelLocation {
+  pub rect: Rect,
+  /// Mean edge strength along the frame divided by that of the whole image.
+  pub contrast: f32
+}
+
+/// Prefix sums of edge strength along rows, for horizontal edges, and along
+/// columns, for vertical edges, so the edges along a line take constant time.
+struct EdgeSums {
+  width: usize,
+  height: usize,
+  rows: Vec<u32>,
+  columns: Vec<u32>,
+  mean: f32
+}
+
+impl EdgeSums {
+  fn new(image: &GrayImage) -> Self {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let pixel = |x: usize, y: usize| image.as_raw()[y * width + x] as i32;
+    let difference = |a: i32, b: i32| (a - b).unsigned_abs();
+
+    let mut rows = vec![0; (width + 1) * height];
+    let mut columns = vec![0; (height + 1) * width];
+    let mut total = 0u64;
+
+    for y in 0 .. height {
+      for x in 0 .. width {
+        let value = pixel(x, y);
+        let horizontal = (if y > 0 { difference(value, pixel(x, y - 1)) } else { 0 })
+          .max(if y + 1 < height { difference(value, pixel(x, y + 1)) } else { 0 });
+        let vertical = (if x > 0 { difference(value, pixel(x - 1, y)) } else { 0 })
+          .max(if x + 1 < width { difference(value, pixel(x + 1, y)) } else { 0 });
+
+        rows[y * (width + 1) + x + 1] = rows[y * (width + 1) + x] + horizontal;
+        columns[x * (height + 1) + y + 1] = columns[x * (height + 1) + y] + vertical;
+        total += (horizontal + vertical) as u64;
+      }
+    }
+
+    let mean = total as f32 / (2 * width * height).max(1) as f32;
+    Self { width, height, rows, columns, mean }
+  }
+
+  fn row(&self, y: usize, x: usize, length: usize) -> u32 {
+    let start = y * (self.width + 1) + x;
+    self.rows[start + length] - self.rows[start]
+  }
+
+  fn column(&self, x: usize, y: usize, length: usize) -> u32 {
+    let start = x * (self.height + 1) + y;
+    self.columns[start + length] - self.columns[start]
+  }
+
+  /// Mean edge strength along the border of a rectangle.
+  fn border(&self, x: usize, y: usize, w: usize, h: usize) -> f32 {
+    let sum = self.row(y, x, w)
+      + self.row(y + h - 1, x, w)
+      + self.column(x, y, h)
+      + self.column(x + w - 1, y, h);
+    sum as f32 / (2 * (w + h)) as f32
+  }
+}
+
+/// Searches a screenshot for the frame of the disk panel, trying sizes around
+/// the panel size of the layout, and returns the rectangle whose border has
+/// the strongest edges. Returns `None` if no frame stands out from the rest
+/// of the image.
+pub fn locate_panel(image: &GrayImage, layout: &LayoutProfile) -> Option<PanelLocation> {
+  let panel = layout.regions.get(&Region::Panel)?;
+
+  let downscale = (image.height() as f64 / SEARCH_HEIGHT as f64).max(1.0);
+  let search = if downscale > 1.0 {
+    image::imageops::resize(
+      image,
+      (image.width() as f64 / downscale).round() as u32,
+      (image.height() as f64 / downscale).round() as u32,
+      FilterType::Triangle
+    )
+  } else {
+    image.clone()
+  };
+
+  let edges = EdgeSums::new(&search);
+  let safe_area = layout.safe_area(image.width(), image.height());
+  let base_scale = safe_area.height as f64 / REFERENCE_HEIGHT as f64 / downscale;
+
+  let mut best: Option<(f32, usize, usize, usize, usize)> = None;
+  for scale in SCALES {
+    let w = (panel.width as f64 * base_scale * scale).round() as usize;
+    let h = (panel.height as f64 * base_scale * scale).round() as usize;
+    if w < 2 || h < 2 || w > edges.width || h > edges.height {
+      continue;
+    }
+
+    for y in 0 ..= edges.height - h {
+      for x in 0 ..= edges.width - w {
+        let score = edges.border(x, y, w, h);
+        if best.is_none_or(|(best_score, ..)| score > best_score) {
+          best = Some((score, x, y, w, h));
+        }
+      }
+    }
+  }
+
+  let (score, x, y, w, h) = best?;
+  let contrast = if edges.mean > 0.0 { score / edges.mean } else { 0.0 };
+  if contrast < MIN_CONTRAST {
+    return None;
+  }
+
+  let rect = Rect {
+    x: (x as f64 * downscale).round() as u32,
+    y: (y as f64 * downscale).round() as u32,
+    width: ((w as f64 * downscale).round() as u32).min(image.width()),
+    height: ((h as f64 * downscale).round() as u32).min(image.height())
+  };
+  Some(PanelLocation { rect, contrast })
+}
+
+impl LayoutProfile {
+  /// Returns where `region` is in an image of the given size when the panel
+  /// was found at `panel`, keeping the region's position relative to where
+  /// the layout puts the panel. The prompts region doesn't move with the
+  /// panel and is never returned.
+  pub fn region_in_panel(
+    &self,
+    region: Region,
+    panel: Rect,
+    width: u32,
+    height: u32
+  ) -> Option<Rect> {
+    if region == Region::Prompts {
+      return None;
+    }
+    let expected_panel = self.region(Region::Panel, width, height)?;
+    let expected = self.region(region, width, height)?;
+
+    let scale_x = panel.width as f64 / expected_panel.width as f64;
+    let scale_y = panel.height as f64 / expected_panel.height as f64;
+    let left = panel.x as f64 + (expected.x as f64 - expected_panel.x as f64) * scale_x;
+    let top = panel.y as f64 + (expected.y as f64 - expected_panel.y as f64) * scale_y;
+    let right = left + expected.width as f64 * scale_x;
+    let bottom = top + expected.height as f64 * scale_y;
+
+    let (left, top) = (left.round().max(0.0), top.round().max(0.0));
+    let (right, bottom) =
+      (right.round().min(width as f64), bottom.round().min(height as f64));
+    if right <= left || bottom <= top {
+      return None;
+    }
+
+    Some(Rect {
+      x: left as u32,
+      y: top as u32,
+      width: (right - left) as u32,
+      height: (bottom - top) as u32
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    crate::scan::{
+      input::{InputMethod, PromptTemplates},
+      layout::Layouts,
+      screenshot::ScreenshotLayout
+    },
+    image::Luma
+  };
+
+  // A dark screenshot with a light frame `thickness` pixels wide around
+  // `frame`, and a few light blocks standing for text inside it.
+  fn screenshot(width: u32, height: u32, frame: Rect, thickness: u32) -> GrayImage {
+    let mut image = GrayImage::from_pixel(width, height, Luma([40]));
+    for y in frame.y .. frame.y + frame.height {
+      for x in frame.x .. frame.x + frame.width {
+        let (dx, dy) = (x - frame.x, y - frame.y);
+        let on_frame = dx < thickness
+          || dy < thickness
+          || dx >= frame.width - thickness
+          || dy >= frame.height - thickness;
+        let text = (dx / 10) % 3 == 1 && (dy / 20) % 4 == 2 && dx > 20;
+        if on_frame || text {
+          image.put_pixel(x, y, Luma([if on_frame { 210 } else { 150 }]));
+        }
+      }
+    }
+    image
+  }
+
+  fn rect(x: u32, y: u32, width: u32, height: u32) -> Rect {
+    Rect { x, y, width, height }
+  }
+
+  #[test]
+  fn finds_a_moved_and_scaled_panel() {
+    let layouts = Layouts::bundled();
+    let layout = layouts.select(1920, 1080);
+    assert_eq!(layout.region(Region::Panel, 1920, 1080), Some(rect(1360, 140, 500, 760)));
+
+    // 1.1 times the expected size, 60 pixels left of and 20 above where the
+    // layout expects it
+    let frame = rect(1300, 120, 550, 836);
+    let location = locate_panel(&screenshot(1920, 1080, frame, 2), layout).unwrap();
+    assert_eq!(location.rect, frame);
+    assert!(location.contrast >= MIN_CONTRAST, "{}", location.contrast);
+  }
+
+  #[test]
+  fn maps_regions_into_the_found_panel() {
+    let layouts = Layouts::bundled();
+    let layout = layouts.select(1920, 1080);
+    let frame = rect(1300, 120, 550, 836);
+
+    assert_eq!(
+      layout.region(Region::SetName, 1920, 1080),
+      Some(rect(1390, 170, 400, 40))
+    );
+    assert_eq!(
+      layout.region_in_panel(Region::SetName, frame, 1920, 1080),
+      Some(rect(1333, 153, 440, 44))
+    );
+    assert_eq!(
+      layout.region_in_panel(Region::SecondaryValue(4), frame, 1920, 1080),
+      Some(rect(1674, 626, 176, 40))
+    );
+    assert_eq!(layout.region_in_panel(Region::Prompts, frame, 1920, 1080), None);
+    // Where the layout expects the panel, regions stay where they are
+    let expected = layout.region(Region::Panel, 1920, 1080).unwrap();
+    assert_eq!(
+      layout.region_in_panel(Region::Equipped, expected, 1920, 1080),
+      layout.region(Region::Equipped, 1920, 1080)
+    );
+
+    let image = screenshot(1920, 1080, frame, 2);
+    let screenshot = ScreenshotLayout::new(
+      &image,
+      &layouts,
+      None,
+      Some(InputMethod::KeyboardMouse),
+      &PromptTemplates::default(),
+      true
+    )
+    .unwrap();
+    assert_eq!(screenshot.region(Region::Panel), Some(frame));
+    assert_eq!(screenshot.region(Region::SetName), Some(rect(1333, 153, 440, 44)));
+    assert_eq!(
+      screenshot.region(Region::Prompts),
+      layout.region(Region::Prompts, 1920, 1080)
+    );
+  }
+
+  #[test]
+  fn searches_large_screenshots_downscaled() {
+    let layouts = Layouts::bundled();
+    let layout = layouts.select(3840, 2160);
+    let frame = rect(2640, 300, 1000, 1520);
+
+    let location = locate_panel(&screenshot(3840, 2160, frame, 4), layout).unwrap();
+    let found = location.rect;
+    assert!(
+      found.x.abs_diff(frame.x) <= 2 && found.y.abs_diff(frame.y) <= 2,
+      "{}",
+      found
+    );
+    assert!(found.width.abs_diff(frame.width) <= 2, "{}", found);
+    assert!(found.height.abs_diff(frame.height) <= 2, "{}", found);
+  }
+
+  #[test]
+  fn finds_nothing_without_a_frame() {
+    let layouts = Layouts::bundled();
+    let layout = layouts.select(1920, 1080);
+
+    assert_eq!(
+      locate_panel(&GrayImage::from_pixel(1920, 1080, Luma([40])), layout),
+      None
+    );
+    let noise =
+      GrayImage::from_fn(1920, 1080, |x, y| Luma([((x * 31 + y * 17) % 97) as u8]));
+    assert_eq!(locate_panel(&noise, layout), None);
+  }
+}
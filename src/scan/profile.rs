@@ -0,0 +1,68 @@
+use {
+  serde::{Deserialize, Serialize},
+  std::{
+    fs,
+    path::{Path, PathBuf}
+  }
+};
+
+/// Settings describing how a user's game client is scanned, saved as TOML so
+/// they can be reused between runs.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScanProfile {
+  /// Locale of the game client's text.
+  pub language: String,
+  /// ID of the font profile matching the client's font scheme. When not set,
+  /// every font profile is tried and the most confident reading kept.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub font: Option<String>,
+  /// ID of the layout profile to use. When not set, it is picked from the
+  /// aspect ratio of each screenshot.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub layout: Option<String>,
+  /// Control layout of the client, `keyboard_mouse` or `controller`. When not
+  /// set, it is detected from the button prompts of each screenshot.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub input: Option<String>,
+  /// Database file to save disks to. When not set, the default one in the
+  /// user's data directory is used.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub database: Option<PathBuf>,
+  /// UID of the game account whose inventory the disks belong to. When not
+  /// set, the default account is used.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub account: Option<String>
+}
+
+impl ScanProfile {
+  /// A profile for a client of `language` that detects everything else.
+  pub fn new(language: &str) -> Self {
+    Self {
+      language: language.to_string(),
+      font: None,
+      layout: None,
+      input: None,
+      database: None,
+      account: None
+    }
+  }
+
+  pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path)
+      .map_err(|error| format!("Failed to read profile {}: {}", path.display(), error))?;
+
+    toml::from_str(&content)
+      .map_err(|error| format!("Invalid profile {}: {}", path.display(), error))
+  }
+
+  pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+    let path = path.as_ref();
+    let content = toml::to_string(self)
+      .map_err(|error| format!("Failed to serialize profile: {}", error))?;
+
+    fs::write(path, content)
+      .map_err(|error| format!("Failed to write profile {}: {}", path.display(), error))
+  }
+}
@@ -0,0 +1,259 @@
+use {
+  crate::{
+    disk::disk::EquippedBy,
+    ocr::{
+      engine::{OcrEngine, OcrOptions},
+      font::{recognize_with_fonts, FontProfile}
+    },
+    source::{
+      alias::{AliasTable, WordScope},
+      game_data::{GameData, Language},
+      match_closest::match_closest_word,
+      vocabulary::Vocabulary
+    }
+  },
+  image::GrayImage,
+  levenshtein::levenshtein
+};
+
+/// Characters that may separate the equipped label from the agent name.
+const EQUIPPED_SEPARATORS: &[char] = &[':', '：', '·', '-', '|', ' '];
+
+/// The text read from a word region and the vocabulary word it was matched
+/// to, if any.
+#[derive(Clone, Debug)]
+pub struct RecognizedWord<'a> {
+  pub raw: String,
+  pub word: Option<&'a str>,
+  pub confidence: f32
+}
+
+/// Reads a region holding a single name or UI word of the client of
+/// `language`, shown in `font`, and matches it against the vocabulary.
+pub fn recognize_word<'a>(
+  engine: &dyn OcrEngine,
+  image: &GrayImage,
+  language: &Language,
+  font: &FontProfile,
+  vocabulary: &'a Vocabulary,
+  aliases: &AliasTable,
+  scope: WordScope
+) -> Result<RecognizedWord<'a>, String> {
+  let options = font.apply_options(OcrOptions::for_language(language));
+  let text = engine.recognize(&font.preprocessing.apply(image), &options)?;
+  let raw = language.normalize_text(&text.text);
+  let word = match_closest_word(vocabulary, aliases, scope, &raw);
+
+  Ok(RecognizedWord { raw, word, confidence: text.confidence })
+}
+
+/// Reads a word region with every font profile and keeps the reading with
+/// the highest confidence, preferring readings that matched a word.
+pub fn recognize_word_with_fonts<'a, 'f>(
+  engine: &dyn OcrEngine,
+  image: &GrayImage,
+  language: &Language,
+  fonts: &[&'f FontProfile],
+  vocabulary: &'a Vocabulary,
+  aliases: &AliasTable,
+  scope: WordScope
+) -> Result<(RecognizedWord<'a>, &'f FontProfile), String> {
+  recognize_with_fonts(fonts, |font| {
+    let word = recognize_word(engine, image, language, font, vocabulary, aliases, scope)?;
+    let score = word.confidence + if word.word.is_some() { 1.0 } else { 0.0 };
+    Ok((word, score))
+  })
+}
+
+/// Returns the agent name on an equipped line, or `None` if the line has no
+/// equipped label, as on unequipped disks. The label is found allowing one
+/// misread character.
+pub fn parse_equipped_line(text: &str, language: &Language) -> Option<String> {
+  let label: Vec<char> =
+    language.normalize_text(language.equipped_label.as_ref()?).chars().collect();
+  let text: Vec<char> = language.normalize_text(text).chars().collect();
+  if label.is_empty() || text.len() < label.len() {
+    return None;
+  }
+
+  let max_distance = if label.len() >= 3 { 1 } else { 0 };
+  let label_string: String = label.iter().collect();
+  let end = (0 ..= text.len() - label.len())
+    .map(|start| {
+      let window: String = text[start .. start + label.len()].iter().collect();
+      (start, levenshtein(&window, &label_string))
+    })
+    .filter(|(_, distance)| *distance <= max_distance)
+    .min_by_key(|(_, distance)| *distance)
+    .map(|(start, _)| start + label.len())?;
+
+  let name: String = text[end ..].iter().collect();
+  let name = name.trim_matches(EQUIPPED_SEPARATORS);
+  if name.is_empty() {
+    None
+  } else {
+    Some(name.to_string())
+  }
+}
+
+/// Identifies the agent named on an equipped line of the client of `locale`,
+/// or returns `None` for lines without the equipped label. Names that don't
+/// closely match an agent of the vocabulary are kept as read rather than
+/// failing the scan.
+pub fn parse_equipped_by(
+  text: &str,
+  game_data: &GameData,
+  locale: &str,
+  agents: &Vocabulary,
+  aliases: &AliasTable
+) -> Result<Option<EquippedBy>, String> {
+  let language = game_data.language(locale)?;
+  if language.equipped_label.is_none() {
+    return Err(format!("No equipped label defined for locale {}", locale));
+  }
+
+  let name = match parse_equipped_line(text, language) {
+    Some(name) => name,
+    None => return Ok(None)
+  };
+
+  // The closest agent is always found, so only accept it when the name is
+  // mostly right; short names, down to a single character, must match
+  // exactly
+  let max_distance = name.chars().count() / 3;
+  let matched =
+    match_closest_word(agents, aliases, WordScope::Agent, &name).filter(|agent| {
+      aliases.get(WordScope::Agent, &name).is_some_and(|alias| alias.canonical == *agent)
+        || levenshtein(agent, &name) <= max_distance
+    });
+
+  Ok(Some(match matched {
+    Some(agent) => EquippedBy::from_name(game_data, locale, agent),
+    None => EquippedBy::Unknown(name)
+  }))
+}
+
+/// The text read from the equipped line and the agent it names, if any.
+#[derive(Clone, Debug)]
+pub struct RecognizedEquipped {
+  pub raw: String,
+  pub equipped_by: Option<EquippedBy>,
+  pub confidence: f32
+}
+
+/// Reads the equipped line of a disk of the client of `locale`, shown in
+/// `font`, and identifies the agent with `parse_equipped_by`.
+pub fn recognize_equipped_by(
+  engine: &dyn OcrEngine,
+  image: &GrayImage,
+  game_data: &GameData,
+  locale: &str,
+  font: &FontProfile,
+  agents: &Vocabulary,
+  aliases: &AliasTable
+) -> Result<RecognizedEquipped, String> {
+  let language = game_data.language(locale)?;
+  let options = font.apply_options(OcrOptions::for_language(language));
+  let text = engine.recognize(&font.preprocessing.apply(image), &options)?;
+  let raw = language.normalize_text(&text.text);
+  let equipped_by = parse_equipped_by(&raw, game_data, locale, agents, aliases)?;
+
+  Ok(RecognizedEquipped { raw, equipped_by, confidence: text.confidence })
+}
+
+/// Reads the equipped line with every font profile and keeps the reading
+/// with the highest confidence, preferring readings that named a known agent.
+#[allow(clippy::too_many_arguments)]
+pub fn recognize_equipped_by_with_fonts<'f>(
+  engine: &dyn OcrEngine,
+  image: &GrayImage,
+  game_data: &GameData,
+  locale: &str,
+  fonts: &[&'f FontProfile],
+  agents: &Vocabulary,
+  aliases: &AliasTable
+) -> Result<(RecognizedEquipped, &'f FontProfile), String> {
+  recognize_with_fonts(fonts, |font| {
+    let equipped =
+      recognize_equipped_by(engine, image, game_data, locale, font, agents, aliases)?;
+    let known = matches!(equipped.equipped_by, Some(EquippedBy::Agent(_)));
+    let score = equipped.confidence + if known { 1.0 } else { 0.0 };
+    Ok((equipped, score))
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use {super::*, crate::source::alias::Alias};
+
+  fn parse(text: &str, aliases: &AliasTable) -> Option<EquippedBy> {
+    let game_data = GameData::bundled();
+    let agents = Vocabulary::agents_from_game_data(&game_data, "zh-CN");
+    parse_equipped_by(text, &game_data, "zh-CN", &agents, aliases).unwrap()
+  }
+
+  fn agent(id: &str) -> Option<EquippedBy> {
+    Some(EquippedBy::Agent(id.to_string()))
+  }
+
+  #[test]
+  fn finds_the_name_after_the_equipped_label() {
+    let game_data = GameData::bundled();
+    let zh = game_data.language("zh-CN").unwrap();
+    let en = game_data.language("en").unwrap();
+
+    assert_eq!(parse_equipped_line("装备中：安比", zh).as_deref(), Some("安比"));
+    assert_eq!(parse_equipped_line("装备中 · 猫又", zh).as_deref(), Some("猫又"));
+    assert_eq!(parse_equipped_line("|装备中:「11号」", zh).as_deref(), Some("「11号」"));
+    // One misread character of the label is allowed
+    assert_eq!(parse_equipped_line("装备巾：比利", zh).as_deref(), Some("比利"));
+    assert_eq!(
+      parse_equipped_line("Equipped: Soldier 11", en).as_deref(),
+      Some("Soldier 11")
+    );
+    assert_eq!(parse_equipped_line("Equlpped: Anby", en).as_deref(), Some("Anby"));
+  }
+
+  #[test]
+  fn finds_no_name_on_unequipped_disks() {
+    let game_data = GameData::bundled();
+    let zh = game_data.language("zh-CN").unwrap();
+
+    assert_eq!(parse_equipped_line("", zh), None);
+    assert_eq!(parse_equipped_line("混沌爵士", zh), None);
+    assert_eq!(parse_equipped_line("装备中：", zh), None);
+    assert_eq!(parse_equipped_line("装甲巾：安比", zh), None);
+    assert_eq!(parse(" ", &AliasTable::new()), None);
+  }
+
+  #[test]
+  fn identifies_the_agent() {
+    let aliases = AliasTable::new();
+
+    assert_eq!(parse("装备中：安比", &aliases), agent("anby"));
+    assert_eq!(parse("装备中：珂蕾妲", &aliases), agent("koleda"));
+    // A misread character in a name of three or more
+    assert_eq!(parse("装备中：珂雷妲", &aliases), agent("koleda"));
+    assert_eq!(parse("装备中：本", &aliases), agent("ben"));
+  }
+
+  #[test]
+  fn keeps_unknown_names_as_read() {
+    let unknown = |name: &str| Some(EquippedBy::Unknown(name.to_string()));
+    let mut aliases = AliasTable::new();
+
+    assert_eq!(parse("装备中：路人甲乙", &aliases), unknown("路人甲乙"));
+    // Short names must match exactly
+    assert_eq!(parse("装备中：安t", &aliases), unknown("安t"));
+    assert_eq!(parse("装备中：木", &aliases), unknown("木"));
+
+    aliases.insert(Alias {
+      locale: "zh-CN".to_string(),
+      scope: WordScope::Agent,
+      raw: "木".to_string(),
+      canonical: "本".to_string(),
+      updated_at: 0
+    });
+    assert_eq!(parse("装备中：木", &aliases), agent("ben"));
+  }
+}
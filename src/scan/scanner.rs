@@ -0,0 +1,487 @@
+use {
+  crate::{
+    database::provenance::RegionReading,
+    disk::disk::{Affix, AffixValueType, Disk, EquippedBy},
+    ocr::{
+      engine::OcrEngine,
+      font::FontProfile,
+      numeric::{recognize_value_with_fonts, select_value_candidate, RecognizedValue}
+    },
+    scan::{
+      layout::Region,
+      recognize::{
+        recognize_equipped_by_with_fonts,
+        recognize_word_with_fonts,
+        RecognizedWord
+      },
+      screenshot::ScreenshotLayout
+    },
+    source::{
+      alias::{AliasTable, WordScope},
+      game_data::{GameData, Language, Stat},
+      vocabulary::Vocabulary
+    }
+  },
+  image::GrayImage
+};
+
+/// A disk read from a screenshot, with what was read from each region.
+#[derive(Clone, Debug)]
+pub struct ScannedDisk {
+  pub disk: Disk,
+  /// The readings in the order the regions were read.
+  pub readings: Vec<RegionReading>
+}
+
+/// Reads disks from screenshots of the disk panel of the client of one
+/// locale, trying each of its font profiles.
+pub struct DiskScanner<'a> {
+  engine: &'a dyn OcrEngine,
+  game_data: &'a GameData,
+  locale: &'a str,
+  language: &'a Language,
+  fonts: Vec<&'a FontProfile>,
+  vocabulary: Vocabulary,
+  agents: Vocabulary,
+  aliases: &'a AliasTable,
+  keep_crops: bool
+}
+
+impl<'a> DiskScanner<'a> {
+  pub fn new(
+    engine: &'a dyn OcrEngine,
+    game_data: &'a GameData,
+    locale: &'a str,
+    fonts: Vec<&'a FontProfile>,
+    aliases: &'a AliasTable
+  ) -> Result<Self, String> {
+    Ok(Self {
+      engine,
+      game_data,
+      locale,
+      language: game_data.language(locale)?,
+      fonts,
+      vocabulary: Vocabulary::from_game_data(game_data, locale),
+      agents: Vocabulary::agents_from_game_data(game_data, locale),
+      aliases,
+      keep_crops: false
+    })
+  }
+
+  /// Keeps the crop of each region with its reading.
+  pub fn with_crops(mut self, keep_crops: bool) -> Self {
+    self.keep_crops = keep_crops;
+    self
+  }
+
+  fn push_reading(
+    &self,
+    readings: &mut Vec<RegionReading>,
+    reading: RegionReading,
+    crop: &GrayImage
+  ) -> Result<(), String> {
+    readings.push(if self.keep_crops { reading.with_crop(crop)? } else { reading });
+    Ok(())
+  }
+
+  fn crop(
+    image: &GrayImage,
+    layout: &ScreenshotLayout,
+    region: Region
+  ) -> Result<GrayImage, String> {
+    layout
+      .crop(image, region)
+      .ok_or(format!("Layout {} has no {} region", layout.profile.id, region))
+  }
+
+  fn read_word(
+    &self,
+    image: &GrayImage,
+    layout: &ScreenshotLayout,
+    region: Region,
+    scope: WordScope,
+    readings: &mut Vec<RegionReading>
+  ) -> Result<RecognizedWord<'_>, String> {
+    let crop = Self::crop(image, layout, region)?;
+    let (word, _) = recognize_word_with_fonts(
+      self.engine,
+      &crop,
+      self.language,
+      &self.fonts,
+      &self.vocabulary,
+      self.aliases,
+      scope
+    )?;
+    self.push_reading(readings, RegionReading::from_word(region, &word), &crop)?;
+    Ok(word)
+  }
+
+  fn read_value(
+    &self,
+    image: &GrayImage,
+    layout: &ScreenshotLayout,
+    region: Region,
+    allowed: &[AffixValueType],
+    readings: &mut Vec<RegionReading>
+  ) -> Result<RecognizedValue, String> {
+    let crop = Self::crop(image, layout, region)?;
+    let (value, _) = recognize_value_with_fonts(
+      self.engine,
+      &crop,
+      self.language,
+      &self.fonts,
+      Some(allowed)
+    )?;
+    self.push_reading(readings, RegionReading::from_value(region, &value), &crop)?;
+    Ok(value)
+  }
+
+  /// Reads the agent the disk is equipped on, or `None` for unequipped disks
+  /// and clients without an equipped label.
+  fn read_equipped_by(
+    &self,
+    image: &GrayImage,
+    layout: &ScreenshotLayout,
+    readings: &mut Vec<RegionReading>
+  ) -> Result<Option<EquippedBy>, String> {
+    if self.language.equipped_label.is_none() || layout.region(Region::Equipped).is_none()
+    {
+      return Ok(None);
+    }
+
+    let crop = Self::crop(image, layout, Region::Equipped)?;
+    let (equipped, _) = recognize_equipped_by_with_fonts(
+      self.engine,
+      &crop,
+      self.game_data,
+      self.locale,
+      &self.fonts,
+      &self.agents,
+      self.aliases
+    )?;
+    self.push_reading(
+      readings,
+      RegionReading::from_equipped(Region::Equipped, &equipped),
+      &crop
+    )?;
+    Ok(equipped.equipped_by)
+  }
+
+  /// Reads the stat name of `region`, or `None` when the region is blank.
+  fn read_stat(
+    &self,
+    image: &GrayImage,
+    layout: &ScreenshotLayout,
+    region: Region,
+    readings: &mut Vec<RegionReading>
+  ) -> Result<Option<&'a Stat>, String> {
+    let word = self.read_word(image, layout, region, WordScope::Affix, readings)?;
+    if word.raw.is_empty() {
+      return Ok(None);
+    }
+
+    match word.word.and_then(|word| self.game_data.find_stat(self.locale, word)) {
+      Some(stat) => Ok(Some(stat)),
+      None => Err(format!("Unrecognized stat in {}: {}", region, word.raw))
+    }
+  }
+
+  /// Reads the affix of `stat` from the value in `region`, with one of the
+  /// `allowed` value types.
+  fn read_affix(
+    &self,
+    image: &GrayImage,
+    layout: &ScreenshotLayout,
+    region: Region,
+    stat: &Stat,
+    allowed: &[AffixValueType],
+    readings: &mut Vec<RegionReading>
+  ) -> Result<Affix, String> {
+    let value = self.read_value(image, layout, region, allowed, readings)?;
+    match select_value_candidate(&value.candidates, allowed) {
+      Some(candidate) => {
+        Affix::new(self.game_data, &stat.id, candidate.value, candidate.value_type)
+      },
+      None => {
+        Err(format!("Unrecognized value of {} in {}: {}", stat.id, region, value.raw))
+      },
+    }
+  }
+
+  /// Reads the disk shown in `image`, laid out by `layout`, with the agent it
+  /// is equipped on. The rarity, level and marks aren't read.
+  pub fn read(
+    &self,
+    image: &GrayImage,
+    layout: &ScreenshotLayout
+  ) -> Result<ScannedDisk, String> {
+    let mut readings = vec![];
+    let set =
+      self.read_word(image, layout, Region::SetName, WordScope::Set, &mut readings)?;
+    let set = match set.word.and_then(|word| self.game_data.find_set(self.locale, word)) {
+      Some(set) => set,
+      None => return Err(format!("Unrecognized set: {}", set.raw))
+    };
+
+    let slot = self.read_value(
+      image,
+      layout,
+      Region::Slot,
+      &[AffixValueType::Flat],
+      &mut readings
+    )?;
+    let slot = match slot.candidates.iter().find(|candidate| {
+      candidate.value.fract() == 0.0 && (1.0 ..= 6.0).contains(&candidate.value)
+    }) {
+      Some(candidate) => candidate.value as u8,
+      None => return Err(format!("Unrecognized slot: {}", slot.raw))
+    };
+
+    let primary_stat = self
+      .read_stat(image, layout, Region::PrimaryStat, &mut readings)?
+      .ok_or("No primary stat read".to_string())?;
+    // Only the value types the slot allows for the stat, which tells flat
+    // from percentage primary affixes of the same stat apart
+    let allowed: Vec<_> = self
+      .game_data
+      .slot(slot)
+      .into_iter()
+      .flat_map(|rule| rule.primary.iter())
+      .filter(|(stat, _)| *stat == primary_stat.id)
+      .map(|&(_, value_type)| value_type)
+      .collect();
+    let primary_affix = self.read_affix(
+      image,
+      layout,
+      Region::PrimaryValue,
+      primary_stat,
+      &allowed,
+      &mut readings
+    )?;
+
+    let mut secondary_affixes = [None, None, None, None];
+    for (index, affix) in secondary_affixes.iter_mut().enumerate() {
+      let position = index as u8 + 1;
+      if layout.region(Region::SecondaryStat(position)).is_none() {
+        continue;
+      }
+      if let Some(stat) =
+        self.read_stat(image, layout, Region::SecondaryStat(position), &mut readings)?
+      {
+        *affix = Some(self.read_affix(
+          image,
+          layout,
+          Region::SecondaryValue(position),
+          stat,
+          &stat.value_types,
+          &mut readings
+        )?);
+      }
+    }
+
+    let equipped_by = self.read_equipped_by(image, layout, &mut readings)?;
+
+    let [affix_1, affix_2, affix_3, affix_4] = secondary_affixes;
+    let disk = Disk::new(
+      self.game_data,
+      &set.id,
+      slot,
+      primary_affix,
+      affix_1,
+      affix_2,
+      affix_3,
+      affix_4
+    )?
+    .with_equipped_by(equipped_by);
+    Ok(ScannedDisk { disk, readings })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    crate::{
+      ocr::engine::{OcrOptions, OcrText},
+      scan::{
+        input::{InputMethod, PromptTemplates},
+        layout::Layouts
+      }
+    },
+    std::{cell::RefCell, collections::VecDeque}
+  };
+
+  /// Answers each recognition with the next of fixed texts, which follow the
+  /// order the regions are read in.
+  struct StubEngine {
+    texts: RefCell<VecDeque<&'static str>>
+  }
+
+  impl StubEngine {
+    fn new(texts: &[&'static str]) -> Self {
+      Self { texts: RefCell::new(texts.iter().copied().collect()) }
+    }
+  }
+
+  impl OcrEngine for StubEngine {
+    fn recognize(&self, _: &GrayImage, _: &OcrOptions) -> Result<OcrText, String> {
+      match self.texts.borrow_mut().pop_front() {
+        Some(text) => Ok(OcrText { text: text.to_string(), confidence: 0.9 }),
+        None => Err("Read more regions than expected".to_string())
+      }
+    }
+  }
+
+  fn scan_with_crops(
+    texts: &[&'static str],
+    keep_crops: bool
+  ) -> Result<ScannedDisk, String> {
+    let game_data = GameData::bundled();
+    let font = FontProfile::default();
+    let aliases = AliasTable::new();
+    let engine = StubEngine::new(texts);
+    let scanner = DiskScanner::new(&engine, &game_data, "zh-CN", vec![&font], &aliases)?
+      .with_crops(keep_crops);
+
+    let image = GrayImage::new(1920, 1080);
+    let layout = ScreenshotLayout::new(
+      &image,
+      &Layouts::bundled(),
+      None,
+      Some(InputMethod::KeyboardMouse),
+      &PromptTemplates::default(),
+      false
+    )?;
+    let scanned = scanner.read(&image, &layout);
+    assert!(engine.texts.borrow().is_empty(), "not every text was read");
+    scanned
+  }
+
+  fn scan(texts: &[&'static str]) -> Result<Disk, String> {
+    scan_with_crops(texts, false).map(|scanned| scanned.disk)
+  }
+
+  #[test]
+  fn reads_every_region_of_the_panel() {
+    let disk = scan(&[
+      "混沌爵土",
+      "4",
+      "暴击率",
+      "24%",
+      "攻击力",
+      "19",
+      "暴击伤害",
+      "9.6%",
+      "生命值",
+      "3.0%",
+      "",
+      "装备中：安比"
+    ])
+    .unwrap();
+    let game_data = GameData::bundled();
+
+    assert_eq!(disk.set, "chaos_jazz");
+    assert_eq!(disk.slot, 4);
+    assert_eq!(
+      disk.primary_affix,
+      Affix::new(&game_data, "crit_rate", 24.0, AffixValueType::Percentage).unwrap()
+    );
+    assert_eq!(
+      disk.secondary_affixes,
+      [
+        Some(Affix::new(&game_data, "atk", 19.0, AffixValueType::Flat).unwrap()),
+        Some(
+          Affix::new(&game_data, "crit_dmg", 9.6, AffixValueType::Percentage).unwrap()
+        ),
+        Some(Affix::new(&game_data, "hp", 3.0, AffixValueType::Percentage).unwrap()),
+        None
+      ]
+    );
+    assert_eq!(disk.equipped_by, Some(EquippedBy::Agent("anby".to_string())));
+  }
+
+  #[test]
+  fn reads_the_primary_value_type_the_slot_allows() {
+    let disk = scan(&["混沌爵士", "1", "生命值", "2200", "", "", "", "", ""]).unwrap();
+
+    assert_eq!(disk.slot, 1);
+    assert_eq!(disk.primary_affix.value, 2200.0);
+    assert_eq!(disk.primary_affix.value_type, AffixValueType::Flat);
+    assert!(disk.secondary_affixes.iter().all(Option::is_none));
+    assert_eq!(disk.equipped_by, None);
+  }
+
+  #[test]
+  fn records_what_was_read_from_each_region() {
+    let scanned = scan_with_crops(
+      &[
+        "混沌爵土",
+        "1",
+        "生命值",
+        "2200",
+        "暴击率",
+        "4.896",
+        "",
+        "",
+        "",
+        "装备中：路人甲"
+      ],
+      true
+    )
+    .unwrap();
+    let readings: Vec<_> = scanned
+      .readings
+      .iter()
+      .map(|reading| {
+        (
+          reading.region,
+          reading.raw.as_str(),
+          reading.matched.as_deref(),
+          reading.correction.as_deref()
+        )
+      })
+      .collect();
+
+    assert_eq!(
+      readings,
+      [
+        (Region::SetName, "混沌爵土", Some("混沌爵士"), Some("混沌爵土 → 混沌爵士")),
+        (Region::Slot, "1", Some("1 (flat)"), None),
+        (Region::PrimaryStat, "生命值", Some("生命值"), None),
+        (Region::PrimaryValue, "2200", Some("2200 (flat)"), None),
+        (Region::SecondaryStat(1), "暴击率", Some("暴击率"), None),
+        (
+          Region::SecondaryValue(1),
+          "4.896",
+          Some("4.8 (percentage)"),
+          Some("1 corrections")
+        ),
+        (Region::SecondaryStat(2), "", None, None),
+        (Region::SecondaryStat(3), "", None, None),
+        (Region::SecondaryStat(4), "", None, None),
+        (Region::Equipped, "装备中：路人甲", None, None)
+      ]
+    );
+    // Unknown agents are kept as read
+    assert_eq!(scanned.disk.equipped_by, Some(EquippedBy::Unknown("路人甲".to_string())));
+    assert!(scanned.readings.iter().all(|reading| reading.crop.is_some()));
+    assert!(scan_with_crops(
+      &["混沌爵士", "1", "生命值", "2200", "", "", "", "", ""],
+      false
+    )
+    .unwrap()
+    .readings
+    .iter()
+    .all(|reading| reading.crop.is_none()));
+  }
+
+  #[test]
+  fn fails_on_unreadable_regions() {
+    assert_eq!(scan(&[""]).unwrap_err(), "Unrecognized set: ");
+    assert_eq!(scan(&["混沌爵士", "9"]).unwrap_err(), "Unrecognized slot: 9");
+    assert_eq!(
+      scan(&["混沌爵士", "1", "生命值", "--"]).unwrap_err(),
+      "Unrecognized value of hp in primary_value: --"
+    );
+  }
+}
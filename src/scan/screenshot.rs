@@ -0,0 +1,88 @@
+use {
+  crate::scan::{
+    input::{detect_input_method, InputDetection, InputMethod, PromptTemplates},
+    layout::{LayoutProfile, Layouts, Rect, Region},
+    panel::{locate_panel, PanelLocation}
+  },
+  image::GrayImage
+};
+
+/// Where the regions of one screenshot are: the layout profile for its
+/// aspect ratio with the panel regions moved for its input method, or
+/// relative to the panel when it was located.
+#[derive(Clone, Debug)]
+pub struct ScreenshotLayout {
+  pub profile: LayoutProfile,
+  pub input: InputMethod,
+  /// Set when the input method was detected rather than given.
+  pub detection: Option<InputDetection>,
+  /// Set when the panel frame was found in the screenshot.
+  pub panel: Option<PanelLocation>,
+  width: u32,
+  height: u32
+}
+
+impl ScreenshotLayout {
+  /// Lays out a screenshot with the given layout, or the one matching its
+  /// aspect ratio, and the given input method, or the one detected from its
+  /// button prompts with `templates`.
+  /// With `locate`, the panel is searched for and regions are placed
+  /// relative to it, falling back to the layout when it isn't found.
+  pub fn new(
+    image: &GrayImage,
+    layouts: &Layouts,
+    layout: Option<&str>,
+    input: Option<InputMethod>,
+    templates: &PromptTemplates,
+    locate: bool
+  ) -> Result<Self, String> {
+    let profile = match layout {
+      Some(layout) => layouts.get(layout)?,
+      None => layouts.select(image.width(), image.height())
+    };
+
+    let (input, detection) = match input {
+      Some(input) => (input, None),
+      None => {
+        let detection = detect_input_method(image, profile, templates)?;
+        (detection.input, Some(detection))
+      }
+    };
+
+    let profile = profile.for_input(input);
+    let panel = if locate { locate_panel(image, &profile) } else { None };
+
+    Ok(Self {
+      profile,
+      input,
+      detection,
+      panel,
+      width: image.width(),
+      height: image.height()
+    })
+  }
+
+  pub fn region(&self, region: Region) -> Option<Rect> {
+    match self.panel {
+      Some(panel) if region == Region::Panel => Some(panel.rect),
+      Some(panel) if region != Region::Prompts => {
+        self.profile.region_in_panel(region, panel.rect, self.width, self.height)
+      },
+      _ => self.profile.region(region, self.width, self.height)
+    }
+  }
+
+  pub fn regions(&self) -> Vec<(Region, Rect)> {
+    self
+      .profile
+      .regions
+      .keys()
+      .filter_map(|&region| self.region(region).map(|rect| (region, rect)))
+      .collect()
+  }
+
+  /// Crops `region` out of the screenshot the layout was made for.
+  pub fn crop(&self, image: &GrayImage, region: Region) -> Option<GrayImage> {
+    self.region(region).map(|rect| rect.crop(image))
+  }
+}
@@ -0,0 +1,173 @@
+use image::{imageops::FilterType, GrayImage};
+
+/// Where a template matched best and how well, as the normalized
+/// cross-correlation between -1 and 1.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TemplateMatch {
+  pub x: u32,
+  pub y: u32,
+  pub score: f32
+}
+
+/// Summed-area table of the pixel values and their squares, so the mean and
+/// variance of any window take constant time.
+struct IntegralImage {
+  width: usize,
+  sums: Vec<f64>,
+  squares: Vec<f64>
+}
+
+impl IntegralImage {
+  fn new(image: &GrayImage) -> Self {
+    let width = image.width() as usize + 1;
+    let height = image.height() as usize + 1;
+    let mut sums = vec![0.0; width * height];
+    let mut squares = vec![0.0; width * height];
+
+    for (x, y, pixel) in image.enumerate_pixels() {
+      let (x, y) = (x as usize + 1, y as usize + 1);
+      let value = pixel[0] as f64;
+      let index = y * width + x;
+      sums[index] =
+        value + sums[index - 1] + sums[index - width] - sums[index - width - 1];
+      squares[index] = value * value + squares[index - 1] + squares[index - width]
+        - squares[index - width - 1];
+    }
+
+    Self { width, sums, squares }
+  }
+
+  fn window(&self, table: &[f64], x: usize, y: usize, w: usize, h: usize) -> f64 {
+    let (a, b) = (y * self.width + x, y * self.width + x + w);
+    let (c, d) = ((y + h) * self.width + x, (y + h) * self.width + x + w);
+    table[d] - table[b] - table[c] + table[a]
+  }
+}
+
+/// Slides `template` over `image` and returns the position with the highest
+/// normalized cross-correlation, or `None` if the template doesn't fit.
+///
+/// Flat windows, which have no variance to correlate, score 0.
+pub fn match_template(image: &GrayImage, template: &GrayImage) -> Option<TemplateMatch> {
+  let (width, height) = (image.width() as usize, image.height() as usize);
+  let (w, h) = (template.width() as usize, template.height() as usize);
+  if w == 0 || h == 0 || w > width || h > height {
+    return None;
+  }
+
+  let count = (w * h) as f64;
+  let template_mean = template.pixels().map(|p| p[0] as f64).sum::<f64>() / count;
+  let centered: Vec<f64> =
+    template.pixels().map(|p| p[0] as f64 - template_mean).collect();
+  let template_norm = centered.iter().map(|v| v * v).sum::<f64>().sqrt();
+
+  let integral = IntegralImage::new(image);
+  let pixels = image.as_raw();
+  let mut best = TemplateMatch { x: 0, y: 0, score: f32::MIN };
+
+  for y in 0 ..= height - h {
+    for x in 0 ..= width - w {
+      let sum = integral.window(&integral.sums, x, y, w, h);
+      let squares = integral.window(&integral.squares, x, y, w, h);
+      let window_norm = (squares - sum * sum / count).max(0.0).sqrt();
+
+      let score = if template_norm < f64::EPSILON || window_norm < f64::EPSILON {
+        0.0
+      } else {
+        // The template is centered, so the window's mean cancels out
+        let mut correlation = 0.0;
+        for row in 0 .. h {
+          let offset = (y + row) * width + x;
+          for (column, value) in centered[row * w .. (row + 1) * w].iter().enumerate() {
+            correlation += pixels[offset + column] as f64 * value;
+          }
+        }
+        (correlation / (template_norm * window_norm)) as f32
+      };
+
+      if score > best.score {
+        best = TemplateMatch { x: x as u32, y: y as u32, score };
+      }
+    }
+  }
+
+  Some(best)
+}
+
+/// Resizes a template captured at one scale to another.
+pub fn scale_template(template: &GrayImage, scale: f64) -> GrayImage {
+  if (scale - 1.0).abs() < 0.01 {
+    return template.clone();
+  }
+
+  let width = ((template.width() as f64 * scale).round() as u32).max(1);
+  let height = ((template.height() as f64 * scale).round() as u32).max(1);
+  image::imageops::resize(template, width, height, FilterType::Triangle)
+}
+
+#[cfg(test)]
+mod tests {
+  use {super::*, image::Luma};
+
+  // A ring on a mid grey background, like a controller button glyph.
+  fn ring(size: u32) -> GrayImage {
+    let center = (size as f64 - 1.0) / 2.0;
+    GrayImage::from_fn(size, size, |x, y| {
+      let distance = ((x as f64 - center).powi(2) + (y as f64 - center).powi(2)).sqrt();
+      let inside = distance > size as f64 * 0.25 && distance < size as f64 * 0.45;
+      Luma([if inside { 230 } else { 90 }])
+    })
+  }
+
+  fn paste(image: &mut GrayImage, template: &GrayImage, x: u32, y: u32) {
+    image::imageops::replace(image, template, x as i64, y as i64);
+  }
+
+  #[test]
+  fn finds_the_template_where_it_was_drawn() {
+    let template = ring(20);
+    let mut image =
+      GrayImage::from_fn(120, 60, |x, y| Luma([((x * 3 + y * 5) % 40) as u8]));
+    paste(&mut image, &template, 73, 21);
+
+    let found = match_template(&image, &template).unwrap();
+    assert_eq!((found.x, found.y), (73, 21));
+    assert!(found.score > 0.999, "{}", found.score);
+  }
+
+  #[test]
+  fn scores_brightness_and_contrast_changes_alike() {
+    let template = ring(20);
+    let mut image = GrayImage::from_pixel(60, 40, Luma([10]));
+    let dimmed =
+      GrayImage::from_fn(20, 20, |x, y| Luma([template.get_pixel(x, y)[0] / 2 + 20]));
+    paste(&mut image, &dimmed, 30, 10);
+
+    let found = match_template(&image, &template).unwrap();
+    assert_eq!((found.x, found.y), (30, 10));
+    assert!(found.score > 0.99, "{}", found.score);
+  }
+
+  #[test]
+  fn scores_flat_images_0() {
+    let found =
+      match_template(&GrayImage::from_pixel(30, 30, Luma([128])), &ring(10)).unwrap();
+    assert_eq!(found.score, 0.0);
+    let found =
+      match_template(&ring(30), &GrayImage::from_pixel(10, 10, Luma([128]))).unwrap();
+    assert_eq!(found.score, 0.0);
+  }
+
+  #[test]
+  fn skips_templates_larger_than_the_image() {
+    assert_eq!(match_template(&ring(10), &ring(11)), None);
+    assert_eq!(match_template(&ring(10), &GrayImage::new(0, 0)), None);
+  }
+
+  #[test]
+  fn scales_templates() {
+    assert_eq!(scale_template(&ring(30), 1.005).dimensions(), (30, 30));
+    assert_eq!(scale_template(&ring(30), 2.0 / 3.0).dimensions(), (20, 20));
+    assert_eq!(scale_template(&ring(30), 2.0).dimensions(), (60, 60));
+  }
+}
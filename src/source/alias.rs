@@ -0,0 +1,107 @@
+use {
+  crate::source::vocabulary::Vocabulary,
+  std::{collections::HashMap, fmt::Display}
+};
+
+/// The kind of screen region a word was read from, so that a correction
+/// learned for one region does not leak into another.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum WordScope {
+  Set,
+  Affix,
+  Fixed,
+  Agent
+}
+
+impl TryFrom<&str> for WordScope {
+  type Error = String;
+
+  fn try_from(value: &str) -> Result<Self, Self::Error> {
+    match value {
+      "set" => Ok(Self::Set),
+      "affix" => Ok(Self::Affix),
+      "fixed" => Ok(Self::Fixed),
+      "agent" => Ok(Self::Agent),
+      _ => Err(format!("Invalid word scope: {}", value))
+    }
+  }
+}
+
+impl Display for WordScope {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let str = match self {
+      Self::Set => "set",
+      Self::Affix => "affix",
+      Self::Fixed => "fixed",
+      Self::Agent => "agent"
+    };
+    write!(f, "{}", str)
+  }
+}
+
+/// A learned correction from raw OCR text to a vocabulary word of the client
+/// of `locale`.
+#[derive(Clone, Debug)]
+pub struct Alias {
+  pub locale: String,
+  pub scope: WordScope,
+  pub raw: String,
+  pub canonical: String,
+  /// Unix timestamp of when the correction was last recorded.
+  pub updated_at: i64
+}
+
+impl Alias {
+  /// An alias is stale when its target left the vocabulary or its raw text
+  /// became a vocabulary word itself.
+  pub fn is_stale(&self, vocabulary: &Vocabulary) -> bool {
+    !vocabulary.contains(&self.canonical) || vocabulary.contains(&self.raw)
+  }
+}
+
+/// The aliases learned for the client of one locale.
+#[derive(Clone, Debug, Default)]
+pub struct AliasTable {
+  aliases: HashMap<(WordScope, String), Alias>
+}
+
+impl AliasTable {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn len(&self) -> usize {
+    self.aliases.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.aliases.is_empty()
+  }
+
+  pub fn insert(&mut self, alias: Alias) {
+    self.aliases.insert((alias.scope, alias.raw.clone()), alias);
+  }
+
+  pub fn get(&self, scope: WordScope, raw: &str) -> Option<&Alias> {
+    self.aliases.get(&(scope, raw.to_string()))
+  }
+
+  /// Returns the aliases sorted by scope, then by raw text.
+  pub fn aliases(&self) -> Vec<&Alias> {
+    let mut aliases: Vec<&Alias> = self.aliases.values().collect();
+    aliases.sort_by(|a, b| {
+      a.scope.to_string().cmp(&b.scope.to_string()).then(a.raw.cmp(&b.raw))
+    });
+    aliases
+  }
+}
+
+impl FromIterator<Alias> for AliasTable {
+  fn from_iter<T: IntoIterator<Item = Alias>>(iter: T) -> Self {
+    let mut table = Self::new();
+    for alias in iter {
+      table.insert(alias);
+    }
+    table
+  }
+}
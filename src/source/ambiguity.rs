@@ -0,0 +1,246 @@
+use {
+  crate::source::{vocabulary::Vocabulary, word_groups},
+  std::{collections::HashMap, fmt::Display}
+};
+
+/// A single edit turning the first word of a pair into the second.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CharDifference {
+  Substituted(char, char),
+  Inserted(char),
+  Deleted(char)
+}
+
+impl Display for CharDifference {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Substituted(a, b) => write!(f, "{}→{}", a, b),
+      Self::Inserted(c) => write!(f, "+{}", c),
+      Self::Deleted(c) => write!(f, "-{}", c)
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct AmbiguousPair {
+  pub first: String,
+  pub second: String,
+  pub distance: usize,
+  pub differences: Vec<CharDifference>
+}
+
+/// Every pair of vocabulary words within `max_distance` edits of each other,
+/// and the maximal groups of words that are all pairwise that close.
+#[derive(Clone, Debug)]
+pub struct AmbiguityReport {
+  pub max_distance: usize,
+  pub pairs: Vec<AmbiguousPair>,
+  pub groups: Vec<Vec<String>>
+}
+
+impl Display for AmbiguityReport {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    writeln!(
+      f,
+      "Words within edit distance {}: {} pairs, {} groups",
+      self.max_distance,
+      self.pairs.len(),
+      self.groups.len()
+    )?;
+
+    if !self.pairs.is_empty() {
+      writeln!(f, "\nPairs:")?;
+      for pair in self.pairs.iter() {
+        let differences: Vec<String> =
+          pair.differences.iter().map(|difference| difference.to_string()).collect();
+        writeln!(
+          f,
+          "  {} ↔ {} ({}): {}",
+          pair.first,
+          pair.second,
+          pair.distance,
+          differences.join(", ")
+        )?;
+      }
+    }
+
+    if !self.groups.is_empty() {
+      writeln!(f, "\nGroups:")?;
+      for group in self.groups.iter() {
+        writeln!(f, "  {}", group.join(", "))?;
+      }
+    }
+
+    Ok(())
+  }
+}
+
+/// Aligns the two words and lists the edits of a minimal edit script from
+/// `word_a` to `word_b`.
+pub fn get_char_differences(word_a: &str, word_b: &str) -> Vec<CharDifference> {
+  let a: Vec<char> = word_a.chars().collect();
+  let b: Vec<char> = word_b.chars().collect();
+
+  let mut table = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+  for (i, row) in table.iter_mut().enumerate() {
+    row[0] = i;
+  }
+  for (j, cell) in table[0].iter_mut().enumerate() {
+    *cell = j;
+  }
+  for i in 1 ..= a.len() {
+    for j in 1 ..= b.len() {
+      let substitution = table[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+      table[i][j] = substitution.min(table[i - 1][j] + 1).min(table[i][j - 1] + 1);
+    }
+  }
+
+  let mut result = vec![];
+  let (mut i, mut j) = (a.len(), b.len());
+  while i > 0 || j > 0 {
+    if i > 0
+      && j > 0
+      && table[i][j] == table[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1])
+    {
+      if a[i - 1] != b[j - 1] {
+        result.push(CharDifference::Substituted(a[i - 1], b[j - 1]));
+      }
+      i -= 1;
+      j -= 1;
+    } else if i > 0 && table[i][j] == table[i - 1][j] + 1 {
+      result.push(CharDifference::Deleted(a[i - 1]));
+      i -= 1;
+    } else {
+      result.push(CharDifference::Inserted(b[j - 1]));
+      j -= 1;
+    }
+  }
+
+  result.reverse();
+  result
+}
+
+pub fn get_ambiguity_report(
+  vocabulary: &Vocabulary,
+  max_distance: usize
+) -> AmbiguityReport {
+  let words: Vec<&str> = vocabulary.words().collect();
+  let indices: HashMap<&str, usize> =
+    words.iter().enumerate().map(|(index, &word)| (word, index)).collect();
+
+  let mut pairs = vec![];
+  let mut edges = vec![];
+
+  if max_distance > 0 {
+    for (i, &word) in words.iter().enumerate() {
+      for (other, distance) in vocabulary.find_within(word, max_distance) {
+        let j = indices[other];
+        if j <= i {
+          continue;
+        }
+
+        edges.push((i, j));
+        pairs.push(AmbiguousPair {
+          first: word.to_string(),
+          second: other.to_string(),
+          distance,
+          differences: get_char_differences(word, other)
+        });
+      }
+    }
+  }
+
+  pairs.sort_by(|a, b| {
+    a.distance.cmp(&b.distance).then(indices[&*a.first].cmp(&indices[&*b.first]))
+  });
+
+  let mut cliques: Vec<Vec<usize>> =
+    word_groups::get_maximal_cliques(words.len(), &edges)
+      .into_iter()
+      .filter(|clique| clique.len() > 1)
+      .map(|clique| {
+        let mut clique: Vec<usize> = clique.into_iter().collect();
+        clique.sort();
+        clique
+      })
+      .collect();
+  cliques.sort();
+
+  let groups = cliques
+    .into_iter()
+    .map(|clique| clique.into_iter().map(|i| words[i].to_string()).collect())
+    .collect();
+
+  AmbiguityReport { max_distance, pairs, groups }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{CharDifference::*, *};
+
+  #[test]
+  fn lists_substitutions() {
+    assert_eq!(get_char_differences("攻击力", "攻击力"), vec![]);
+    assert_eq!(get_char_differences("攻击力", "攻出力"), vec![Substituted('击', '出')]);
+    assert_eq!(
+      get_char_differences("abcd", "xbcy"),
+      vec![Substituted('a', 'x'), Substituted('d', 'y')]
+    );
+  }
+
+  #[test]
+  fn lists_insertions() {
+    assert_eq!(get_char_differences("暴击", "暴击率"), vec![Inserted('率')]);
+    assert_eq!(get_char_differences("bc", "abcd"), vec![Inserted('a'), Inserted('d')]);
+    assert_eq!(get_char_differences("", "ab"), vec![Inserted('a'), Inserted('b')]);
+  }
+
+  #[test]
+  fn lists_deletions() {
+    assert_eq!(get_char_differences("暴击率", "暴击"), vec![Deleted('率')]);
+    assert_eq!(get_char_differences("abcd", "bc"), vec![Deleted('a'), Deleted('d')]);
+    assert_eq!(get_char_differences("ab", ""), vec![Deleted('a'), Deleted('b')]);
+  }
+
+  #[test]
+  fn lists_mixed_edits_in_order() {
+    assert_eq!(
+      get_char_differences("abcdef", "xbdefg"),
+      vec![Substituted('a', 'x'), Deleted('c'), Inserted('g')]
+    );
+  }
+
+  #[test]
+  fn groups_mutually_close_words() {
+    let vocabulary = Vocabulary::new(["abc", "abd", "abe", "xyz", "xyw", "mnop"]);
+    let report = get_ambiguity_report(&vocabulary, 1);
+
+    assert_eq!(report.max_distance, 1);
+    let mut pairs: Vec<(&str, &str, usize)> = report
+      .pairs
+      .iter()
+      .map(|pair| (pair.first.as_str(), pair.second.as_str(), pair.distance))
+      .collect();
+    pairs.sort();
+    assert_eq!(
+      pairs,
+      vec![("abc", "abd", 1), ("abc", "abe", 1), ("abd", "abe", 1), ("xyz", "xyw", 1)]
+    );
+
+    let mut groups = report.groups.clone();
+    for group in groups.iter_mut() {
+      group.sort();
+    }
+    groups.sort();
+    assert_eq!(groups, vec![vec!["abc", "abd", "abe"], vec!["xyw", "xyz"]]);
+  }
+
+  #[test]
+  fn reports_nothing_at_distance_0() {
+    let vocabulary = Vocabulary::new(["abc", "abd", "abe"]);
+    let report = get_ambiguity_report(&vocabulary, 0);
+
+    assert!(report.pairs.is_empty());
+    assert!(report.groups.is_empty());
+  }
+}
@@ -0,0 +1,280 @@
+use {
+  levenshtein::levenshtein,
+  std::{cmp::Ordering, collections::BinaryHeap}
+};
+
+#[derive(Clone, Debug)]
+struct BkNode {
+  word: String,
+  children: Vec<(usize, usize)>
+}
+
+/// A Burkhard-Keller tree over Levenshtein distance. Lookups only visit the
+/// subtrees that can contain a match thanks to the triangle inequality, instead
+/// of comparing against every stored word.
+#[derive(Clone, Debug, Default)]
+pub struct BkTree {
+  nodes: Vec<BkNode>
+}
+
+#[derive(Eq, PartialEq)]
+struct Candidate {
+  distance: usize,
+  node: usize
+}
+
+impl Ord for Candidate {
+  fn cmp(&self, other: &Self) -> Ordering {
+    self.distance.cmp(&other.distance).then(self.node.cmp(&other.node))
+  }
+}
+
+impl PartialOrd for Candidate {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl BkTree {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn len(&self) -> usize {
+    self.nodes.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.nodes.is_empty()
+  }
+
+  /// Inserts `word`, returning `false` if it was already present.
+  pub fn insert(&mut self, word: &str) -> bool {
+    let new_node = self.nodes.len();
+    if new_node == 0 {
+      self.nodes.push(BkNode { word: word.to_string(), children: vec![] });
+      return true;
+    }
+
+    let mut current = 0;
+    loop {
+      let distance = levenshtein(word, &self.nodes[current].word);
+      if distance == 0 {
+        return false;
+      }
+
+      match self.nodes[current].children.iter().find(|(d, _)| *d == distance) {
+        Some(&(_, child)) => current = child,
+        None => {
+          self.nodes[current].children.push((distance, new_node));
+          break;
+        }
+      }
+    }
+
+    self.nodes.push(BkNode { word: word.to_string(), children: vec![] });
+    true
+  }
+
+  /// Returns every word within `max_distance` of `word`, ordered by distance
+  /// and then by insertion order.
+  pub fn find_within(&self, word: &str, max_distance: usize) -> Vec<(&str, usize)> {
+    let mut result = vec![];
+    if self.nodes.is_empty() {
+      return vec![];
+    }
+
+    let mut stack = vec![0];
+    while let Some(current) = stack.pop() {
+      let node = &self.nodes[current];
+      let distance = levenshtein(word, &node.word);
+      if distance <= max_distance {
+        result.push(Candidate { distance, node: current });
+      }
+
+      for &(child_distance, child) in node.children.iter() {
+        if child_distance.abs_diff(distance) <= max_distance {
+          stack.push(child);
+        }
+      }
+    }
+
+    result.sort();
+    result.into_iter().map(|c| (self.nodes[c.node].word.as_str(), c.distance)).collect()
+  }
+
+  /// Returns the `count` words closest to `word`, ordered by distance and then
+  /// by insertion order.
+  pub fn find_best(&self, word: &str, count: usize) -> Vec<(&str, usize)> {
+    let mut best = BinaryHeap::<Candidate>::new();
+    if self.nodes.is_empty() || count == 0 {
+      return vec![];
+    }
+
+    let mut stack = vec![0];
+    while let Some(current) = stack.pop() {
+      let node = &self.nodes[current];
+      let distance = levenshtein(word, &node.word);
+      let candidate = Candidate { distance, node: current };
+
+      if best.len() < count {
+        best.push(candidate);
+      } else if best.peek().is_some_and(|worst| candidate < *worst) {
+        best.pop();
+        best.push(candidate);
+      }
+
+      let radius = match best.peek() {
+        Some(worst) if best.len() == count => worst.distance,
+        _ => usize::MAX
+      };
+
+      for &(child_distance, child) in node.children.iter() {
+        if child_distance.abs_diff(distance) <= radius {
+          stack.push(child);
+        }
+      }
+    }
+
+    best
+      .into_sorted_vec()
+      .into_iter()
+      .map(|c| (self.nodes[c.node].word.as_str(), c.distance))
+      .collect()
+  }
+}
+
+impl<'a> FromIterator<&'a str> for BkTree {
+  fn from_iter<T: IntoIterator<Item = &'a str>>(iter: T) -> Self {
+    let mut tree = Self::new();
+    for word in iter {
+      tree.insert(word);
+    }
+    tree
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {super::*, crate::source::vocabulary::Vocabulary};
+
+  fn bundled_words() -> Vec<String> {
+    let mut words: Vec<String> = vec![];
+    for word in Vocabulary::bundled().words() {
+      if !words.iter().any(|w| w == word) {
+        words.push(word.to_string());
+      }
+    }
+    words
+  }
+
+  // Every word within `max_distance`, ordered by distance and then by
+  // insertion order, as a linear scan finds them.
+  fn linear_within<'a>(
+    words: &'a [String],
+    word: &str,
+    max_distance: usize
+  ) -> Vec<(&'a str, usize)> {
+    let mut result: Vec<_> = words
+      .iter()
+      .map(|w| (w.as_str(), levenshtein(word, w)))
+      .filter(|&(_, distance)| distance <= max_distance)
+      .collect();
+    result.sort_by_key(|&(_, distance)| distance);
+    result
+  }
+
+  fn linear_best<'a>(
+    words: &'a [String],
+    word: &str,
+    count: usize
+  ) -> Vec<(&'a str, usize)> {
+    let mut result = linear_within(words, word, usize::MAX);
+    result.truncate(count);
+    result
+  }
+
+  // The words themselves, then misreadings of them with one character
+  // replaced, dropped or added.
+  fn queries(words: &[String]) -> Vec<String> {
+    let mut queries = vec![String::new(), "x".to_string()];
+    for word in words.iter().step_by(3) {
+      let chars: Vec<char> = word.chars().collect();
+      queries.push(word.clone());
+      queries.push(std::iter::once('x').chain(chars[1 ..].iter().copied()).collect());
+      queries.push(chars[.. chars.len() - 1].iter().collect());
+      queries.push(format!("{}x", word));
+    }
+    queries
+  }
+
+  #[test]
+  fn finds_within_like_a_linear_scan() {
+    let words = bundled_words();
+    let tree: BkTree = words.iter().map(String::as_str).collect();
+    assert_eq!(tree.len(), words.len());
+
+    for query in queries(&words) {
+      for max_distance in 0 ..= 3 {
+        assert_eq!(
+          tree.find_within(&query, max_distance),
+          linear_within(&words, &query, max_distance),
+          "{:?} within {}",
+          query,
+          max_distance
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn finds_best_like_a_linear_scan() {
+    let words = bundled_words();
+    let tree: BkTree = words.iter().map(String::as_str).collect();
+
+    for query in queries(&words) {
+      for count in [0, 1, 3, 10] {
+        assert_eq!(
+          tree.find_best(&query, count),
+          linear_best(&words, &query, count),
+          "best {} for {:?}",
+          count,
+          query
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn finds_only_the_word_itself_at_radius_0() {
+    let words = bundled_words();
+    let tree: BkTree = words.iter().map(String::as_str).collect();
+
+    for word in words.iter() {
+      assert_eq!(tree.find_within(word, 0), vec![(word.as_str(), 0)]);
+    }
+    assert!(tree.find_within("不是词", 0).is_empty());
+  }
+
+  #[test]
+  fn ignores_duplicates() {
+    let mut tree = BkTree::new();
+    assert!(tree.insert("攻击力"));
+    assert!(tree.insert("防御力"));
+    assert!(!tree.insert("攻击力"));
+    assert!(!tree.insert("防御力"));
+    assert_eq!(tree.len(), 2);
+
+    assert_eq!(tree.find_within("攻击力", 0), vec![("攻击力", 0)]);
+    assert_eq!(tree.find_best("攻击力", 3), vec![("攻击力", 0), ("防御力", 2)]);
+  }
+
+  #[test]
+  fn orders_ties_by_insertion() {
+    let tree: BkTree = ["abd", "abc", "xbc", "abe"].into_iter().collect();
+
+    assert_eq!(tree.find_within("abx", 1), vec![("abd", 1), ("abc", 1), ("abe", 1)]);
+    assert_eq!(tree.find_best("abx", 2), vec![("abd", 1), ("abc", 1)]);
+    assert_eq!(tree.find_best("xbx", 2), vec![("xbc", 1), ("abd", 2)]);
+  }
+}
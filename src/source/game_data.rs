@@ -0,0 +1,643 @@
+use {
+  crate::{
+    disk::disk::AffixValueType,
+    ocr::{
+      font::{Confusion, FontProfile},
+      numeric::VALUE_CHARS,
+      preprocess::Preprocessing
+    }
+  },
+  serde::Deserialize,
+  std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fs,
+    path::Path
+  }
+};
+
+static BUNDLED_GAME_DATA: &str = include_str!("../../data/game_data.toml");
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawGameData {
+  version: u32,
+  game_version: String,
+  default_locale: String,
+  #[serde(default)]
+  fixed_words: BTreeMap<String, Vec<String>>,
+  #[serde(default)]
+  languages: BTreeMap<String, RawLanguage>,
+  #[serde(default)]
+  sets: Vec<RawDiskSet>,
+  #[serde(default)]
+  stats: Vec<RawStat>,
+  #[serde(default)]
+  agents: Vec<RawAgent>,
+  #[serde(default)]
+  slots: Vec<RawSlotRule>,
+  default_font: Option<String>,
+  #[serde(default)]
+  fonts: BTreeMap<String, RawFont>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawLanguage {
+  tesseract: String,
+  #[serde(default)]
+  word_separator: String,
+  thousands_separator: Option<char>,
+  equipped_label: Option<String>,
+  #[serde(default)]
+  experimental: bool
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawDiskSet {
+  id: String,
+  names: BTreeMap<String, String>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawAgent {
+  id: String,
+  names: BTreeMap<String, String>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawStat {
+  id: String,
+  names: BTreeMap<String, String>,
+  value_types: Vec<String>,
+  #[serde(default)]
+  substat_roll: BTreeMap<String, f64>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawSlotRule {
+  slot: u8,
+  primary: Vec<RawPrimaryAffix>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawPrimaryAffix {
+  stat: String,
+  value_type: String
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawFont {
+  name: String,
+  #[serde(default)]
+  preprocessing: RawPreprocessing,
+  #[serde(default)]
+  variables: BTreeMap<String, String>,
+  #[serde(default)]
+  confusions: Vec<RawConfusion>
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawPreprocessing {
+  scale: Option<u32>,
+  threshold: Option<u8>,
+  invert: Option<bool>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfusion {
+  read: char,
+  actual: char,
+  penalty: Option<u32>
+}
+
+/// How the text shown by the client of one locale is recognized and parsed.
+#[derive(Clone, Debug)]
+pub struct Language {
+  pub locale: String,
+  /// Tesseract language the client's text is recognized with.
+  pub tesseract: String,
+  /// Put between the words OCR returns; empty for scripts written without
+  /// spaces.
+  pub word_separator: String,
+  /// Digit group separator in flat values, if the client uses one.
+  pub thousands_separator: Option<char>,
+  /// Label in front of the agent name on equipped disks, e.g. `装备中`.
+  pub equipped_label: Option<String>,
+  /// The locale's names haven't been checked against its client yet, so it
+  /// is left out of language detection and only scanned when asked for.
+  pub experimental: bool
+}
+
+impl Language {
+  /// Joins the words of raw OCR text the way the client writes them, so it
+  /// can be matched against the vocabulary.
+  pub fn normalize_text(&self, raw: &str) -> String {
+    raw.split_whitespace().collect::<Vec<_>>().join(&self.word_separator)
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct DiskSet {
+  pub id: String,
+  /// Display names keyed by locale.
+  pub names: BTreeMap<String, String>
+}
+
+#[derive(Clone, Debug)]
+pub struct Agent {
+  pub id: String,
+  /// Display names keyed by locale.
+  pub names: BTreeMap<String, String>
+}
+
+#[derive(Clone, Debug)]
+pub struct Stat {
+  pub id: String,
+  /// Display names keyed by locale.
+  pub names: BTreeMap<String, String>,
+  pub value_types: Vec<AffixValueType>,
+  /// Value added by one secondary affix roll, per value type. Empty for
+  /// stats that are never secondary affixes.
+  pub substat_rolls: Vec<(AffixValueType, f64)>
+}
+
+impl Stat {
+  pub fn substat_roll(&self, value_type: AffixValueType) -> Option<f64> {
+    self.substat_rolls.iter().find(|(t, _)| *t == value_type).map(|(_, roll)| *roll)
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct SlotRule {
+  pub slot: u8,
+  /// The stat IDs and value types a primary affix in this slot can have.
+  pub primary: Vec<(String, AffixValueType)>
+}
+
+/// Sets, stats, agents, slot rules and fixed UI words of the game, loaded from
+/// a versioned TOML or JSON bundle.
+#[derive(Clone, Debug)]
+pub struct GameData {
+  pub version: u32,
+  pub game_version: String,
+  /// Locale whose names are used when a name is missing in another locale.
+  pub default_locale: String,
+  /// Fixed UI words keyed by locale.
+  pub fixed_words: BTreeMap<String, Vec<String>>,
+  pub languages: BTreeMap<String, Language>,
+  pub sets: Vec<DiskSet>,
+  pub stats: Vec<Stat>,
+  pub agents: Vec<Agent>,
+  pub slots: Vec<SlotRule>,
+  /// Font profile used when none is selected and only one can be tried.
+  pub default_font: String,
+  /// Font profiles keyed by ID; the bundle may leave them out, in which case
+  /// only the default "Global Light" profile exists.
+  pub fonts: BTreeMap<String, FontProfile>
+}
+
+fn validate_id(
+  kind: &str,
+  id: &str,
+  ids: &mut HashSet<String>,
+  errors: &mut Vec<String>
+) {
+  if id.is_empty() {
+    errors.push(format!("{} with an empty id", kind));
+  } else if !id.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+  {
+    errors.push(format!("{} {}: ids may only contain a-z, 0-9 and _", kind, id));
+  } else if !ids.insert(id.to_string()) {
+    errors.push(format!("{} {}: defined more than once", kind, id));
+  }
+}
+
+fn validate_names(
+  kind: &str,
+  id: &str,
+  names: BTreeMap<String, String>,
+  default_locale: &str,
+  errors: &mut Vec<String>
+) -> BTreeMap<String, String> {
+  let names: BTreeMap<String, String> =
+    names.into_iter().map(|(locale, name)| (locale, name.trim().to_string())).collect();
+
+  for (locale, name) in names.iter() {
+    if name.is_empty() {
+      errors.push(format!("{} {}: empty {} name", kind, id, locale));
+    }
+  }
+  if !names.contains_key(default_locale) {
+    errors.push(format!(
+      "{} {}: no name for the default locale {}",
+      kind, id, default_locale
+    ));
+  }
+
+  names
+}
+
+impl GameData {
+  pub const SUPPORTED_VERSION: u32 = 2;
+
+  /// The game data compiled into the binary.
+  pub fn bundled() -> Self {
+    Self::from_toml(BUNDLED_GAME_DATA).expect("bundled game data is invalid")
+  }
+
+  /// Loads the game data from `path` if given, falling back to the bundled
+  /// data.
+  pub fn load(path: Option<&Path>) -> Result<Self, String> {
+    match path {
+      Some(path) => Self::from_file(path),
+      None => Ok(Self::bundled())
+    }
+  }
+
+  /// Loads a bundle from a `.json` or `.toml` file.
+  pub fn from_file(path: impl AsRef<Path>) -> Result<Self, String> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path).map_err(|error| {
+      format!("Failed to read game data {}: {}", path.display(), error)
+    })?;
+
+    let result = match path.extension().and_then(|extension| extension.to_str()) {
+      Some("json") => Self::from_json(&content),
+      _ => Self::from_toml(&content)
+    };
+    result.map_err(|error| format!("Invalid game data {}: {}", path.display(), error))
+  }
+
+  pub fn from_toml(content: &str) -> Result<Self, String> {
+    let raw: RawGameData = toml::from_str(content).map_err(|error| error.to_string())?;
+    Self::validate(raw)
+  }
+
+  pub fn from_json(content: &str) -> Result<Self, String> {
+    let raw: RawGameData =
+      serde_json::from_str(content).map_err(|error| error.to_string())?;
+    Self::validate(raw)
+  }
+
+  fn validate(raw: RawGameData) -> Result<Self, String> {
+    if raw.version != Self::SUPPORTED_VERSION {
+      return Err(format!(
+        "unsupported version {}, expected {}",
+        raw.version,
+        Self::SUPPORTED_VERSION
+      ));
+    }
+
+    let mut errors = vec![];
+    let default_locale = raw.default_locale.trim().to_string();
+
+    let mut set_ids = HashSet::new();
+    let mut sets = vec![];
+    for set in raw.sets {
+      let id = set.id.trim().to_string();
+      validate_id("set", &id, &mut set_ids, &mut errors);
+      let names = validate_names("set", &id, set.names, &default_locale, &mut errors);
+      sets.push(DiskSet { id, names });
+    }
+
+    let mut stat_ids = HashSet::new();
+    let mut stats: Vec<Stat> = vec![];
+    for stat in raw.stats {
+      let id = stat.id.trim().to_string();
+      validate_id("stat", &id, &mut stat_ids, &mut errors);
+      let names = validate_names("stat", &id, stat.names, &default_locale, &mut errors);
+
+      let mut value_types = vec![];
+      for value_type in stat.value_types.iter() {
+        match AffixValueType::try_from(value_type as &str) {
+          Ok(value_type) => value_types.push(value_type),
+          Err(error) => errors.push(format!("stat {}: {}", id, error))
+        }
+      }
+      if stat.value_types.is_empty() {
+        errors.push(format!("stat {}: no value types", id));
+      }
+
+      let mut substat_rolls = vec![];
+      for (value_type, roll) in stat.substat_roll.iter() {
+        match AffixValueType::try_from(value_type as &str) {
+          Ok(value_type) if value_types.contains(&value_type) => {
+            substat_rolls.push((value_type, *roll))
+          },
+          Ok(value_type) => {
+            errors.push(format!(
+              "stat {}: substat roll for {} value, which the stat cannot have",
+              id, value_type
+            ))
+          },
+          Err(error) => errors.push(format!("stat {}: {}", id, error))
+        }
+      }
+
+      stats.push(Stat { id, names, value_types, substat_rolls });
+    }
+
+    let mut agent_ids = HashSet::new();
+    let mut agents = vec![];
+    for agent in raw.agents {
+      let id = agent.id.trim().to_string();
+      validate_id("agent", &id, &mut agent_ids, &mut errors);
+      let names = validate_names("agent", &id, agent.names, &default_locale, &mut errors);
+      agents.push(Agent { id, names });
+    }
+
+    let mut slots: Vec<SlotRule> = vec![];
+    for slot in raw.slots {
+      if !(1 ..= 6).contains(&slot.slot) {
+        errors.push(format!("slot {}: slots must be between 1 and 6", slot.slot));
+      } else if slots.iter().any(|s| s.slot == slot.slot) {
+        errors.push(format!("slot {}: defined more than once", slot.slot));
+      }
+
+      let mut primary = vec![];
+      for affix in slot.primary {
+        let value_type = match AffixValueType::try_from(&affix.value_type as &str) {
+          Ok(value_type) => value_type,
+          Err(error) => {
+            errors.push(format!("slot {}: {}", slot.slot, error));
+            continue;
+          }
+        };
+
+        match stats.iter().find(|stat| stat.id == affix.stat) {
+          Some(stat) if stat.value_types.contains(&value_type) => {
+            primary.push((stat.id.clone(), value_type))
+          },
+          Some(_) => {
+            errors.push(format!(
+              "slot {}: stat {} cannot have a {} value",
+              slot.slot, affix.stat, value_type
+            ))
+          },
+          None => errors.push(format!("slot {}: unknown stat {}", slot.slot, affix.stat))
+        }
+      }
+      if primary.is_empty() {
+        errors.push(format!("slot {}: no primary affixes", slot.slot));
+      }
+
+      slots.push(SlotRule { slot: slot.slot, primary });
+    }
+    for slot in 1 ..= 6 {
+      if !slots.iter().any(|s| s.slot == slot) {
+        errors.push(format!("slot {}: missing", slot));
+      }
+    }
+
+    let mut fixed_words = BTreeMap::new();
+    for (locale, words) in raw.fixed_words {
+      let words: Vec<String> = words.iter().map(|word| word.trim().to_string()).collect();
+      if words.iter().any(|word| word.is_empty()) {
+        errors.push(format!("empty {} fixed word", locale));
+      }
+      fixed_words.insert(locale, words);
+    }
+
+    let mut languages = BTreeMap::new();
+    for (locale, language) in raw.languages {
+      if language.tesseract.trim().is_empty() {
+        errors.push(format!("language {}: no tesseract language", locale));
+      }
+      if language.thousands_separator.is_some_and(|c| c.is_ascii_digit() || c == '.') {
+        errors.push(format!("language {}: invalid thousands separator", locale));
+      }
+      languages.insert(
+        locale.clone(),
+        Language {
+          locale,
+          tesseract: language.tesseract.trim().to_string(),
+          word_separator: language.word_separator,
+          thousands_separator: language.thousands_separator,
+          equipped_label: language
+            .equipped_label
+            .map(|label| label.trim().to_string())
+            .filter(|label| !label.is_empty()),
+          experimental: language.experimental
+        }
+      );
+    }
+    if !languages.contains_key(&default_locale) {
+      errors.push(format!("no language for the default locale {}", default_locale));
+    }
+
+    let mut font_ids = HashSet::new();
+    let mut fonts = BTreeMap::new();
+    for (id, font) in raw.fonts {
+      validate_id("font", &id, &mut font_ids, &mut errors);
+
+      let defaults = Preprocessing::default();
+      let preprocessing = Preprocessing {
+        scale: font.preprocessing.scale.unwrap_or(defaults.scale),
+        threshold: font.preprocessing.threshold.or(defaults.threshold),
+        invert: font.preprocessing.invert.unwrap_or(defaults.invert)
+      };
+      if preprocessing.scale == 0 || preprocessing.scale > 8 {
+        errors.push(format!("font {}: scale must be between 1 and 8", id));
+      }
+
+      let mut confusions = vec![];
+      for confusion in font.confusions {
+        if !VALUE_CHARS.contains(confusion.actual) {
+          errors.push(format!(
+            "font {}: confusion {} → {} corrects to a character that is not part of a \
+             value",
+            id, confusion.read, confusion.actual
+          ));
+        } else if confusions.iter().any(|c: &Confusion| c.read == confusion.read) {
+          errors.push(format!(
+            "font {}: confusion for {} defined more than once",
+            id, confusion.read
+          ));
+        }
+        confusions.push(Confusion {
+          read: confusion.read,
+          actual: confusion.actual,
+          penalty: confusion.penalty.unwrap_or(1)
+        });
+      }
+
+      fonts.insert(
+        id.clone(),
+        FontProfile {
+          id,
+          name: font.name.trim().to_string(),
+          preprocessing,
+          variables: font.variables,
+          confusions
+        }
+      );
+    }
+    if fonts.is_empty() {
+      let font = FontProfile::default();
+      fonts.insert(font.id.clone(), font);
+    }
+    let default_font = raw.default_font.unwrap_or(FontProfile::default().id);
+    if !fonts.contains_key(&default_font) {
+      errors.push(format!("unknown default font {}", default_font));
+    }
+
+    if !errors.is_empty() {
+      let list: String = errors.iter().map(|error| format!("\n  {}", error)).collect();
+      return Err(format!("{} errors:{}", errors.len(), list));
+    }
+
+    Ok(Self {
+      version: raw.version,
+      game_version: raw.game_version,
+      default_locale,
+      fixed_words,
+      languages,
+      sets,
+      stats,
+      agents,
+      slots,
+      default_font,
+      fonts
+    })
+  }
+
+  /// Every locale that has at least one name, fixed word or language.
+  pub fn locales(&self) -> BTreeSet<&str> {
+    self
+      .sets
+      .iter()
+      .flat_map(|set| set.names.keys())
+      .chain(self.stats.iter().flat_map(|stat| stat.names.keys()))
+      .chain(self.agents.iter().flat_map(|agent| agent.names.keys()))
+      .chain(self.fixed_words.keys())
+      .chain(self.languages.keys())
+      .map(String::as_str)
+      .collect()
+  }
+
+  /// Returns the language of `locale`, which a client must have to be scanned.
+  pub fn language(&self, locale: &str) -> Result<&Language, String> {
+    self.languages.get(locale).ok_or(format!("No language defined for locale {}", locale))
+  }
+
+  /// Returns the font profile with the given ID.
+  pub fn font(&self, id: &str) -> Result<&FontProfile, String> {
+    self.fonts.get(id).ok_or(format!("Unknown font profile {}", id))
+  }
+
+  /// Returns the font profiles a scan should try: the selected one, or all
+  /// of them, default first, when none is selected.
+  pub fn scan_fonts(&self, selected: Option<&str>) -> Result<Vec<&FontProfile>, String> {
+    match selected {
+      Some(id) => Ok(vec![self.font(id)?]),
+      None => {
+        Ok(
+          self
+            .fonts
+            .values()
+            .filter(|font| font.id == self.default_font)
+            .chain(self.fonts.values().filter(|font| font.id != self.default_font))
+            .collect()
+        )
+      },
+    }
+  }
+
+  pub fn set(&self, id: &str) -> Option<&DiskSet> {
+    self.sets.iter().find(|set| set.id == id)
+  }
+
+  pub fn stat(&self, id: &str) -> Option<&Stat> {
+    self.stats.iter().find(|stat| stat.id == id)
+  }
+
+  pub fn agent(&self, id: &str) -> Option<&Agent> {
+    self.agents.iter().find(|agent| agent.id == id)
+  }
+
+  pub fn slot(&self, slot: u8) -> Option<&SlotRule> {
+    self.slots.iter().find(|rule| rule.slot == slot)
+  }
+
+  /// Finds the set displayed as `name` by the client of `locale`.
+  pub fn find_set(&self, locale: &str, name: &str) -> Option<&DiskSet> {
+    self.sets.iter().find(|set| set.names.get(locale).is_some_and(|n| n == name))
+  }
+
+  /// Finds the stat displayed as `name` by the client of `locale`.
+  pub fn find_stat(&self, locale: &str, name: &str) -> Option<&Stat> {
+    self.stats.iter().find(|stat| stat.names.get(locale).is_some_and(|n| n == name))
+  }
+
+  /// Finds the agent displayed as `name` by the client of `locale`.
+  pub fn find_agent(&self, locale: &str, name: &str) -> Option<&Agent> {
+    self.agents.iter().find(|agent| agent.names.get(locale).is_some_and(|n| n == name))
+  }
+
+  fn localize<'a>(
+    &'a self,
+    names: &'a BTreeMap<String, String>,
+    locale: &str
+  ) -> &'a str {
+    names
+      .get(locale)
+      .or_else(|| names.get(&self.default_locale))
+      .map_or("", String::as_str)
+  }
+
+  /// Returns the name of the set in `locale`, falling back to the default
+  /// locale and then to the ID.
+  pub fn set_name<'a>(&'a self, id: &'a str, locale: &str) -> &'a str {
+    match self.set(id) {
+      Some(set) => self.localize(&set.names, locale),
+      None => id
+    }
+  }
+
+  /// Returns the name of the stat in `locale`, falling back to the default
+  /// locale and then to the ID.
+  pub fn stat_name<'a>(&'a self, id: &'a str, locale: &str) -> &'a str {
+    match self.stat(id) {
+      Some(stat) => self.localize(&stat.names, locale),
+      None => id
+    }
+  }
+
+  /// Returns the name of the agent in `locale`, falling back to the default
+  /// locale and then to the ID.
+  pub fn agent_name<'a>(&'a self, id: &'a str, locale: &str) -> &'a str {
+    match self.agent(id) {
+      Some(agent) => self.localize(&agent.names, locale),
+      None => id
+    }
+  }
+
+  /// The names of the agents in the client of `locale`.
+  pub fn agent_names<'a>(&'a self, locale: &'a str) -> impl Iterator<Item = &'a str> {
+    self
+      .agents
+      .iter()
+      .filter_map(move |agent| agent.names.get(locale))
+      .map(String::as_str)
+  }
+
+  /// Every word the client of `locale` can show on a disk panel besides
+  /// agent names: set names, stat names and fixed UI words.
+  pub fn words<'a>(&'a self, locale: &'a str) -> impl Iterator<Item = &'a str> {
+    self
+      .sets
+      .iter()
+      .filter_map(move |set| set.names.get(locale))
+      .chain(self.stats.iter().filter_map(move |stat| stat.names.get(locale)))
+      .map(String::as_str)
+      .chain(self.fixed_words.get(locale).into_iter().flatten().map(String::as_str))
+  }
+}
@@ -1,37 +1,56 @@
-use {
-  crate::source::source::{get_close_words, get_possible_words},
-  levenshtein::levenshtein
+use crate::source::{
+  alias::{AliasTable, WordScope},
+  vocabulary::Vocabulary
 };
 
-pub async fn select_close_words<'a>(source: &str, words: &Vec<&'a str>) -> &'a str {
-  let close_words = get_close_words().await;
-
+pub fn select_close_words<'a>(
+  _vocabulary: &Vocabulary,
+  _source: &str,
+  words: &[&'a str]
+) -> &'a str {
   // TODO: Implement this; now returning the first word
 
   words[0]
 }
 
-pub async fn match_closest_word(word: &str) -> Option<&'static str> {
-  let possible_words = match get_possible_words().await.get(word.chars().count() - 1) {
-    Some(words) => words,
-    None => return None
-  };
-
-  let mut best_candidate: Option<&str> = None;
-  let mut best_distance = usize::MAX;
+/// Matches OCR text read from a region of the given scope to a vocabulary
+/// word. Learned aliases take precedence over fuzzy matching.
+pub fn match_closest_word<'a>(
+  vocabulary: &'a Vocabulary,
+  aliases: &AliasTable,
+  scope: WordScope,
+  word: &str
+) -> Option<&'a str> {
+  let length = word.chars().count();
+  if length == 0 {
+    return None;
+  }
 
-  for possible_word in possible_words {
-    let distance = levenshtein(word, possible_word);
-    if distance < best_distance {
-      best_candidate = Some(possible_word);
-      best_distance = distance;
-    }
+  if let Some(word) =
+    aliases.get(scope, word).and_then(|alias| vocabulary.get(&alias.canonical))
+  {
+    return Some(word);
   }
 
-  let close_words = get_close_words().await.get(word).unwrap();
-  if close_words.len() > 0 {
-    let close_words: Vec<&'static str> = close_words.iter().map(|&word| word).collect();
-    best_candidate = Some(select_close_words(word, &close_words).await);
+  let best_distance = vocabulary.find_best(word, 1).first()?.1;
+  let candidates = vocabulary.find_within(word, best_distance);
+
+  // OCR mostly misreads characters rather than dropping them, so prefer a
+  // candidate of the same length when several are equally close
+  let mut best_candidate = candidates
+    .iter()
+    .find(|(candidate, _)| candidate.chars().count() == length)
+    .or(candidates.first())
+    .map(|&(candidate, _)| candidate);
+
+  let close_words = match best_candidate.and_then(|word| vocabulary.close_words(word)) {
+    Some(close_words) => close_words,
+    None => return best_candidate
+  };
+  if !close_words.is_empty() {
+    let close_words: Vec<&str> =
+      best_candidate.into_iter().chain(close_words.iter().map(String::as_str)).collect();
+    best_candidate = Some(select_close_words(vocabulary, word, &close_words));
   }
 
   best_candidate
@@ -1,3 +1,7 @@
-mod match_closest;
-pub mod source;
+pub mod alias;
+pub mod ambiguity;
+pub mod bk_tree;
+pub mod game_data;
+pub mod match_closest;
+pub mod vocabulary;
 pub mod word_groups;
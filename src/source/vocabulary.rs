@@ -0,0 +1,141 @@
+use {
+  crate::source::{bk_tree::BkTree, game_data::GameData, word_groups},
+  std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path
+  }
+};
+
+/// The set of words OCR results are matched against, bucketed by length
+/// together with the groups of words that are easily confused with each
+/// other. All words are also indexed in a BK-tree for fuzzy lookups.
+#[derive(Clone, Debug, Default)]
+pub struct Vocabulary {
+  index: BkTree,
+  possible_words: Vec<Vec<String>>,
+  close_word_groups: Vec<Vec<String>>,
+  close_words: HashMap<String, HashSet<String>>
+}
+
+impl Vocabulary {
+  pub fn new<I, S>(words: I) -> Self
+  where
+    I: IntoIterator<Item = S>,
+    S: Into<String>
+  {
+    let mut possible_words: Vec<Vec<String>> = vec![];
+    let mut close_words: HashMap<String, HashSet<String>> = HashMap::new();
+    let mut index = BkTree::new();
+
+    for word in words {
+      let word: String = word.into();
+      let word = word.trim();
+      let length = word.chars().count();
+      if length == 0 || close_words.contains_key(word) {
+        continue;
+      }
+
+      if possible_words.len() < length {
+        possible_words.resize(length, vec![]);
+      }
+      possible_words[length - 1].push(word.to_string());
+      close_words.insert(word.to_string(), HashSet::new());
+      index.insert(word);
+    }
+
+    let mut close_word_groups: Vec<Vec<String>> = vec![];
+
+    for pool in possible_words.iter() {
+      let pool: Vec<&str> = pool.iter().map(String::as_str).collect();
+      for group in word_groups::get_close_word_groups(&pool) {
+        if group.len() < 2 {
+          continue;
+        }
+        close_word_groups.push(group.iter().map(|word| word.to_string()).collect());
+      }
+    }
+
+    for group in close_word_groups.iter() {
+      for word in group.iter() {
+        close_words
+          .get_mut(word)
+          .unwrap()
+          .extend(group.iter().filter(|&w| w != word).cloned());
+      }
+    }
+
+    Self { index, possible_words, close_word_groups, close_words }
+  }
+
+  /// Builds the vocabulary of the default locale from the game data compiled
+  /// into the binary.
+  pub fn bundled() -> Self {
+    let game_data = GameData::bundled();
+    Self::from_game_data(&game_data, &game_data.default_locale)
+  }
+
+  /// Builds the vocabulary from the set names, stat names and fixed UI words
+  /// the client of `locale` shows.
+  pub fn from_game_data(game_data: &GameData, locale: &str) -> Self {
+    Self::new(game_data.words(locale))
+  }
+
+  /// Builds the vocabulary of agent names the client of `locale` shows, for
+  /// reading the equipped line on its own.
+  pub fn agents_from_game_data(game_data: &GameData, locale: &str) -> Self {
+    Self::new(game_data.agent_names(locale))
+  }
+
+  /// Builds the vocabulary from a text file containing one word per line.
+  /// Blank lines and lines starting with `#` are ignored.
+  pub fn from_file(path: impl AsRef<Path>) -> Result<Self, String> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path).map_err(|error| {
+      format!("Failed to read vocabulary {}: {}", path.display(), error)
+    })?;
+
+    Ok(Self::new(content.lines().filter(|line| !line.trim_start().starts_with('#'))))
+  }
+
+  pub fn contains(&self, word: &str) -> bool {
+    self.close_words.contains_key(word)
+  }
+
+  /// Returns the vocabulary's own copy of `word`, if present.
+  pub fn get(&self, word: &str) -> Option<&str> {
+    self.close_words.get_key_value(word).map(|(word, _)| word.as_str())
+  }
+
+  pub fn words(&self) -> impl Iterator<Item = &str> {
+    self.possible_words.iter().flatten().map(String::as_str)
+  }
+
+  /// Returns the words with exactly `length` characters.
+  pub fn possible_words(&self, length: usize) -> &[String] {
+    match length.checked_sub(1).and_then(|index| self.possible_words.get(index)) {
+      Some(words) => words,
+      None => &[]
+    }
+  }
+
+  pub fn close_word_groups(&self) -> &[Vec<String>] {
+    &self.close_word_groups
+  }
+
+  /// Returns the words that differ from `word` by a single character, or
+  /// `None` if `word` is not part of the vocabulary.
+  pub fn close_words(&self, word: &str) -> Option<&HashSet<String>> {
+    self.close_words.get(word)
+  }
+
+  /// Returns every word within `max_distance` edits of `word`, closest first.
+  pub fn find_within(&self, word: &str, max_distance: usize) -> Vec<(&str, usize)> {
+    self.index.find_within(word, max_distance)
+  }
+
+  /// Returns the `count` words closest to `word`, closest first.
+  pub fn find_best(&self, word: &str, count: usize) -> Vec<(&str, usize)> {
+    self.index.find_best(word, count)
+  }
+}
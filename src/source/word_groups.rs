@@ -21,7 +21,7 @@ fn bron_kerbosch(
   r: &mut HashSet<usize>,
   p: &mut HashSet<usize>,
   x: &mut HashSet<usize>,
-  graph: &Vec<HashSet<usize>>,
+  graph: &[HashSet<usize>],
   result: &mut Vec<HashSet<usize>>
 ) {
   if p.is_empty() && x.is_empty() {
@@ -54,7 +54,7 @@ fn bron_kerbosch(
   }
 }
 
-fn get_maximal_cliques(n: usize, edges: &Vec<(usize, usize)>) -> Vec<HashSet<usize>> {
+pub fn get_maximal_cliques(n: usize, edges: &[(usize, usize)]) -> Vec<HashSet<usize>> {
   let mut graph = vec![HashSet::<usize>::new(); n];
   for (a, b) in edges.iter() {
     graph[*a].insert(*b);
@@ -71,7 +71,7 @@ fn get_maximal_cliques(n: usize, edges: &Vec<(usize, usize)>) -> Vec<HashSet<usi
   result
 }
 
-pub fn get_close_word_groups(words: &Vec<&'static str>) -> Vec<Vec<&'static str>> {
+pub fn get_close_word_groups<'a>(words: &[&'a str]) -> Vec<Vec<&'a str>> {
   let mut edges = vec![];
   for i in 0 .. words.len() {
     for j in i + 1 .. words.len() {